@@ -2,8 +2,9 @@ use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned};
 use std::str::FromStr;
 use syn::{
-    parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned, token::Comma, Data,
-    DeriveInput, Field, Fields, GenericParam, Generics, TypeParamBound,
+    parse_macro_input, parse_quote, spanned::Spanned, Attribute, Data, DataEnum, DeriveInput, Expr,
+    ExprLit, Field, Fields, GenericParam, Generics, Ident, Index, Lit, Member, Meta, NestedMeta,
+    Path, TypeParamBound,
 };
 
 /// The attribute macro to inject the code at the beginning of entry functions
@@ -19,7 +20,15 @@ use syn::{
 /// type ProcessMsg = ();
 /// type QueryMsg = ();
 ///
-/// enum Error {};
+/// enum Error {
+///     StorageError(kelk::storage::error::Error),
+/// }
+///
+/// impl From<kelk::storage::error::Error> for Error {
+///     fn from(error: kelk::storage::error::Error) -> Self {
+///         Error::StorageError(error)
+///     }
+/// }
 ///
 /// #[kelk_derive(instantiate)]
 /// pub fn instantiate(ctx: Context, msg: InstantiateMsg) -> Result<(), Error> {
@@ -39,6 +48,12 @@ use syn::{
 ///
 /// where `InstantiateMsg`, `ProcessMsg`, and `QueryMsg` are contract defined
 /// types that implement CBOR encoding.
+///
+/// The instantiate and process functions are run atomically: if they return an error,
+/// all the writes into the storage are discarded.
+/// The storage cache is flushed when the entry functions return. If flushing fails,
+/// the storage error is returned, so the error type of the entry functions should
+/// implement `From<kelk::storage::error::Error>`.
 #[proc_macro_attribute]
 pub fn kelk_derive(
     _attr: proc_macro::TokenStream,
@@ -48,7 +63,19 @@ pub fn kelk_derive(
     let function = parse_macro_input!(cloned as syn::ItemFn);
     let name = function.sig.ident.to_string();
 
-    let new_code = format!(
+    let new_code = entry_code(&name);
+    let entry = proc_macro::TokenStream::from_str(&new_code).unwrap();
+    item.extend(entry);
+    item
+}
+
+// Generates the exported Wasm function that runs the entry function `name`.
+fn entry_code(name: &str) -> String {
+    let run = match name {
+        "query" => format!("super::{}(ctx, msg)", name),
+        _ => format!("storage.atomic(move || super::{}(ctx, msg))", name),
+    };
+    format!(
         r##"
         #[cfg(target_arch = "wasm32")]
         mod __wasm_export_{name} {{
@@ -60,18 +87,256 @@ pub fn kelk_derive(
                     blockchain: kelk::blockchain::Blockchain::new(
                         kelk::alloc::boxed::Box::new(kelk::Kelk::new())),
                 }};
-                kelk::do_{name}(&super::{name}, ctx.as_ref(), msg_ptr)
+                let func = |ctx: kelk::context::Context<'_>, msg| {{
+                    let storage = ctx.storage;
+                    {run}.and_then(|res| storage.flush().map(|_| res).map_err(Into::into))
+                }};
+                kelk::do_{name}(&func, ctx.as_ref(), msg_ptr)
             }}
         }}
     "##,
         name = name,
-    );
-    let entry = proc_macro::TokenStream::from_str(&new_code).unwrap();
-    item.extend(entry);
-    item
+        run = run,
+    )
 }
 
-#[proc_macro_derive(Codec)]
+/// The attribute macro to declare the state of the contract.
+///
+/// Each field of the struct is kept as a named root, with the name of the field.
+/// Fields with the lifetime of the state, like `StorageVec<'a, T>`, are collections and other
+/// fields are scalars that implement the `Codec` trait, that are kept in a `StorageCell`.
+/// Other lifetimes and references are rejected, and the field names should not be longer
+/// than `kelk::storage::root::MAX_NAME_LEN`.
+///
+/// ```
+/// use kelk::storage::bst::StorageBST;
+///
+/// #[kelk::state]
+/// pub struct Token<'a> {
+///     balances: StorageBST<'a, u32, i64>,
+///     total_supply: i64,
+/// }
+/// ```
+///
+/// It generates `Token::init(storage)` for `instantiate`, that creates the roots, and
+/// `Token::load(storage)` for `process` and `query`, that loads the roots on the first access.
+/// For each collection field, `field()` and `field_mut()` accessors are generated,
+/// and for each scalar field, `field()`, `set_field(&value)` and `update_field(f)` accessors
+/// are generated.
+/// `Token::verify(storage)` checks the storage file and all the fields of the state.
+#[proc_macro_attribute]
+pub fn state(
+    _attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(item as syn::ItemStruct);
+    match state_struct(input) {
+        Ok(expanded) => proc_macro::TokenStream::from(expanded),
+        Err(err) => proc_macro::TokenStream::from(err.to_compile_error()),
+    }
+}
+
+fn state_struct(input: syn::ItemStruct) -> syn::Result<TokenStream> {
+    let fields = match &input.fields {
+        Fields::Named(fields) => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "state should be a struct with named fields",
+            ))
+        }
+    };
+    let lifetime = match input.generics.lifetimes().next() {
+        Some(param) => param.lifetime.clone(),
+        None => {
+            return Err(syn::Error::new(
+                input.generics.span(),
+                "state should have a lifetime for the storage, like `State<'a>`",
+            ))
+        }
+    };
+
+    let mut decls = vec![];
+    let mut inits = vec![];
+    let mut loads = vec![];
+    let mut verifies = vec![];
+    let mut accessors = vec![];
+    let mut checks = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let name = ident.to_string();
+        let message = format!(
+            "the name of field `{}` is longer than `kelk::storage::root::MAX_NAME_LEN`",
+            name
+        );
+        checks.push(quote_spanned! {ident.span()=>
+            const _: () = ::core::assert!(
+                #name.len() <= ::kelk::storage::root::MAX_NAME_LEN,
+                #message
+            );
+        });
+        let attrs = &field.attrs;
+        let vis = &field.vis;
+        let ty = &field.ty;
+        let root = if is_collection(ty, &lifetime)? {
+            let ident_mut = Ident::new(&format!("{}_mut", name), ident.span());
+            accessors.push(quote! {
+                #(#attrs)*
+                #vis fn #ident(&self) -> ::core::result::Result<&#ty, ::kelk::storage::error::Error> {
+                    self.#ident.get()
+                }
+
+                #(#attrs)*
+                #vis fn #ident_mut(&mut self) -> ::core::result::Result<&mut #ty, ::kelk::storage::error::Error> {
+                    self.#ident.get_mut()
+                }
+            });
+            quote!(#ty)
+        } else {
+            let ident_set = Ident::new(&format!("set_{}", name), ident.span());
+            let ident_update = Ident::new(&format!("update_{}", name), ident.span());
+            accessors.push(quote! {
+                #(#attrs)*
+                #vis fn #ident(&self) -> ::core::result::Result<#ty, ::kelk::storage::error::Error> {
+                    self.#ident.get()?.get()
+                }
+
+                #(#attrs)*
+                #vis fn #ident_set(&self, value: &#ty) -> ::core::result::Result<(), ::kelk::storage::error::Error> {
+                    self.#ident.get()?.set(value)
+                }
+
+                #(#attrs)*
+                #vis fn #ident_update<F: FnOnce(#ty) -> #ty>(&self, f: F) -> ::core::result::Result<#ty, ::kelk::storage::error::Error> {
+                    self.#ident.get()?.update(f)
+                }
+            });
+            quote!(::kelk::storage::cell::StorageCell<#lifetime, #ty>)
+        };
+        decls.push(quote! {
+            #ident: ::kelk::storage::state::Lazy<#lifetime, #root>
+        });
+        inits.push(quote! {
+            #ident: ::kelk::storage::state::Lazy::init(storage, #name)?
+        });
+        loads.push(quote! {
+            #ident: ::kelk::storage::state::Lazy::new(storage, #name)
+        });
+        verifies.push(quote! {
+            verifier.root::<#root>(#name)?;
+        });
+    }
+
+    let attrs = &input.attrs;
+    let vis = &input.vis;
+    let ident = &input.ident;
+    let generics = &input.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        #(#attrs)*
+        #vis struct #ident #generics #where_clause {
+            #(#decls,)*
+        }
+
+        #(#checks)*
+
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Creates the roots of the state. It should be called once, in `instantiate`.
+            #vis fn init(
+                storage: &#lifetime ::kelk::storage::Storage,
+            ) -> ::core::result::Result<Self, ::kelk::storage::error::Error> {
+                Ok(Self {
+                    #(#inits,)*
+                })
+            }
+
+            /// Loads the state. The roots are loaded on the first access.
+            #vis fn load(storage: &#lifetime ::kelk::storage::Storage) -> Self {
+                Self {
+                    #(#loads,)*
+                }
+            }
+
+            /// Checks the storage file and all the fields of the state,
+            /// and returns the problems that are found.
+            #vis fn verify(
+                storage: &#lifetime ::kelk::storage::Storage,
+            ) -> ::core::result::Result<
+                ::kelk::alloc::vec::Vec<::kelk::storage::verify::Problem>,
+                ::kelk::storage::error::Error,
+            > {
+                let mut verifier = storage.verifier()?;
+                #(#verifies)*
+                Ok(verifier.finish())
+            }
+
+            #(#accessors)*
+        }
+    })
+}
+
+// Returns true if the field type is a collection, that borrows the storage by the lifetime
+// of the state, like `StorageVec<'a, T>`. The other fields are `Codec` values without any
+// lifetime. The collections should implement `Root` and the values should implement `Codec`,
+// that are checked by the compiler. The other shapes, like references, are rejected.
+fn is_collection(ty: &syn::Type, lifetime: &syn::Lifetime) -> syn::Result<bool> {
+    let mut lifetimes = vec![];
+    collect_lifetimes(quote!(#ty), &mut lifetimes);
+    if lifetimes.is_empty() {
+        return Ok(false);
+    }
+    match ty {
+        syn::Type::Path(path)
+            if path.qself.is_none() && lifetimes.iter().all(|l| *l == lifetime.ident) =>
+        {
+            Ok(true)
+        }
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            format!(
+                "state fields should be collections like `StorageVec<{}, T>` or `Codec` values \
+                 without a lifetime",
+                lifetime
+            ),
+        )),
+    }
+}
+
+// Collects the lifetimes in the tokens, like `'a`.
+fn collect_lifetimes(tokens: TokenStream, lifetimes: &mut Vec<Ident>) {
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            proc_macro2::TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                if let Some(proc_macro2::TokenTree::Ident(ident)) = tokens.next() {
+                    lifetimes.push(ident);
+                }
+            }
+            proc_macro2::TokenTree::Group(group) => collect_lifetimes(group.stream(), lifetimes),
+            _ => {}
+        }
+    }
+}
+
+/// Derives the `Codec` trait for structs and enums.
+///
+/// The fields are encoded in order. Enums are encoded as a tag byte followed by the fields of
+/// the variant, padded to the size of the largest variant.
+///
+/// The following attributes are supported:
+///
+/// - `#[codec(version = N)]` on the type, sets the version of the layout.
+/// - `#[codec(migrate_from(v1 = "OldType"))]` on the type, decodes the records of version 1 as
+///   `OldType` and converts them by calling `Self::migrate_from_v1(old)`.
+/// - `#[codec(tag = N)]` on a variant, sets the tag of the variant.
+/// - `#[codec(skip)]` on a field, doesn't encode the field and fills it by `Default::default()`.
+/// - `#[codec(default = "path")]` on a field, doesn't encode the field and fills it by
+///   calling `path()`.
+/// - `#[codec(fixed_str = N)]` on a `String` field, encodes the string as UTF-8 in `N` bytes,
+///   padded by zeros.
+/// - `#[codec(le)]` on a primitive integer field, encodes the field in little-endian byte order.
+/// - `#[codec(pad = N)]` on a field, reserves `N` zero bytes after the field.
+#[proc_macro_derive(Codec, attributes(codec))]
 pub fn derive_codec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Parse the input tokens into a syntax tree.
     let input = parse_macro_input!(input as DeriveInput);
@@ -83,9 +348,19 @@ pub fn derive_codec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let generics = add_trait_bounds(input.generics, parse_quote!(Codec));
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    // Generate an expression to sum up the heap size of each field.
-    let packed_len_body = packed_len_body(&input.data);
-    let (to_bytes_body, from_bytes_body) = codec_body(&input.data);
+    let body = match input.data {
+        Data::Struct(ref data) => codec_struct(&data.fields),
+        Data::Enum(ref data) => codec_enum(data),
+        Data::Union(_) => Err(syn::Error::new_spanned(
+            &name,
+            "Codec cannot be derived for unions",
+        )),
+    };
+    let body = body.and_then(|body| Ok((body, codec_version(&input.attrs)?)));
+    let ((packed_len_body, to_bytes_body, from_bytes_body), version_body) = match body {
+        Ok(body) => body,
+        Err(err) => return proc_macro::TokenStream::from(err.to_compile_error()),
+    };
 
     let expanded = quote! {
         impl #impl_generics Codec for #name #ty_generics #where_clause {
@@ -100,9 +375,14 @@ pub fn derive_codec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             }
 
             #[inline]
-            fn from_bytes(bytes: &[u8]) -> Self {
-                Self { #from_bytes_body }
+            fn try_from_bytes(bytes: &[u8]) -> core::result::Result<Self, ::kelk::storage::codec::CodecError> {
+                if bytes.len() != <Self as Codec>::PACKED_LEN {
+                    return Err(::kelk::storage::codec::CodecError::InvalidLength);
+                }
+                #from_bytes_body
             }
+
+            #version_body
         }
     };
 
@@ -110,47 +390,492 @@ pub fn derive_codec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     proc_macro::TokenStream::from(expanded)
 }
 
-fn packed_len_body(data: &Data) -> TokenStream {
-    match *data {
-        Data::Struct(ref data) => {
-            match data.fields {
-                Fields::Named(ref fields) => {
-                    // Expands to an expression like
-                    //
-                    //     0 + <self.x as Codec>::PACKED_LEN + <self.y as Codec>::PACKED_LEN
-                    let recurse = fields.named.iter().map(|f| {
-                        let ty = &f.ty;
-                        quote_spanned! {f.span()=>
-                            <#ty as Codec>::PACKED_LEN
-                        }
-                    });
+#[proc_macro_derive(DynCodec)]
+pub fn derive_dyn_codec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    // Parse the input tokens into a syntax tree.
+    let input = parse_macro_input!(input as DeriveInput);
+
+    // Used in the quasi-quotation below as `#name`.
+    let name = input.ident;
 
-                    quote! {
-                        0  #(+ #recurse)*
+    // Add a bound `T: DynCodec` to every type parameter T.
+    let generics = add_trait_bounds(input.generics, parse_quote!(DynCodec));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        Data::Enum(_) | Data::Union(_) => {
+            let err = syn::Error::new_spanned(&name, "DynCodec cannot be derived for enums/unions");
+            return proc_macro::TokenStream::from(err.to_compile_error());
+        }
+    };
+    let members: Vec<Member> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match &f.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        })
+        .collect();
+    let spans = fields.iter().map(|f| f.span());
+
+    let packed_len_body = members.iter().zip(spans.clone()).map(|(member, span)| {
+        quote_spanned! {span=>
+            DynCodec::packed_len(&self.#member)
+        }
+    });
+    let encode_body = members.iter().zip(spans.clone()).map(|(member, span)| {
+        quote_spanned! {span=>
+            DynCodec::encode(&self.#member, bytes);
+        }
+    });
+    let decode_body = members.iter().zip(spans).map(|(member, span)| {
+        quote_spanned! {span=>
+            #member: DynCodec::decode(bytes)?,
+        }
+    });
+
+    let expanded = quote! {
+        impl #impl_generics DynCodec for #name #ty_generics #where_clause {
+            #[inline]
+            fn packed_len(&self) -> usize {
+                0 #(+ #packed_len_body)*
+            }
+
+            #[inline]
+            fn encode(&self, bytes: &mut alloc::vec::Vec<u8>) {
+                #(#encode_body)*
+            }
+
+            #[inline]
+            fn decode(bytes: &mut &[u8]) -> core::result::Result<Self, ::kelk::storage::codec::CodecError> {
+                Ok(Self { #(#decode_body)* })
+            }
+        }
+    };
+
+    // Hand the output tokens back to the compiler.
+    proc_macro::TokenStream::from(expanded)
+}
+
+// Returns the `VERSION`, `packed_len_of` and `try_from_version` items, if the type has
+// `#[codec(version = N)]` attribute.
+fn codec_version(attrs: &[Attribute]) -> syn::Result<TokenStream> {
+    let mut version = None;
+    let mut migrations = vec![];
+    for meta in codec_attrs(attrs)? {
+        match &meta {
+            Meta::NameValue(nv) if nv.path.is_ident("version") => {
+                version = Some(match &nv.lit {
+                    Lit::Int(lit) => lit.base10_parse::<u8>()?,
+                    lit => return Err(syn::Error::new(lit.span(), "expected an integer version")),
+                })
+            }
+            Meta::List(list) if list.path.is_ident("migrate_from") => {
+                for nested in &list.nested {
+                    migrations.push(migration(nested)?);
+                }
+            }
+            meta => return Err(syn::Error::new(meta.span(), "unknown codec attribute")),
+        }
+    }
+
+    let version = match version {
+        Some(version) => version,
+        None if migrations.is_empty() => return Ok(quote! {}),
+        None => {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "`migrate_from` needs `#[codec(version = N)]`",
+            ))
+        }
+    };
+    let mut len_arms = vec![];
+    let mut decode_arms = vec![];
+    for (old_version, ty) in migrations {
+        if old_version >= version {
+            return Err(syn::Error::new(
+                ty.span(),
+                "migrations should be from older versions",
+            ));
+        }
+        let migrate_fn = Ident::new(&format!("migrate_from_v{}", old_version), ty.span());
+        len_arms.push(quote! {
+            #old_version => Some(<#ty as Codec>::PACKED_LEN),
+        });
+        decode_arms.push(quote! {
+            #old_version => Ok(Self::#migrate_fn(<#ty as Codec>::try_from_bytes(bytes)?)),
+        });
+    }
+
+    Ok(quote! {
+        const VERSION: u8 = #version;
+
+        fn packed_len_of(version: u8) -> Option<usize> {
+            match version {
+                #version => Some(<Self as Codec>::PACKED_LEN),
+                #(#len_arms)*
+                _ => None,
+            }
+        }
+
+        fn try_from_version(version: u8, bytes: &[u8]) -> core::result::Result<Self, ::kelk::storage::codec::CodecError> {
+            match version {
+                #version => <Self as Codec>::try_from_bytes(bytes),
+                #(#decode_arms)*
+                version => Err(::kelk::storage::codec::CodecError::InvalidVersion(version)),
+            }
+        }
+    })
+}
+
+// Parses `vN = "Type"` inside `migrate_from(...)`.
+fn migration(nested: &NestedMeta) -> syn::Result<(u8, syn::Type)> {
+    let err = || syn::Error::new(nested.span(), "expected `vN = \"Type\"`");
+    let nv = match nested {
+        NestedMeta::Meta(Meta::NameValue(nv)) => nv,
+        _ => return Err(err()),
+    };
+    let version = nv
+        .path
+        .get_ident()
+        .and_then(|ident| ident.to_string().strip_prefix('v')?.parse::<u8>().ok())
+        .ok_or_else(err)?;
+    let ty = match &nv.lit {
+        Lit::Str(lit) => lit.parse()?,
+        _ => return Err(err()),
+    };
+    Ok((version, ty))
+}
+
+// Returns the bodies of `PACKED_LEN`, `to_bytes` and `from_bytes` for a struct.
+fn codec_struct(fields: &Fields) -> syn::Result<(TokenStream, TokenStream, TokenStream)> {
+    let values: Vec<TokenStream> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let member = match &f.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(i)),
+            };
+            quote! { &self.#member }
+        })
+        .collect();
+    let codec = codec_fields(fields, &values, quote! { 0 })?;
+    let constructor = construct(quote! { Self }, fields, &codec.decoded);
+
+    Ok((
+        codec.packed_len,
+        codec.to_bytes,
+        quote! { Ok(#constructor) },
+    ))
+}
+
+// Enums are encoded as a tag byte followed by the fields of the variant.
+// The encoded size is the size of the largest variant, and smaller variants are padded by zeros.
+// Returns the bodies of `PACKED_LEN`, `to_bytes` and `from_bytes`.
+fn codec_enum(data: &DataEnum) -> syn::Result<(TokenStream, TokenStream, TokenStream)> {
+    let mut variant_lens = vec![];
+    let mut to_bytes_arms = vec![];
+    let mut from_bytes_arms = vec![];
+    let mut tags: Vec<u8> = vec![];
+    let mut next_tag: u16 = 0;
+
+    for variant in data.variants.iter() {
+        let tag = match variant_tag(variant)? {
+            Some(tag) => tag,
+            None => u8::try_from(next_tag).map_err(|_| {
+                syn::Error::new(variant.span(), "the enum has more than 256 variants")
+            })?,
+        };
+        if tags.contains(&tag) {
+            return Err(syn::Error::new(
+                variant.span(),
+                format!("duplicated tag {} for variant", tag),
+            ));
+        }
+        tags.push(tag);
+        next_tag = tag as u16 + 1;
+
+        // The skipped fields are not bound, to avoid unused variables.
+        let mut bindings = vec![];
+        for (i, field) in variant.fields.iter().enumerate() {
+            bindings.push(match field_attrs(field)?.skip {
+                true => quote! { _ },
+                false => {
+                    let ident = Ident::new(&format!("__field_{}", i), variant.span());
+                    quote! { #ident }
+                }
+            });
+        }
+        let codec = codec_fields(&variant.fields, &bindings, quote! { 1 })?;
+        let FieldsCodec {
+            packed_len,
+            to_bytes,
+            decoded,
+        } = codec;
+
+        let ident = &variant.ident;
+        let pattern = construct(quote! { Self::#ident }, &variant.fields, &bindings);
+        let constructor = construct(quote! { Self::#ident }, &variant.fields, &decoded);
+
+        variant_lens.push(packed_len);
+        to_bytes_arms.push(quote_spanned! {variant.span()=>
+            #pattern => {
+                bytes.push(#tag);
+                #to_bytes
+            }
+        });
+        from_bytes_arms.push(quote_spanned! {variant.span()=>
+            #tag => Ok(#constructor),
+        });
+    }
+
+    Ok((
+        quote! {
+            {
+                let mut max = 0;
+                #(
+                    let len = #variant_lens;
+                    if len > max {
+                        max = len;
                     }
+                )*
+                1 + max
+            }
+        },
+        quote! {
+            match self {
+                #(#to_bytes_arms)*
+            }
+            bytes.resize(<Self as Codec>::PACKED_LEN, 0);
+        },
+        quote! {
+            match bytes[0] {
+                #(#from_bytes_arms)*
+                tag => Err(::kelk::storage::codec::CodecError::InvalidTag(tag)),
+            }
+        },
+    ))
+}
+
+// The generated code for a list of fields.
+struct FieldsCodec {
+    // The expression of the packed length of all fields.
+    packed_len: TokenStream,
+    // The statements that append the fields to `bytes`.
+    to_bytes: TokenStream,
+    // The expressions that decode each field from `bytes`.
+    decoded: Vec<TokenStream>,
+}
+
+// Generates the code for encoding and decoding the fields.
+// `values` are the expressions that reference the fields, and `beg_offset` is the offset of
+// the first field in the packed bytes.
+fn codec_fields(
+    fields: &Fields,
+    values: &[TokenStream],
+    mut beg_offset: TokenStream,
+) -> syn::Result<FieldsCodec> {
+    let mut field_lens = vec![];
+    let mut recurse_to_bytes = vec![];
+    let mut decoded = vec![];
+
+    for (field, value) in fields.iter().zip(values) {
+        let attrs = field_attrs(field)?;
+        let ty = &field.ty;
+
+        let field_len = if attrs.skip {
+            quote! { 0 }
+        } else if let Some(len) = attrs.fixed_str {
+            quote! { #len }
+        } else {
+            quote! { <#ty as Codec>::PACKED_LEN }
+        };
+        let end_offset = quote! { #beg_offset + #field_len };
+        let bytes_slice = quote! { bytes[#beg_offset..#end_offset] };
+
+        if attrs.skip {
+            decoded.push(match attrs.default {
+                Some(path) => quote_spanned! {field.span()=> #path() },
+                None => quote_spanned! {field.span()=> core::default::Default::default() },
+            });
+        } else if let Some(len) = attrs.fixed_str {
+            recurse_to_bytes.push(quote_spanned! {field.span()=>
+                bytes.extend_from_slice(&::kelk::storage::codec::encode_fixed_str(#value, #len));
+            });
+            decoded.push(quote_spanned! {field.span()=>
+                ::kelk::storage::codec::decode_fixed_str(& #bytes_slice)?
+            });
+        } else if attrs.le {
+            recurse_to_bytes.push(quote_spanned! {field.span()=>
+                let mut le_bytes = Codec::to_bytes(#value);
+                le_bytes.reverse();
+                bytes.extend_from_slice(&le_bytes);
+            });
+            decoded.push(quote_spanned! {field.span()=>
+                {
+                    let mut le_bytes = #bytes_slice.to_vec();
+                    le_bytes.reverse();
+                    <#ty as Codec>::try_from_bytes(&le_bytes)?
+                }
+            });
+        } else {
+            recurse_to_bytes.push(quote_spanned! {field.span()=>
+                bytes.extend_from_slice(&Codec::to_bytes(#value));
+            });
+            decoded.push(quote_spanned! {field.span()=>
+                <#ty as Codec>::try_from_bytes(& #bytes_slice)?
+            });
+        }
+
+        let pad = attrs.pad;
+        if pad > 0 {
+            recurse_to_bytes.push(quote! {
+                bytes.resize(bytes.len() + #pad, 0);
+            });
+        }
+
+        field_lens.push(quote! { #field_len + #pad });
+        beg_offset = quote! { #end_offset + #pad };
+    }
+
+    Ok(FieldsCodec {
+        packed_len: quote! { 0 #(+ #field_lens)* },
+        to_bytes: quote! { #(#recurse_to_bytes)* },
+        decoded,
+    })
+}
+
+// Builds a struct or variant expression (or pattern) from the given values of the fields.
+fn construct(path: TokenStream, fields: &Fields, values: &[TokenStream]) -> TokenStream {
+    match fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|f| &f.ident);
+            quote! { #path { #(#names: #values),* } }
+        }
+        Fields::Unnamed(_) => quote! { #path ( #(#values),* ) },
+        Fields::Unit => quote! { #path },
+    }
+}
+
+// The attributes of a field, defined by `#[codec(...)]`.
+#[derive(Default)]
+struct FieldAttrs {
+    skip: bool,
+    default: Option<Path>,
+    fixed_str: Option<usize>,
+    le: bool,
+    pad: usize,
+}
+
+fn field_attrs(field: &Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for meta in codec_attrs(&field.attrs)? {
+        match &meta {
+            Meta::Path(path) if path.is_ident("skip") => attrs.skip = true,
+            Meta::Path(path) if path.is_ident("le") => attrs.le = true,
+            Meta::NameValue(nv) if nv.path.is_ident("default") => match &nv.lit {
+                Lit::Str(lit) => {
+                    attrs.skip = true;
+                    attrs.default = Some(lit.parse()?);
                 }
-                Fields::Unnamed(ref fields) => {
-                    // Expands to an expression like
-                    //
-                    //     0 + <self.0 as Codec>::PACKED_LEN + <self.1 as Codec>::PACKED_LEN
-                    let recurse = fields.unnamed.iter().map(|f| {
-                        let ty = &f.ty;
-                        quote_spanned! {f.span()=>
-                            <#ty as Codec>::PACKED_LEN
+                lit => return Err(syn::Error::new(lit.span(), "expected a function path")),
+            },
+            Meta::NameValue(nv) if nv.path.is_ident("fixed_str") => {
+                attrs.fixed_str = Some(int_lit(&nv.lit)?)
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("pad") => attrs.pad = int_lit(&nv.lit)?,
+            meta => return Err(syn::Error::new(meta.span(), "unknown codec attribute")),
+        }
+    }
+
+    let encodings = attrs.skip as u8 + attrs.le as u8 + attrs.fixed_str.is_some() as u8;
+    if encodings > 1 {
+        return Err(syn::Error::new(
+            field.span(),
+            "`skip`, `fixed_str` and `le` can't be used together",
+        ));
+    }
+    // The little-endian bytes are the reversed big-endian bytes, only for the integers.
+    if attrs.le && !is_integer(&field.ty) {
+        return Err(syn::Error::new_spanned(
+            &field.ty,
+            "`le` can only be used on primitive integer types",
+        ));
+    }
+    Ok(attrs)
+}
+
+fn is_integer(ty: &syn::Type) -> bool {
+    const INTEGERS: [&str; 10] = [
+        "u8", "i8", "u16", "i16", "u32", "i32", "u64", "i64", "u128", "i128",
+    ];
+    match ty {
+        syn::Type::Path(ty) if ty.qself.is_none() => {
+            INTEGERS.iter().any(|integer| ty.path.is_ident(integer))
+        }
+        _ => false,
+    }
+}
+
+fn int_lit(lit: &Lit) -> syn::Result<usize> {
+    match lit {
+        Lit::Int(lit) => lit.base10_parse(),
+        lit => Err(syn::Error::new(lit.span(), "expected an integer")),
+    }
+}
+
+// Returns the tag of the variant, defined by `#[codec(tag = N)]` attribute or
+// by the explicit discriminant.
+fn variant_tag(variant: &syn::Variant) -> syn::Result<Option<u8>> {
+    let mut tag = None;
+    for meta in codec_attrs(&variant.attrs)? {
+        match meta {
+            Meta::NameValue(nv) if nv.path.is_ident("tag") => match &nv.lit {
+                Lit::Int(lit) => tag = Some(lit.base10_parse::<u8>()?),
+                lit => return Err(syn::Error::new(lit.span(), "expected an integer tag")),
+            },
+            meta => return Err(syn::Error::new(meta.span(), "unknown codec attribute")),
+        }
+    }
+    if tag.is_some() {
+        return Ok(tag);
+    }
+    match &variant.discriminant {
+        Some((
+            _,
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(lit), ..
+            }),
+        )) => lit.base10_parse::<u8>().map(Some),
+        Some((_, expr)) => Err(syn::Error::new(
+            expr.span(),
+            "expected an integer discriminant",
+        )),
+        None => Ok(None),
+    }
+}
+
+// Returns the items inside `#[codec(...)]` attributes.
+fn codec_attrs(attrs: &[Attribute]) -> syn::Result<Vec<Meta>> {
+    let mut metas = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("codec")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(meta) => metas.push(meta),
+                        NestedMeta::Lit(lit) => {
+                            return Err(syn::Error::new(lit.span(), "unknown codec attribute"))
                         }
-                    });
-                    quote! {
-                        0 #(+ #recurse)*
                     }
                 }
-                Fields::Unit => {
-                    // Unit structs cannot own more than 0 bytes of heap memory.
-                    quote!(0)
-                }
             }
+            meta => return Err(syn::Error::new(meta.span(), "expected `#[codec(...)]`")),
         }
-        Data::Enum(_) | Data::Union(_) => unimplemented!(),
     }
+    Ok(metas)
 }
 
 // Add a bound `T: trait_bound` to every type parameter T.
@@ -163,52 +888,75 @@ fn add_trait_bounds(mut generics: Generics, trait_bound: TypeParamBound) -> Gene
     generics
 }
 
-fn codec_body(data: &Data) -> (TokenStream, TokenStream) {
-    // this also contains `bytes` variable
-    match *data {
-        Data::Struct(ref data) => {
-            match data.fields {
-                Fields::Named(ref fields) => codec_fields(&fields.named),
-                Fields::Unnamed(ref fields) => codec_fields(&fields.unnamed),
-                Fields::Unit => {
-                    // Unit structs cannot own more than 0 bytes of heap memory.
-                    (quote!(0), quote!(0))
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_field(input: DeriveInput) -> Field {
+        match input.data {
+            Data::Struct(data) => data.fields.into_iter().next().unwrap(),
+            _ => unreachable!(),
         }
-        Data::Enum(_) | Data::Union(_) => unimplemented!(),
     }
-}
-
-fn codec_fields(fields: &Punctuated<Field, Comma>) -> (TokenStream, TokenStream) {
-    let mut beg_offset = quote! { 0 };
-    let mut recurse_to_bytes = vec![];
-    let mut recurse_from_bytes = vec![];
-
-    for field in fields.iter() {
-        let name = &field.ident;
-        let ty = &field.ty;
 
-        recurse_to_bytes.push(quote_spanned! {field.span()=>
-            bytes.extend_from_slice(&Codec::to_bytes(&self.#name));
+    #[test]
+    fn test_le_attribute() {
+        let field = first_field(parse_quote! {
+            struct S { #[codec(le)] a: u32 }
         });
+        assert!(field_attrs(&field).unwrap().le);
 
-        let struct_size = quote! { <#ty as Codec>::PACKED_LEN };
-        let end_offset = quote! { #beg_offset + #struct_size };
-        let bytes_slice = quote! { bytes[#beg_offset..#end_offset] };
-        recurse_from_bytes.push(quote_spanned! {field.span()=>
-            #name: Codec::from_bytes(& #bytes_slice),
-        });
+        for input in [
+            parse_quote! { struct S { #[codec(le)] a: [u8; 4] } },
+            parse_quote! { struct S { #[codec(le)] a: (u16, u16) } },
+            parse_quote! { struct S { #[codec(le)] a: Address } },
+            parse_quote! { struct S { #[codec(le)] a: bool } },
+        ] {
+            let err = field_attrs(&first_field(input)).err().unwrap();
+            assert_eq!(
+                err.to_string(),
+                "`le` can only be used on primitive integer types"
+            );
+        }
+    }
 
-        beg_offset = quote! { #beg_offset + #struct_size };
+    #[test]
+    fn test_entry_code() {
+        for name in ["instantiate", "process", "query"] {
+            let code = entry_code(name);
+            let file: syn::File = syn::parse_str(&code).unwrap();
+            let module = match &file.items[..] {
+                [syn::Item::Mod(module)] => module,
+                _ => panic!("expected a single module"),
+            };
+            assert_eq!(module.ident, format!("__wasm_export_{}", name));
+            assert!(!code.contains("expect("));
+            assert!(code.contains("storage.flush().map(|_| res).map_err(Into::into)"));
+            assert_eq!(code.contains("storage.atomic("), name != "query");
+        }
     }
 
-    (
-        quote! {
-            #(#recurse_to_bytes)*
-        },
-        quote! {
-            #(#recurse_from_bytes)*
-        },
-    )
+    #[test]
+    fn test_state_field_kind() {
+        let lifetime: syn::Lifetime = parse_quote!('a);
+        let kind = |ty: syn::Type| is_collection(&ty, &lifetime).map_err(|err| err.to_string());
+
+        assert_eq!(kind(parse_quote!(u64)), Ok(false));
+        assert_eq!(kind(parse_quote!([u8; 4])), Ok(false));
+        assert_eq!(kind(parse_quote!(StorageVec<'a, u32>)), Ok(true));
+        assert_eq!(kind(parse_quote!(Balances<'a>)), Ok(true));
+        assert_eq!(
+            kind(parse_quote!(kelk::storage::bst::StorageBST<'a, u32, i64>)),
+            Ok(true)
+        );
+        for ty in [
+            parse_quote!(&'a u64),
+            parse_quote!(StorageVec<'static, u32>),
+            parse_quote!(StorageVec<'b, u32>),
+            parse_quote!((StorageVec<'a, u32>, u8)),
+            parse_quote!(<T as Trait<'a>>::Root),
+        ] {
+            assert!(kind(ty).is_err());
+        }
+    }
 }
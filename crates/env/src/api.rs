@@ -16,6 +16,13 @@ pub trait StorageAPI {
     /// at the given `offset`
     fn write(&self, offset: u32, data: &[u8]) -> Result<(), HostError>;
 
+    /// This API requests the host to return the size of the storage file in bytes.
+    fn size(&self) -> Result<u32, HostError>;
+
+    /// This API requests the host to grow the storage file at least by `additional` bytes.
+    /// It returns the new size of the storage file.
+    fn grow(&self, additional: u32) -> Result<u32, HostError>;
+
     /// It is useful for downcasting the trait to the underling struct.
     /// For example we can downcast the trait to the mocked object.
     fn as_any(&mut self) -> &mut dyn Any;
@@ -34,6 +34,25 @@ extern "C" {
     /// If the operation is successful it returns 0, otherwise it reruns the error code.
     fn read_storage(offset: u32, ptr: u32, len: u32) -> i32;
 
+    /// gets the size of storage file.
+    ///
+    /// # Arguments
+    ///
+    /// `size_ptr` is the location in sandbox memory where the size should be written to.
+    ///
+    /// If the operation is successful it returns 0, otherwise it reruns the error code.
+    fn storage_size(size_ptr: *mut u32) -> i32;
+
+    /// grows the storage file.
+    ///
+    /// # Arguments
+    ///
+    /// `additional` is the minimum number of bytes that should be added to the storage file.
+    /// `size_ptr` is the location in sandbox memory where the new size should be written to.
+    ///
+    /// If the operation is successful it returns 0, otherwise it reruns the error code.
+    fn grow_storage(additional: u32, size_ptr: *mut u32) -> i32;
+
     /// gets parameter value from the host.
     ///
     /// # Arguments
@@ -87,6 +106,26 @@ impl StorageAPI for Kelk {
         Ok(vec.to_vec())
     }
 
+    fn size(&self) -> Result<u32, HostError> {
+        let mut size = 0;
+
+        let code = unsafe { storage_size(&mut size) };
+        if code != 0 {
+            return Err(HostError { code });
+        }
+        Ok(size)
+    }
+
+    fn grow(&self, additional: u32) -> Result<u32, HostError> {
+        let mut size = 0;
+
+        let code = unsafe { grow_storage(additional, &mut size) };
+        if code != 0 {
+            return Err(HostError { code });
+        }
+        Ok(size)
+    }
+
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
@@ -167,6 +206,18 @@ pub unsafe fn read_storage(_offset: u32, _ptr: u32, _len: u32) -> i32 {
     0
 }
 
+/// For testing
+#[cfg(test)]
+pub unsafe fn storage_size(_size_ptr: *mut u32) -> i32 {
+    0
+}
+
+/// For testing
+#[cfg(test)]
+pub unsafe fn grow_storage(_additional: u32, _size_ptr: *mut u32) -> i32 {
+    0
+}
+
 /// For testing
 #[cfg(test)]
 pub unsafe fn get_param(_param_id: u32, _ptr: *mut u32, _len: *mut u32) -> i32 {
@@ -1,5 +1,7 @@
 //! Address type for representing actor address
 use super::error::Error;
+use crate::storage::codec::{Codec, CodecError};
+use alloc::vec::Vec;
 use core::cmp::PartialOrd;
 use core::result::Result;
 
@@ -26,6 +28,20 @@ impl Address {
     }
 }
 
+impl Codec for Address {
+    const PACKED_LEN: usize = ADDRESS_SIZE;
+
+    #[inline]
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    #[inline]
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        Ok(Self(<[u8; ADDRESS_SIZE]>::try_from_bytes(bytes)?))
+    }
+}
+
 impl<C> minicbor::Encode<C> for Address {
     fn encode<W>(
         &self,
@@ -53,6 +69,9 @@ impl<'a, C> minicbor::Decode<'a, C> for Address {
 #[cfg(test)]
 mod tests {
     use super::Address;
+    use crate::storage::bst::StorageBST;
+    use crate::storage::codec::Codec;
+    use crate::storage::mock::mock_storage;
 
     #[test]
     fn test_decoding() {
@@ -67,4 +86,19 @@ mod tests {
         assert_eq!(decoded_addr, addr);
         assert_eq!(encoded_addr, bytes);
     }
+
+    #[test]
+    fn test_codec() {
+        let addr = Address([
+            1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
+        ]);
+        assert_eq!(addr.to_bytes(), addr.as_bytes());
+        assert_eq!(<Address as Codec>::from_bytes(&addr.to_bytes()), addr);
+        assert!(<Address as Codec>::try_from_bytes(&[1, 2, 3]).is_err());
+
+        let storage = mock_storage(1024 * 1024);
+        let mut balances = StorageBST::<Address, i64>::create(&storage).unwrap();
+        balances.insert(addr.clone(), 100).unwrap();
+        assert_eq!(balances.find(&addr).unwrap(), Some(100));
+    }
 }
@@ -5,14 +5,12 @@
     missing_docs,
     bad_style,
     bare_trait_objects,
-    const_err,
     improper_ctypes,
     non_shorthand_field_patterns,
     no_mangle_generic_items,
     overflowing_literals,
     path_statements,
     patterns_in_fns_without_body,
-    private_in_public,
     unconditional_recursion,
     unused_allocation,
     unused_comparisons,
@@ -24,6 +22,9 @@
 
 extern crate kelk_allocator;
 
+// Allows the derive macros to refer to this crate as `::kelk` inside the crate itself.
+extern crate self as kelk;
+
 pub extern crate alloc;
 
 pub mod blockchain;
@@ -31,7 +32,7 @@ pub mod context;
 pub mod mock;
 pub mod storage;
 
-pub use kelk_derive::{kelk_derive, Codec};
+pub use kelk_derive::{kelk_derive, state, Codec, DynCodec};
 
 #[cfg(target_arch = "wasm32")]
 pub use kelk_env::import::*;
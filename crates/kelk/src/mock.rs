@@ -27,6 +27,7 @@ impl MockContext {
     pub fn mocked_storage(&mut self) -> &mut MockStorage {
         self.storage
             .api_mut()
+            .expect("flushing the storage failed")
             .as_any()
             .downcast_mut::<MockStorage>()
             .expect("Wasn't a trusty printer!")
@@ -0,0 +1,154 @@
+//! Storage Box
+//!
+//! Storage Box keeps a single value inside the storage file, and a copy of the value in memory.
+//! The value is read once when the box is loaded, and the changes are kept in memory until
+//! the box is flushed. Therefore a value that is accessed many times costs only one read
+//! and at most one write.
+//!
+//! ```ignore
+//! let mut config = StorageBox::<Config>::load(storage, offset)?;
+//! config.get_mut().fee = 10;
+//! config.flush()?;
+//! ```
+//!
+//! Note that the changes are not written into the storage file if the box is dropped
+//! without flushing.
+//!
+
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::verify::{Verifier, Verify};
+use crate::storage::{Offset, Storage};
+use core::result::Result;
+
+/// The instance of Storage Box
+pub struct StorageBox<'a, T: Codec> {
+    storage: &'a Storage,
+    offset: Offset,
+    value: T,
+    dirty: bool,
+}
+
+impl<'a, T: Codec> StorageBox<'a, T> {
+    /// Creates a new instance of `StorageBox` and writes the value into the storage file.
+    pub fn create(storage: &'a Storage, value: T) -> Result<Self, Error> {
+        let offset = storage.allocate(T::PACKED_LEN)?;
+        storage.write(offset, &value)?;
+        Ok(StorageBox {
+            storage,
+            offset,
+            value,
+            dirty: false,
+        })
+    }
+
+    /// Loads the `StorageBox` at the given offset and reads its value.
+    pub fn load(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        let value = storage.read(offset)?;
+        Ok(StorageBox {
+            storage,
+            offset,
+            value,
+            dirty: false,
+        })
+    }
+
+    /// Returns the offset of `StorageBox` in the storage file.
+    pub fn offset(&self) -> Offset {
+        self.offset
+    }
+
+    /// Returns a reference to the cached value.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns a mutable reference to the cached value, and marks it as dirty.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.value
+    }
+
+    /// Sets the cached value, and marks it as dirty.
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+        self.dirty = true;
+    }
+
+    /// Returns true if the cached value is changed and not flushed yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes the cached value into the storage file, if it is dirty.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.dirty {
+            self.storage.write(self.offset, &self.value)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Reads the value from the storage file again, and discards the changes.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        self.value = self.storage.read(self.offset)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Flushes the box and returns the value.
+    pub fn into_inner(mut self) -> Result<T, Error> {
+        self.flush()?;
+        Ok(self.value)
+    }
+}
+
+impl<'a, T: Codec> Verify for StorageBox<'a, T> {
+    fn verify(&self, verifier: &mut Verifier) {
+        if verifier.block(self.offset, T::PACKED_LEN) {
+            verifier.read::<T>(self.offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::mock_storage;
+    use crate::Codec;
+
+    #[derive(Codec, Clone, Debug, PartialEq, Default)]
+    struct Config {
+        fee: u32,
+        paused: bool,
+    }
+
+    #[test]
+    fn test_box() {
+        let storage = mock_storage(1024 * 1024);
+        let mut config = StorageBox::create(&storage, Config::default()).unwrap();
+        let offset = config.offset();
+
+        config.get_mut().fee = 10;
+        config.get_mut().paused = true;
+        assert!(config.is_dirty());
+        assert_eq!(
+            StorageBox::<Config>::load(&storage, offset).unwrap().get(),
+            &Config::default()
+        );
+
+        config.flush().unwrap();
+        assert!(!config.is_dirty());
+        let mut loaded = StorageBox::<Config>::load(&storage, offset).unwrap();
+        assert_eq!(loaded.get().fee, 10);
+
+        // Discarding the changes
+        loaded.set(Config::default());
+        loaded.reload().unwrap();
+        assert!(loaded.get().paused);
+
+        loaded.get_mut().fee = 20;
+        assert_eq!(loaded.into_inner().unwrap().fee, 20);
+        assert_eq!(storage.read::<Config>(offset).unwrap().fee, 20);
+    }
+}
@@ -2,6 +2,10 @@ use crate::storage::codec::Codec;
 use crate::storage::Offset;
 use crate::Codec;
 
+/// The highest bit of `key_len` tags the trees that are kept balanced as AVL trees.
+/// The trees of the former versions don't have it and they should be migrated.
+pub(super) const LAYOUT_AVL: u16 = 0x8000;
+
 #[derive(Codec)]
 pub(super) struct Header {
     pub key_len: u16,
@@ -13,10 +17,20 @@ pub(super) struct Header {
 impl Header {
     pub fn new<K: Codec, V: Codec>() -> Self {
         Self {
-            key_len: K::PACKED_LEN as u16,
-            value_len: K::PACKED_LEN as u16,
+            key_len: K::PACKED_LEN as u16 | LAYOUT_AVL,
+            value_len: V::PACKED_LEN as u16,
             count: 0,
             root_offset: 0,
         }
     }
+
+    /// Returns true if the tree is kept balanced as an AVL tree.
+    pub fn is_avl(&self) -> bool {
+        self.key_len & LAYOUT_AVL != 0
+    }
+
+    /// Returns the length of the keys, without the layout tag.
+    pub fn key_len(&self) -> u16 {
+        self.key_len & !LAYOUT_AVL
+    }
 }
@@ -2,16 +2,23 @@
 //!
 //! Storage Binary Search Tree, is a Binary Search Tree or BST that instead of using Random Access Memory (RAM),
 //! it uses storage file. Therefore it's permanently stored inside contract's storage.
+//!
+//! The tree is kept balanced as an AVL tree, therefore inserting sequential keys doesn't degrade
+//! the lookups. The trees that are created by the former versions are not balanced and their nodes
+//! don't keep the height. They should be upgraded once using [`StorageBST::migrate`].
 
 mod header;
 mod node;
 
-use self::header::Header;
-use self::node::Node;
+use self::header::{Header, LAYOUT_AVL};
+use self::node::{LegacyNode, Node};
 use crate::storage::codec::Codec;
 use crate::storage::error::Error;
+use crate::storage::verify::{Problem, Verifier, Verify};
 use crate::storage::{Offset, Storage};
+use alloc::vec::Vec;
 use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds, RangeFull};
 use core::result::Result;
 
 /// The instance of Storage Binary Search Tree
@@ -33,6 +40,9 @@ where
 {
     /// Creates a new instance of `StorageBST`.
     pub fn create(storage: &'a Storage) -> Result<Self, Error> {
+        if K::PACKED_LEN >= LAYOUT_AVL as usize {
+            return Err(Error::GenericError("the key is too large".into()));
+        }
         let offset = storage.allocate(Header::PACKED_LEN)?;
         let header = Header::new::<K, V>();
         storage.write(offset, &header)?;
@@ -46,11 +56,15 @@ where
     }
 
     /// Loads the Storage Binary Search Tree
+    /// If the tree is created by the former versions, it returns `VersionMismatch` error.
     pub fn load(storage: &'a Storage, offset: u32) -> Result<Self, Error> {
         let header: Header = storage.read(offset)?;
-
-        debug_assert_eq!(header.key_len, K::PACKED_LEN as u16);
-        debug_assert_eq!(header.value_len, V::PACKED_LEN as u16);
+        if !header.is_avl() {
+            return Err(Error::VersionMismatch(0));
+        }
+        if header.key_len() != K::PACKED_LEN as u16 || header.value_len != V::PACKED_LEN as u16 {
+            return Err(Error::TypeMismatch);
+        }
 
         Ok(StorageBST {
             storage,
@@ -59,7 +73,52 @@ where
             _phantom: PhantomData,
         })
     }
-    /// Returns the offset of `StorageLinkedList` in the storage file.
+
+    /// Migrates the unbalanced tree at the given offset, that is created by the former versions,
+    /// into a balanced tree. The tree stays at the same offset.
+    ///
+    /// All the entries are read and re-inserted into the balanced tree. The old nodes are
+    /// not freed, since the former versions allocated them by their exact length.
+    /// If the tree is migrated before, it is only loaded.
+    pub fn migrate(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        let header: Header = storage.read(offset)?;
+        if header.is_avl() {
+            return Self::load(storage, offset);
+        }
+        // The former versions wrote the length of the keys as `value_len`.
+        if header.key_len != K::PACKED_LEN as u16 {
+            return Err(Error::TypeMismatch);
+        }
+
+        let mut tree = StorageBST {
+            storage,
+            offset,
+            header: Header::new::<K, V>(),
+            _phantom: PhantomData,
+        };
+        let mut pending = Vec::new();
+        if header.root_offset != 0 {
+            pending.push(header.root_offset);
+        }
+        while let Some(node_offset) = pending.pop() {
+            let node: LegacyNode<K, V> = storage.read(node_offset)?;
+            if node.left != 0 {
+                pending.push(node.left);
+            }
+            if node.right != 0 {
+                pending.push(node.right);
+            }
+            tree.insert(node.key, node.value)?;
+        }
+        if tree.header.count != header.count {
+            return Err(Error::GenericError("invalid tree".into()));
+        }
+        storage.write(offset, &tree.header)?;
+
+        Ok(tree)
+    }
+
+    /// Returns the offset of `StorageBST` in the storage file.
     pub fn offset(&self) -> Offset {
         self.offset
     }
@@ -68,69 +127,14 @@ where
     /// If the map did not have this key present, None is returned.
     /// If the map did have this key present, the value is updated, and the old value is returned.
     pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, Error> {
-        if self.header.count == 0 {
-            // create a root node
-            let offset = self.storage.allocate(Node::<K, V>::PACKED_LEN)?;
-            let root = Node::new(key, value);
-
-            self.header.count = 1;
-            self.header.root_offset = offset;
-
+        let (root_offset, _, old_value) = self.insert_at(self.header.root_offset, key, value)?;
+        if old_value.is_none() {
+            // update header
+            self.header.count += 1;
+            self.header.root_offset = root_offset;
             self.storage.write(self.offset, &self.header)?;
-            self.storage.write(offset, &root)?;
-            Ok(None)
-        } else {
-            let mut offset = self.header.root_offset;
-            let mut node: Node<K, V> = self.storage.read(offset)?;
-
-            loop {
-                if node.key.eq(&key) {
-                    let old_value = node.value;
-                    node.value = value;
-
-                    // node exists, update value
-                    self.storage.write(offset, &node)?;
-                    return Ok(Some(old_value));
-                } else if node.key.le(&key) {
-                    if node.left.eq(&0) {
-                        let new_offset = self.storage.allocate(Node::<K, V>::PACKED_LEN)?;
-                        let new_node = Node::new(key, value);
-
-                        // update header
-                        self.header.count += 1;
-                        self.storage.write(self.offset, &self.header)?;
-
-                        // update parent node
-                        node.left = new_offset;
-                        self.storage.write(offset, &node)?;
-
-                        // write new node
-                        self.storage.write(new_offset, &new_node)?;
-                        return Ok(None);
-                    }
-                    offset = node.left;
-                } else {
-                    if node.right.eq(&0) {
-                        let new_offset = self.storage.allocate(Node::<K, V>::PACKED_LEN)?;
-                        let new_node = Node::new(key, value);
-
-                        // update header
-                        self.header.count += 1;
-                        self.storage.write(self.offset, &self.header)?;
-
-                        // update parent node
-                        node.right = new_offset;
-                        self.storage.write(offset, &node)?;
-
-                        // write new node
-                        self.storage.write(new_offset, &new_node)?;
-                        return Ok(None);
-                    }
-                    offset = node.right;
-                }
-                node = self.storage.read(offset)?;
-            }
         }
+        Ok(old_value)
     }
 
     /// Returns the value corresponding to the key. If the key doesn't exists, it returns None.
@@ -145,7 +149,7 @@ where
         loop {
             if node.key.eq(key) {
                 return Ok(Some(node.value));
-            } else if node.key.le(key) {
+            } else if key.lt(&node.key) {
                 if node.left.eq(&0) {
                     return Ok(None);
                 }
@@ -164,6 +168,452 @@ where
     pub fn contains_key(&self, key: &K) -> Result<bool, Error> {
         Ok(self.find(key)?.is_some())
     }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    /// Returns `true` if the tree contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes a key from the tree, returning the value at the key if the key was previously in the tree.
+    /// The space of the removed node is freed.
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, Error> {
+        let (root_offset, _, old_value) = self.remove_at(self.header.root_offset, key)?;
+        if old_value.is_some() {
+            // update header
+            self.header.count -= 1;
+            self.header.root_offset = root_offset;
+            self.storage.write(self.offset, &self.header)?;
+        }
+        Ok(old_value)
+    }
+
+    /// Returns the first key-value pair in the tree. The key in this pair is the minimum key in the tree.
+    pub fn first(&self) -> Result<Option<(K, V)>, Error> {
+        self.edge(true)
+    }
+
+    /// Returns the last key-value pair in the tree. The key in this pair is the maximum key in the tree.
+    pub fn last(&self) -> Result<Option<(K, V)>, Error> {
+        self.edge(false)
+    }
+
+    /// Gets an iterator over the entries of the tree, sorted by key.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.range(..)
+    }
+
+    /// Gets an iterator over the keys of the tree, in sorted order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Gets an iterator over the values of the tree, in order by key.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    /// Constructs an iterator over a sub-range of entries in the tree, sorted by key.
+    /// Only the nodes inside the range, plus their ancestors, are read from the storage.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, R> {
+        let mut iter = Range {
+            storage: self.storage,
+            stack: Vec::new(),
+            range,
+            error: None,
+        };
+        if let Err(err) = iter.seek(self.header.root_offset) {
+            iter.stack.clear();
+            iter.error = Some(err);
+        }
+        iter
+    }
+
+    // Inserts the key-value pair into the subtree at the given offset.
+    // It returns the offset and the height of the subtree after rebalancing, plus the old value.
+    fn insert_at(
+        &mut self,
+        offset: Offset,
+        key: K,
+        value: V,
+    ) -> Result<(Offset, u8, Option<V>), Error> {
+        if offset == 0 {
+            let new_offset = self.storage.allocate(Node::<K, V>::PACKED_LEN)?;
+            let new_node = Node::new(key, value);
+            self.storage.write(new_offset, &new_node)?;
+            return Ok((new_offset, new_node.height, None));
+        }
+
+        let mut node: Node<K, V> = self.storage.read(offset)?;
+        if node.key.eq(&key) {
+            // node exists, update value
+            let old_value = core::mem::replace(&mut node.value, value);
+            self.storage.write(offset, &node)?;
+            return Ok((offset, node.height, Some(old_value)));
+        }
+
+        if key.lt(&node.key) {
+            let (child, _, old_value) = self.insert_at(node.left, key, value)?;
+            if old_value.is_some() {
+                return Ok((offset, node.height, old_value));
+            }
+            node.left = child;
+        } else {
+            let (child, _, old_value) = self.insert_at(node.right, key, value)?;
+            if old_value.is_some() {
+                return Ok((offset, node.height, old_value));
+            }
+            node.right = child;
+        }
+
+        let (offset, height) = self.rebalance(offset, node)?;
+        Ok((offset, height, None))
+    }
+
+    // Removes the key from the subtree at the given offset.
+    // It returns the offset and the height of the subtree after rebalancing, plus the removed value.
+    fn remove_at(&mut self, offset: Offset, key: &K) -> Result<(Offset, u8, Option<V>), Error> {
+        if offset == 0 {
+            return Ok((0, 0, None));
+        }
+
+        let mut node: Node<K, V> = self.storage.read(offset)?;
+        if key.lt(&node.key) {
+            let (child, _, old_value) = self.remove_at(node.left, key)?;
+            if old_value.is_none() {
+                return Ok((offset, node.height, None));
+            }
+            node.left = child;
+            let (offset, height) = self.rebalance(offset, node)?;
+            return Ok((offset, height, old_value));
+        }
+        if key.gt(&node.key) {
+            let (child, _, old_value) = self.remove_at(node.right, key)?;
+            if old_value.is_none() {
+                return Ok((offset, node.height, None));
+            }
+            node.right = child;
+            let (offset, height) = self.rebalance(offset, node)?;
+            return Ok((offset, height, old_value));
+        }
+
+        if node.left == 0 || node.right == 0 {
+            self.storage.free(offset, Node::<K, V>::PACKED_LEN)?;
+            let child = if node.left != 0 {
+                node.left
+            } else {
+                node.right
+            };
+            let height = self.height(child)?;
+            return Ok((child, height, Some(node.value)));
+        }
+
+        // The node has two children. It will be replaced by its in-order successor,
+        // which is the leftmost node of the right subtree.
+        let (child, succ_key, succ_value) = self.remove_min(node.right)?;
+        node.right = child;
+        node.key = succ_key;
+        let old_value = core::mem::replace(&mut node.value, succ_value);
+        let (offset, height) = self.rebalance(offset, node)?;
+        Ok((offset, height, Some(old_value)))
+    }
+
+    // Removes the leftmost node of the subtree at the given offset.
+    // It returns the offset of the subtree after rebalancing, plus the key and the value of the removed node.
+    fn remove_min(&mut self, offset: Offset) -> Result<(Offset, K, V), Error> {
+        let mut node: Node<K, V> = self.storage.read(offset)?;
+        if node.left == 0 {
+            self.storage.free(offset, Node::<K, V>::PACKED_LEN)?;
+            return Ok((node.right, node.key, node.value));
+        }
+
+        let (child, key, value) = self.remove_min(node.left)?;
+        node.left = child;
+        let (offset, _) = self.rebalance(offset, node)?;
+        Ok((offset, key, value))
+    }
+
+    // Returns the height of the subtree at the given offset.
+    fn height(&self, offset: Offset) -> Result<u8, Error> {
+        if offset == 0 {
+            return Ok(0);
+        }
+        self.storage.read_u8(offset + node::HEIGHT_OFFSET)
+    }
+
+    // Updates the height of the node and rotates the subtree if it is unbalanced.
+    // It writes the changed nodes and returns the offset and the height of the subtree.
+    fn rebalance(&mut self, offset: Offset, mut node: Node<K, V>) -> Result<(Offset, u8), Error> {
+        let left_height = self.height(node.left)?;
+        let right_height = self.height(node.right)?;
+
+        if left_height > right_height + 1 {
+            let left_offset = node.left;
+            let mut left: Node<K, V> = self.storage.read(left_offset)?;
+            if self.height(left.right)? > self.height(left.left)? {
+                // left-right case
+                let left_right = self.storage.read(left.right)?;
+                let (new_left_offset, new_left) =
+                    self.rotate_left(left_offset, left, left_right)?;
+                node.left = new_left_offset;
+                left = new_left;
+            }
+            let (offset, node) = self.rotate_right(offset, node, left)?;
+            return Ok((offset, node.height));
+        }
+
+        if right_height > left_height + 1 {
+            let right_offset = node.right;
+            let mut right: Node<K, V> = self.storage.read(right_offset)?;
+            if self.height(right.left)? > self.height(right.right)? {
+                // right-left case
+                let right_left = self.storage.read(right.left)?;
+                let (new_right_offset, new_right) =
+                    self.rotate_right(right_offset, right, right_left)?;
+                node.right = new_right_offset;
+                right = new_right;
+            }
+            let (offset, node) = self.rotate_left(offset, node, right)?;
+            return Ok((offset, node.height));
+        }
+
+        node.height = 1 + left_height.max(right_height);
+        self.storage.write(offset, &node)?;
+        Ok((offset, node.height))
+    }
+
+    // Rotates the subtree to the right. The left child becomes the root of the subtree.
+    fn rotate_right(
+        &mut self,
+        offset: Offset,
+        mut node: Node<K, V>,
+        mut left: Node<K, V>,
+    ) -> Result<(Offset, Node<K, V>), Error> {
+        let left_offset = node.left;
+        node.left = left.right;
+        node.height = 1 + self.height(node.left)?.max(self.height(node.right)?);
+        self.storage.write(offset, &node)?;
+
+        left.right = offset;
+        left.height = 1 + self.height(left.left)?.max(node.height);
+        self.storage.write(left_offset, &left)?;
+        Ok((left_offset, left))
+    }
+
+    // Rotates the subtree to the left. The right child becomes the root of the subtree.
+    fn rotate_left(
+        &mut self,
+        offset: Offset,
+        mut node: Node<K, V>,
+        mut right: Node<K, V>,
+    ) -> Result<(Offset, Node<K, V>), Error> {
+        let right_offset = node.right;
+        node.right = right.left;
+        node.height = 1 + self.height(node.left)?.max(self.height(node.right)?);
+        self.storage.write(offset, &node)?;
+
+        right.left = offset;
+        right.height = 1 + self.height(right.right)?.max(node.height);
+        self.storage.write(right_offset, &right)?;
+        Ok((right_offset, right))
+    }
+
+    // Returns the leftmost or the rightmost node of the tree.
+    fn edge(&self, leftmost: bool) -> Result<Option<(K, V)>, Error> {
+        let mut offset = self.header.root_offset;
+        if offset == 0 {
+            return Ok(None);
+        }
+
+        loop {
+            let node: Node<K, V> = self.storage.read(offset)?;
+            let next = if leftmost { node.left } else { node.right };
+            if next == 0 {
+                return Ok(Some((node.key, node.value)));
+            }
+            offset = next;
+        }
+    }
+}
+
+/// An iterator over a sub-range of entries in a `StorageBST`, sorted by key.
+///
+/// It walks the nodes directly from the storage and keeps the path to the next node in memory.
+pub struct Range<'a, K, V, R>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    storage: &'a Storage,
+    stack: Vec<Node<K, V>>,
+    range: R,
+    error: Option<Error>,
+}
+
+/// An iterator over the entries of a `StorageBST`, sorted by key.
+pub type Iter<'a, K, V> = Range<'a, K, V, RangeFull>;
+
+impl<'a, K, V, R> Range<'a, K, V, R>
+where
+    K: Codec + Ord,
+    V: Codec,
+    R: RangeBounds<K>,
+{
+    // Pushes the path from the given node to the first node inside the range into the stack.
+    fn seek(&mut self, mut offset: Offset) -> Result<(), Error> {
+        while offset != 0 {
+            let node: Node<K, V> = self.storage.read(offset)?;
+            let after_start = match self.range.start_bound() {
+                Bound::Included(start) => node.key.ge(start),
+                Bound::Excluded(start) => node.key.gt(start),
+                Bound::Unbounded => true,
+            };
+            if after_start {
+                offset = node.left;
+                self.stack.push(node);
+            } else {
+                offset = node.right;
+            }
+        }
+        Ok(())
+    }
+
+    fn before_end(&self, key: &K) -> bool {
+        match self.range.end_bound() {
+            Bound::Included(end) => key.le(end),
+            Bound::Excluded(end) => key.lt(end),
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<'a, K, V, R> Iterator for Range<'a, K, V, R>
+where
+    K: Codec + Ord,
+    V: Codec,
+    R: RangeBounds<K>,
+{
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        let node = self.stack.pop()?;
+        if !self.before_end(&node.key) {
+            self.stack.clear();
+            return None;
+        }
+
+        // All nodes in the right subtree are after the start of the range.
+        let mut offset = node.right;
+        while offset != 0 {
+            match self.storage.read::<Node<K, V>>(offset) {
+                Ok(child) => {
+                    offset = child.left;
+                    self.stack.push(child);
+                }
+                Err(err) => {
+                    self.stack.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        Some(Ok((node.key, node.value)))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a StorageBST<'_, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    type Item = Result<(K, V), Error>;
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the keys of a `StorageBST`, sorted by key.
+pub struct Keys<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    type Item = Result<K, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|res| res.map(|(key, _)| key))
+    }
+}
+
+/// An iterator over the values of a `StorageBST`, in order by key.
+pub struct Values<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    type Item = Result<V, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|res| res.map(|(_, value)| value))
+    }
+}
+
+impl<'a, K, V> Verify for StorageBST<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    fn verify(&self, verifier: &mut Verifier) {
+        if !verifier.block(self.offset, Header::PACKED_LEN) {
+            return;
+        }
+        let mut count = 0;
+        let mut pending = Vec::new();
+        if self.header.root_offset != 0 {
+            pending.push(self.header.root_offset);
+        }
+        while let Some(offset) = pending.pop() {
+            if !verifier.block(offset, Node::<K, V>::PACKED_LEN) {
+                continue;
+            }
+            if let Some(node) = verifier.read::<Node<K, V>>(offset) {
+                count += 1;
+                pending.extend([node.left, node.right].into_iter().filter(|o| *o != 0));
+            }
+        }
+        if count != self.header.count {
+            verifier.problem(Problem::CountMismatch {
+                offset: self.offset,
+                expected: self.header.count,
+                found: count,
+            });
+        }
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +621,203 @@ mod tests {
     use super::*;
     use crate::storage::mock::mock_storage;
 
+    #[test]
+    fn test_bst_remove() {
+        let storage = mock_storage(1024 * 1024);
+        let mut bst = StorageBST::<i32, i32>::create(&storage).unwrap();
+
+        for key in [50, 30, 70, 20, 40, 60, 80, 35, 45, 65] {
+            bst.insert(key, key * 10).unwrap();
+        }
+        assert_eq!(bst.len(), 10);
+
+        // leaf
+        assert_eq!(Some(200), bst.remove(&20).unwrap());
+        // one child
+        assert_eq!(Some(600), bst.remove(&60).unwrap());
+        // two children, successor is not the right child
+        assert_eq!(Some(300), bst.remove(&30).unwrap());
+        // root with two children
+        assert_eq!(Some(500), bst.remove(&50).unwrap());
+        // not exists
+        assert_eq!(None, bst.remove(&50).unwrap());
+        assert_eq!(bst.len(), 6);
+
+        let bst = StorageBST::<i32, i32>::load(&storage, bst.offset()).unwrap();
+        let keys: Vec<i32> = bst.keys().map(|k| k.unwrap()).collect();
+        assert_eq!(keys, [35, 40, 45, 65, 70, 80]);
+        assert_eq!(None, bst.find(&30).unwrap());
+        assert_eq!(Some(350), bst.find(&35).unwrap());
+    }
+
+    #[test]
+    fn test_bst_remove_all() {
+        let storage = mock_storage(1024 * 1024);
+        let mut bst = StorageBST::<i32, i32>::create(&storage).unwrap();
+
+        bst.insert(1, 10).unwrap();
+        bst.insert(2, 20).unwrap();
+        let free_pos = storage.read_u32(storage.free_pos_offset()).unwrap();
+
+        assert_eq!(Some(10), bst.remove(&1).unwrap());
+        assert_eq!(Some(20), bst.remove(&2).unwrap());
+        assert!(bst.is_empty());
+        assert_eq!(None, bst.first().unwrap());
+        assert_eq!(0, bst.iter().count());
+
+        // The freed nodes are reused
+        bst.insert(3, 30).unwrap();
+        bst.insert(4, 40).unwrap();
+        assert_eq!(
+            free_pos,
+            storage.read_u32(storage.free_pos_offset()).unwrap()
+        );
+        assert_eq!(Some((3, 30)), bst.first().unwrap());
+        assert_eq!(Some((4, 40)), bst.last().unwrap());
+    }
+
+    #[test]
+    fn test_bst_iter() {
+        let storage = mock_storage(1024 * 1024);
+        let mut bst = StorageBST::<i32, i32>::create(&storage).unwrap();
+        assert_eq!(None, bst.last().unwrap());
+
+        for key in [5, 2, 8, 1, 9, 3, 7, 4, 6, 0] {
+            bst.insert(key, key * 10).unwrap();
+        }
+
+        let items: Vec<(i32, i32)> = bst.iter().map(|i| i.unwrap()).collect();
+        assert_eq!(items.len(), 10);
+        for (i, (key, value)) in items.into_iter().enumerate() {
+            assert_eq!(key, i as i32);
+            assert_eq!(value, i as i32 * 10);
+        }
+
+        let values: Vec<i32> = bst.values().map(|v| v.unwrap()).collect();
+        assert_eq!(values, [0, 10, 20, 30, 40, 50, 60, 70, 80, 90]);
+
+        let keys: Vec<i32> = bst.range(3..7).map(|i| i.unwrap().0).collect();
+        assert_eq!(keys, [3, 4, 5, 6]);
+
+        let keys: Vec<i32> = bst.range(3..=7).map(|i| i.unwrap().0).collect();
+        assert_eq!(keys, [3, 4, 5, 6, 7]);
+
+        let keys: Vec<i32> = bst.range(..2).map(|i| i.unwrap().0).collect();
+        assert_eq!(keys, [0, 1]);
+
+        let keys: Vec<i32> = bst.range(8..).map(|i| i.unwrap().0).collect();
+        assert_eq!(keys, [8, 9]);
+
+        let keys: Vec<i32> = bst
+            .range((Bound::Excluded(4), Bound::Unbounded))
+            .map(|i| i.unwrap().0)
+            .collect();
+        assert_eq!(keys, [5, 6, 7, 8, 9]);
+
+        assert_eq!(0, bst.range(10..20).count());
+        assert_eq!(Some((0, 0)), bst.first().unwrap());
+        assert_eq!(Some((9, 90)), bst.last().unwrap());
+        assert_eq!(10, (&bst).into_iter().count());
+    }
+
+    // Checks the AVL properties of the subtree and returns its height.
+    fn check_balanced(storage: &Storage, offset: Offset) -> u8 {
+        if offset == 0 {
+            return 0;
+        }
+        let node: Node<i32, i32> = storage.read(offset).unwrap();
+        let left_height = check_balanced(storage, node.left);
+        let right_height = check_balanced(storage, node.right);
+        assert!(left_height.abs_diff(right_height) <= 1);
+        assert_eq!(node.height, 1 + left_height.max(right_height));
+        node.height
+    }
+
+    #[test]
+    fn test_bst_balanced() {
+        let storage = mock_storage(1024 * 1024);
+        let mut bst = StorageBST::<i32, i32>::create(&storage).unwrap();
+
+        for key in 0..1000 {
+            bst.insert(key, key).unwrap();
+        }
+        let height = check_balanced(&storage, bst.header.root_offset);
+        assert!(height <= 11);
+
+        for key in (0..1000).rev() {
+            assert_eq!(Some(key), bst.find(&key).unwrap());
+        }
+
+        for key in (0..1000).step_by(3) {
+            assert_eq!(Some(key), bst.remove(&key).unwrap());
+        }
+        check_balanced(&storage, bst.header.root_offset);
+        assert_eq!(bst.len(), 666);
+
+        for key in 0..500 {
+            bst.remove(&key).unwrap();
+        }
+        check_balanced(&storage, bst.header.root_offset);
+        assert_eq!(Some((500, 500)), bst.first().unwrap());
+    }
+
+    #[test]
+    fn test_bst_migrate() {
+        use crate::storage::mock::MockV1Storage;
+
+        // A version 1 file with a tree that is built by inserting 1, 2, 3 and 4.
+        // The former versions kept the bigger keys on the left: 1 <- 2 <- 3 <- 4
+        let v1 = MockV1Storage::new(2048);
+        let node_len = LegacyNode::<i32, i64>::PACKED_LEN as Offset;
+        let nodes: Vec<Offset> = (0..4)
+            .map(|i| 1032 + Header::PACKED_LEN as Offset + i * node_len)
+            .collect();
+        let header = Header {
+            key_len: 4,
+            value_len: 4,
+            count: 4,
+            root_offset: nodes[0],
+        };
+        let offset = v1.allocate(&header.to_bytes());
+        for (i, key) in (1..=4).enumerate() {
+            let node = LegacyNode {
+                left: nodes.get(i + 1).copied().unwrap_or(0),
+                right: 0,
+                key,
+                value: key as i64 * 10,
+            };
+            assert_eq!(v1.allocate(&node.to_bytes()), nodes[i]);
+        }
+        v1.fill_stack_at(0, offset);
+        let storage = Storage::load(v1.into_api()).unwrap();
+        assert!(matches!(
+            StorageBST::<i32, i64>::load(&storage, offset),
+            Err(Error::VersionMismatch(_))
+        ));
+        assert!(matches!(
+            StorageBST::<i64, i64>::migrate(&storage, offset),
+            Err(Error::TypeMismatch)
+        ));
+
+        let mut bst = StorageBST::<i32, i64>::migrate(&storage, offset).unwrap();
+        assert_eq!(bst.len(), 4);
+        bst.insert(5, 50).unwrap();
+
+        let bst = StorageBST::<i32, i64>::load(&storage, offset).unwrap();
+        let items: Vec<(i32, i64)> = bst.iter().map(|i| i.unwrap()).collect();
+        assert_eq!(items, [(1, 10), (2, 20), (3, 30), (4, 40), (5, 50)]);
+        assert_eq!(
+            StorageBST::<i32, i64>::migrate(&storage, offset)
+                .unwrap()
+                .len(),
+            5
+        );
+
+        let mut verifier = storage.verifier().unwrap();
+        verifier.stack::<StorageBST<i32, i64>>(0).unwrap();
+        assert!(verifier.finish().is_empty());
+    }
+
     #[test]
     fn test_bst() {
         let storage = mock_storage(1024 * 1024);
@@ -2,10 +2,14 @@ use crate::storage::codec::Codec;
 use crate::storage::Offset;
 use crate::Codec;
 
+/// The offset of the `height` field inside the packed node.
+pub(super) const HEIGHT_OFFSET: Offset = 8;
+
 #[derive(Codec)]
 pub(super) struct Node<K: Codec + Ord, V: Codec> {
     pub left: Offset,
     pub right: Offset,
+    pub height: u8,
     pub key: K,
     pub value: V,
 }
@@ -17,6 +21,16 @@ impl<K: Codec + Ord, V: Codec> Node<K, V> {
             value,
             left: 0,
             right: 0,
+            height: 1,
         }
     }
 }
+
+/// The node layout of the unbalanced trees, which doesn't keep the height of the nodes.
+#[derive(Codec)]
+pub(super) struct LegacyNode<K: Codec + Ord, V: Codec> {
+    pub left: Offset,
+    pub right: Offset,
+    pub key: K,
+    pub value: V,
+}
@@ -0,0 +1,828 @@
+//! Storage B-Tree Map
+//!
+//! Storage B-Tree Map, is an ordered map based on a B-Tree that instead of using Random Access Memory (RAM),
+//! it uses storage file. Therefore it's permanently stored inside contract's storage.
+//!
+//! Each node keeps up to `order - 1` entries and it is read or written by one storage call.
+//! Having wide nodes keeps the tree shallow, therefore a lookup in a map with millions of entries
+//! reads only a few nodes from the storage file.
+
+mod header;
+mod node;
+
+use self::header::Header;
+use self::node::Node;
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::verify::{Problem, Verifier, Verify};
+use crate::storage::{Offset, Storage};
+use alloc::string::ToString;
+use alloc::vec::{IntoIter, Vec};
+use core::cmp::Ordering;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds, RangeFull};
+use core::result::Result;
+
+/// The size of the nodes in bytes, when the order is not specified.
+pub const DEFAULT_NODE_SIZE: usize = 4096;
+
+/// The instance of Storage B-Tree Map
+pub struct StorageBTreeMap<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    storage: &'a Storage,
+    offset: Offset,
+    header: Header,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'a, K, V> StorageBTreeMap<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    /// Creates a new instance of `StorageBTreeMap`.
+    /// The order of the tree is chosen in a way that each node fits in `DEFAULT_NODE_SIZE` bytes.
+    pub fn create(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create_with_order(storage, Self::default_order())
+    }
+
+    /// Creates a new instance of `StorageBTreeMap` with the given order.
+    /// The order is the maximum number of children for each node. It should be an even number
+    /// and not less than 4.
+    pub fn create_with_order(storage: &'a Storage, order: u16) -> Result<Self, Error> {
+        check_order(order)?;
+
+        let offset = storage.allocate(Header::PACKED_LEN)?;
+        let header = Header::new::<K, V>(order);
+        storage.write(offset, &header)?;
+
+        Ok(StorageBTreeMap {
+            storage,
+            offset,
+            header,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Loads the Storage B-Tree Map
+    pub fn load(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        let header: Header = storage.read(offset)?;
+        if header.key_len != K::PACKED_LEN as u16 || header.value_len != V::PACKED_LEN as u16 {
+            return Err(Error::TypeMismatch);
+        }
+        check_order(header.order)?;
+
+        Ok(StorageBTreeMap {
+            storage,
+            offset,
+            header,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the offset of `StorageBTreeMap` in the storage file.
+    pub fn offset(&self) -> Offset {
+        self.offset
+    }
+
+    /// Returns the order of the tree, which is the maximum number of children for each node.
+    pub fn order(&self) -> u16 {
+        self.header.order
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts a key-value pair into the map.
+    /// If the map did not have this key present, None is returned.
+    /// If the map did have this key present, the value is updated, and the old value is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, Error> {
+        if self.header.root_offset == 0 {
+            let mut root = Node::new();
+            root.keys.push(key);
+            root.values.push(value);
+            let root_offset = self.allocate_node()?;
+            self.write_node(root_offset, &root)?;
+
+            // update header
+            self.header.count = 1;
+            self.header.root_offset = root_offset;
+            self.storage.write(self.offset, &self.header)?;
+            return Ok(None);
+        }
+
+        let mut offset = self.header.root_offset;
+        let mut node = self.read_node(offset)?;
+        if node.keys.len() == self.max_keys() {
+            // The root is full, splitting it increases the height of the tree.
+            let root_offset = self.allocate_node()?;
+            let mut root = Node::new();
+            root.children.push(offset);
+            self.split_child(root_offset, &mut root, 0, offset, &mut node)?;
+
+            self.header.root_offset = root_offset;
+            self.storage.write(self.offset, &self.header)?;
+
+            offset = root_offset;
+            node = root;
+        }
+
+        // Walking down the tree and splitting the full nodes on the way,
+        // so there is always room in the parent for a splitting child.
+        loop {
+            let index = match node.keys.binary_search(&key) {
+                Ok(index) => {
+                    // key exists, update value
+                    let old_value = core::mem::replace(&mut node.values[index], value);
+                    self.write_node(offset, &node)?;
+                    return Ok(Some(old_value));
+                }
+                Err(index) => index,
+            };
+
+            if node.is_leaf() {
+                node.keys.insert(index, key);
+                node.values.insert(index, value);
+                self.write_node(offset, &node)?;
+
+                // update header
+                self.header.count += 1;
+                self.storage.write(self.offset, &self.header)?;
+                return Ok(None);
+            }
+
+            let mut child_offset = node.children[index];
+            let mut child = self.read_node(child_offset)?;
+            if child.keys.len() == self.max_keys() {
+                let (right_offset, right) =
+                    self.split_child(offset, &mut node, index, child_offset, &mut child)?;
+                match key.cmp(&node.keys[index]) {
+                    Ordering::Equal => {
+                        let old_value = core::mem::replace(&mut node.values[index], value);
+                        self.write_node(offset, &node)?;
+                        return Ok(Some(old_value));
+                    }
+                    Ordering::Less => {}
+                    Ordering::Greater => {
+                        child_offset = right_offset;
+                        child = right;
+                    }
+                }
+            }
+            offset = child_offset;
+            node = child;
+        }
+    }
+
+    /// Returns the value corresponding to the key. If the key doesn't exists, it returns None.
+    pub fn find(&self, key: &K) -> Result<Option<V>, Error> {
+        let mut offset = self.header.root_offset;
+        while offset != 0 {
+            let mut node = self.read_node(offset)?;
+            match node.keys.binary_search(key) {
+                Ok(index) => return Ok(Some(node.values.swap_remove(index))),
+                Err(index) => offset = node.children.get(index).copied().unwrap_or(0),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns true if the map contains a value for the specified key.
+    pub fn contains_key(&self, key: &K) -> Result<bool, Error> {
+        Ok(self.find(key)?.is_some())
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was previously in the map.
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, Error> {
+        let root_offset = self.header.root_offset;
+        if root_offset == 0 {
+            return Ok(None);
+        }
+
+        let root = self.read_node(root_offset)?;
+        let single_key = root.keys.len() == 1;
+        let old_value = self.remove_from(root_offset, root, key)?;
+        if old_value.is_some() {
+            self.header.count -= 1;
+        }
+
+        if single_key {
+            // The root might be empty now, then the tree shrinks in height.
+            let root = self.read_node(root_offset)?;
+            if root.keys.is_empty() {
+                self.header.root_offset = root.children.first().copied().unwrap_or(0);
+                self.free_node(root_offset)?;
+            }
+        }
+
+        if old_value.is_some() || self.header.root_offset != root_offset {
+            self.storage.write(self.offset, &self.header)?;
+        }
+        Ok(old_value)
+    }
+
+    /// Gets an iterator over the entries of the map, sorted by key.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.range(..)
+    }
+
+    /// Constructs an iterator over a sub-range of entries in the map, sorted by key.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, R> {
+        let mut iter = Range {
+            storage: self.storage,
+            order: self.header.order,
+            stack: Vec::new(),
+            range,
+            error: None,
+        };
+        if let Err(err) = iter.seek(self.header.root_offset) {
+            iter.stack.clear();
+            iter.error = Some(err);
+        }
+        iter
+    }
+
+    fn default_order() -> u16 {
+        // packed_len = 2 + (order - 1) * entry_len + order * 4
+        let entry_len = K::PACKED_LEN + V::PACKED_LEN;
+        let order =
+            (DEFAULT_NODE_SIZE - u16::PACKED_LEN + entry_len) / (entry_len + Offset::PACKED_LEN);
+        let order = order.min(u16::MAX as usize) as u16;
+        (order & !1).max(4)
+    }
+
+    fn max_keys(&self) -> usize {
+        self.header.order as usize - 1
+    }
+
+    fn min_keys(&self) -> usize {
+        self.header.order as usize / 2 - 1
+    }
+
+    fn allocate_node(&self) -> Result<Offset, Error> {
+        self.storage
+            .allocate(Node::<K, V>::packed_len(self.header.order))
+    }
+
+    fn free_node(&self, offset: Offset) -> Result<(), Error> {
+        self.storage
+            .free(offset, Node::<K, V>::packed_len(self.header.order))
+    }
+
+    fn read_node(&self, offset: Offset) -> Result<Node<K, V>, Error> {
+        read_node(self.storage, offset, self.header.order)
+    }
+
+    fn write_node(&self, offset: Offset, node: &Node<K, V>) -> Result<(), Error> {
+        self.storage
+            .write_bytes(offset, &node.to_bytes(self.header.order))
+    }
+
+    // Splits the full child at the given index into two nodes and moves its median entry up to the parent.
+    // It writes all three nodes and returns the right node.
+    fn split_child(
+        &self,
+        parent_offset: Offset,
+        parent: &mut Node<K, V>,
+        index: usize,
+        child_offset: Offset,
+        child: &mut Node<K, V>,
+    ) -> Result<(Offset, Node<K, V>), Error> {
+        let mid = self.min_keys() + 1;
+        let mut right = Node::new();
+        right.keys = child.keys.split_off(mid);
+        right.values = child.values.split_off(mid);
+        if !child.is_leaf() {
+            right.children = child.children.split_off(mid);
+        }
+        let right_offset = self.allocate_node()?;
+
+        // The last entry of the left node is the median
+        let (median_key, median_value) = pop_entry(child)?;
+        parent.keys.insert(index, median_key);
+        parent.values.insert(index, median_value);
+        parent.children.insert(index + 1, right_offset);
+
+        self.write_node(child_offset, child)?;
+        self.write_node(right_offset, &right)?;
+        self.write_node(parent_offset, parent)?;
+
+        Ok((right_offset, right))
+    }
+
+    // Removes the key from the subtree at the given offset.
+    // Before walking down to a child, it makes sure that the child has more than the minimum
+    // number of keys, so removing a key from it doesn't need to walk back up.
+    fn remove_from(
+        &mut self,
+        offset: Offset,
+        mut node: Node<K, V>,
+        key: &K,
+    ) -> Result<Option<V>, Error> {
+        match node.keys.binary_search(key) {
+            Ok(index) => {
+                if node.is_leaf() {
+                    node.keys.remove(index);
+                    let old_value = node.values.remove(index);
+                    self.write_node(offset, &node)?;
+                    return Ok(Some(old_value));
+                }
+
+                // Replacing the entry with its predecessor or successor
+                let left_offset = node.children[index];
+                let left = self.read_node(left_offset)?;
+                if left.keys.len() > self.min_keys() {
+                    let (pred_key, pred_value) = self.remove_last(left_offset, left)?;
+                    node.keys[index] = pred_key;
+                    let old_value = core::mem::replace(&mut node.values[index], pred_value);
+                    self.write_node(offset, &node)?;
+                    return Ok(Some(old_value));
+                }
+
+                let right_offset = node.children[index + 1];
+                let right = self.read_node(right_offset)?;
+                if right.keys.len() > self.min_keys() {
+                    let (succ_key, succ_value) = self.remove_first(right_offset, right)?;
+                    node.keys[index] = succ_key;
+                    let old_value = core::mem::replace(&mut node.values[index], succ_value);
+                    self.write_node(offset, &node)?;
+                    return Ok(Some(old_value));
+                }
+
+                // Both children have the minimum number of keys, merging them
+                let merged = self.merge_children(offset, &mut node, index, left, right)?;
+                self.remove_from(left_offset, merged, key)
+            }
+            Err(index) => {
+                if node.is_leaf() {
+                    return Ok(None);
+                }
+                let (child_offset, child) = self.fill_child(offset, &mut node, index)?;
+                self.remove_from(child_offset, child, key)
+            }
+        }
+    }
+
+    // Removes the first entry of the subtree at the given offset.
+    fn remove_first(&mut self, offset: Offset, mut node: Node<K, V>) -> Result<(K, V), Error> {
+        if node.is_leaf() {
+            let key = node.keys.remove(0);
+            let value = node.values.remove(0);
+            self.write_node(offset, &node)?;
+            return Ok((key, value));
+        }
+        let (child_offset, child) = self.fill_child(offset, &mut node, 0)?;
+        self.remove_first(child_offset, child)
+    }
+
+    // Removes the last entry of the subtree at the given offset.
+    fn remove_last(&mut self, offset: Offset, mut node: Node<K, V>) -> Result<(K, V), Error> {
+        if node.is_leaf() {
+            let entry = pop_entry(&mut node)?;
+            self.write_node(offset, &node)?;
+            return Ok(entry);
+        }
+        let index = node.keys.len();
+        let (child_offset, child) = self.fill_child(offset, &mut node, index)?;
+        self.remove_last(child_offset, child)
+    }
+
+    // Makes sure the child at the given index has more than the minimum number of keys,
+    // by borrowing a key from a sibling or merging with a sibling.
+    // It returns the child that should be walked down, which might be the merged node.
+    fn fill_child(
+        &mut self,
+        offset: Offset,
+        node: &mut Node<K, V>,
+        index: usize,
+    ) -> Result<(Offset, Node<K, V>), Error> {
+        let child_offset = node.children[index];
+        let mut child = self.read_node(child_offset)?;
+        if child.keys.len() > self.min_keys() {
+            return Ok((child_offset, child));
+        }
+
+        let mut left_sibling = None;
+        if index > 0 {
+            let left_offset = node.children[index - 1];
+            let mut left = self.read_node(left_offset)?;
+            if left.keys.len() > self.min_keys() {
+                // Borrowing the last entry of the left sibling through the parent
+                let (key, value) = pop_entry(&mut left)?;
+                let parent_key = core::mem::replace(&mut node.keys[index - 1], key);
+                let parent_value = core::mem::replace(&mut node.values[index - 1], value);
+                child.keys.insert(0, parent_key);
+                child.values.insert(0, parent_value);
+                if let Some(grandchild) = left.children.pop() {
+                    child.children.insert(0, grandchild);
+                }
+
+                self.write_node(left_offset, &left)?;
+                self.write_node(child_offset, &child)?;
+                self.write_node(offset, node)?;
+                return Ok((child_offset, child));
+            }
+            left_sibling = Some((left_offset, left));
+        }
+
+        if index < node.keys.len() {
+            let right_offset = node.children[index + 1];
+            let mut right = self.read_node(right_offset)?;
+            if right.keys.len() > self.min_keys() {
+                // Borrowing the first entry of the right sibling through the parent
+                let key = right.keys.remove(0);
+                let value = right.values.remove(0);
+                let parent_key = core::mem::replace(&mut node.keys[index], key);
+                let parent_value = core::mem::replace(&mut node.values[index], value);
+                child.keys.push(parent_key);
+                child.values.push(parent_value);
+                if !right.is_leaf() {
+                    child.children.push(right.children.remove(0));
+                }
+
+                self.write_node(right_offset, &right)?;
+                self.write_node(child_offset, &child)?;
+                self.write_node(offset, node)?;
+                return Ok((child_offset, child));
+            }
+
+            let merged = self.merge_children(offset, node, index, child, right)?;
+            return Ok((child_offset, merged));
+        }
+
+        match left_sibling {
+            Some((left_offset, left)) => {
+                let merged = self.merge_children(offset, node, index - 1, left, child)?;
+                Ok((left_offset, merged))
+            }
+            None => Err(Error::GenericError("invalid node".to_string())),
+        }
+    }
+
+    // Merges the children at `index` and `index + 1` and the entry between them into the left child.
+    // The right child is freed.
+    fn merge_children(
+        &mut self,
+        offset: Offset,
+        node: &mut Node<K, V>,
+        index: usize,
+        mut left: Node<K, V>,
+        right: Node<K, V>,
+    ) -> Result<Node<K, V>, Error> {
+        let right_offset = node.children.remove(index + 1);
+        left.keys.push(node.keys.remove(index));
+        left.values.push(node.values.remove(index));
+        left.keys.extend(right.keys);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+
+        self.free_node(right_offset)?;
+        self.write_node(node.children[index], &left)?;
+        self.write_node(offset, node)?;
+        Ok(left)
+    }
+}
+
+// The order should be even, so a full node splits into two nodes with the minimum keys.
+fn check_order(order: u16) -> Result<(), Error> {
+    if order < 4 || order & 1 != 0 {
+        return Err(Error::GenericError("invalid order".to_string()));
+    }
+    Ok(())
+}
+
+fn read_node<K: Codec + Ord, V: Codec>(
+    storage: &Storage,
+    offset: Offset,
+    order: u16,
+) -> Result<Node<K, V>, Error> {
+    let bytes = storage.read_bytes(offset, Node::<K, V>::packed_len(order) as u32)?;
+    Ok(Node::try_from_bytes(&bytes, order)?)
+}
+
+fn pop_entry<K: Codec + Ord, V: Codec>(node: &mut Node<K, V>) -> Result<(K, V), Error> {
+    match (node.keys.pop(), node.values.pop()) {
+        (Some(key), Some(value)) => Ok((key, value)),
+        _ => Err(Error::GenericError("invalid node".to_string())),
+    }
+}
+
+struct Frame<K, V> {
+    keys: IntoIter<K>,
+    values: IntoIter<V>,
+    children: Vec<Offset>,
+    // The index of the child after the next key
+    next_child: usize,
+}
+
+/// An iterator over a sub-range of entries in a `StorageBTreeMap`, sorted by key.
+///
+/// It keeps the nodes on the path to the next entry in memory.
+pub struct Range<'a, K, V, R>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    storage: &'a Storage,
+    order: u16,
+    stack: Vec<Frame<K, V>>,
+    range: R,
+    error: Option<Error>,
+}
+
+/// An iterator over the entries of a `StorageBTreeMap`, sorted by key.
+pub type Iter<'a, K, V> = Range<'a, K, V, RangeFull>;
+
+impl<'a, K, V, R> Range<'a, K, V, R>
+where
+    K: Codec + Ord,
+    V: Codec,
+    R: RangeBounds<K>,
+{
+    // Pushes the path from the given node to the first entry inside the range into the stack.
+    fn seek(&mut self, mut offset: Offset) -> Result<(), Error> {
+        while offset != 0 {
+            let node: Node<K, V> = read_node(self.storage, offset, self.order)?;
+            let index = node
+                .keys
+                .partition_point(|key| match self.range.start_bound() {
+                    Bound::Included(start) => key.lt(start),
+                    Bound::Excluded(start) => key.le(start),
+                    Bound::Unbounded => false,
+                });
+
+            let mut keys = node.keys;
+            let mut values = node.values;
+            keys.drain(..index);
+            values.drain(..index);
+            offset = node.children.get(index).copied().unwrap_or(0);
+
+            self.stack.push(Frame {
+                keys: keys.into_iter(),
+                values: values.into_iter(),
+                children: node.children,
+                next_child: index + 1,
+            });
+        }
+        Ok(())
+    }
+
+    fn before_end(&self, key: &K) -> bool {
+        match self.range.end_bound() {
+            Bound::Included(end) => key.le(end),
+            Bound::Excluded(end) => key.lt(end),
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<'a, K, V, R> Iterator for Range<'a, K, V, R>
+where
+    K: Codec + Ord,
+    V: Codec,
+    R: RangeBounds<K>,
+{
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+
+        loop {
+            let frame = self.stack.last_mut()?;
+            let (key, value) = match (frame.keys.next(), frame.values.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let child = frame.children.get(frame.next_child).copied().unwrap_or(0);
+            frame.next_child += 1;
+
+            if !self.before_end(&key) {
+                self.stack.clear();
+                return None;
+            }
+            if let Err(err) = self.seek(child) {
+                self.stack.clear();
+                return Some(Err(err));
+            }
+            return Some(Ok((key, value)));
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a StorageBTreeMap<'_, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    type Item = Result<(K, V), Error>;
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> Verify for StorageBTreeMap<'a, K, V>
+where
+    K: Codec + Ord,
+    V: Codec,
+{
+    fn verify(&self, verifier: &mut Verifier) {
+        if !verifier.block(self.offset, Header::PACKED_LEN) {
+            return;
+        }
+        let mut count = 0;
+        let mut pending = Vec::new();
+        if self.header.root_offset != 0 {
+            pending.push(self.header.root_offset);
+        }
+        while let Some(offset) = pending.pop() {
+            if !verifier.block(offset, Node::<K, V>::packed_len(self.header.order)) {
+                continue;
+            }
+            match self.read_node(offset) {
+                Ok(node) => {
+                    count += node.keys.len() as u32;
+                    pending.extend(node.children);
+                }
+                Err(_) => verifier.problem(Problem::Corrupted(offset)),
+            }
+        }
+        if count != self.header.count {
+            verifier.problem(Problem::CountMismatch {
+                offset: self.offset,
+                expected: self.header.count,
+                found: count,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::mock_storage;
+    use alloc::collections::BTreeMap;
+
+    // A simple deterministic pseudo-random generator for shuffling the keys
+    fn next_rand(seed: &mut u32) -> u32 {
+        *seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        *seed >> 8
+    }
+
+    // Checks the B-Tree properties of the subtree and returns its depth.
+    fn check_node(map: &StorageBTreeMap<u32, u64>, offset: Offset, is_root: bool) -> usize {
+        let node = map.read_node(offset).unwrap();
+        assert!(node.keys.len() <= map.max_keys());
+        if !is_root {
+            assert!(node.keys.len() >= map.min_keys());
+        }
+        assert!(node.keys.windows(2).all(|w| w[0] < w[1]));
+        if node.is_leaf() {
+            return 1;
+        }
+        assert_eq!(node.children.len(), node.keys.len() + 1);
+        let depths: Vec<usize> = node
+            .children
+            .iter()
+            .map(|child| check_node(map, *child, false))
+            .collect();
+        assert!(depths.windows(2).all(|w| w[0] == w[1]));
+        depths[0] + 1
+    }
+
+    #[test]
+    fn test_btree_map() {
+        let storage = mock_storage(1024 * 1024);
+        let mut map_1 = StorageBTreeMap::<i32, i32>::create_with_order(&storage, 4).unwrap();
+
+        assert_eq!(None, map_1.insert(1, 10).unwrap());
+        assert_eq!(None, map_1.insert(3, 30).unwrap());
+        assert_eq!(None, map_1.insert(2, 20).unwrap());
+        assert_eq!(Some(10), map_1.insert(1, 100).unwrap());
+
+        let map_2 = StorageBTreeMap::<i32, i32>::load(&storage, map_1.offset()).unwrap();
+        assert_eq!(map_2.len(), 3);
+        assert_eq!(map_2.order(), 4);
+        assert_eq!(Some(20), map_2.find(&2).unwrap());
+        assert_eq!(None, map_2.find(&4).unwrap());
+        assert_eq!(Some(30), map_2.find(&3).unwrap());
+        assert_eq!(Some(100), map_2.find(&1).unwrap());
+        assert!(!map_2.contains_key(&-1).unwrap());
+        assert!(map_2.contains_key(&2).unwrap());
+    }
+
+    #[test]
+    fn test_btree_map_invalid_order() {
+        let storage = mock_storage(1024 * 1024);
+        assert!(StorageBTreeMap::<i32, i32>::create_with_order(&storage, 2).is_err());
+        assert!(StorageBTreeMap::<i32, i32>::create_with_order(&storage, 5).is_err());
+
+        // Loading a corrupted order
+        let map = StorageBTreeMap::<i32, i32>::create_with_order(&storage, 4).unwrap();
+        for order in [0, 1, 2, 5] {
+            let mut header: Header = storage.read(map.offset()).unwrap();
+            header.order = order;
+            storage.write(map.offset(), &header).unwrap();
+            assert!(StorageBTreeMap::<i32, i32>::load(&storage, map.offset()).is_err());
+        }
+
+        let map = StorageBTreeMap::<u32, u64>::create(&storage).unwrap();
+        assert!(Node::<u32, u64>::packed_len(map.order()) <= DEFAULT_NODE_SIZE);
+        assert!(Node::<u32, u64>::packed_len(map.order() + 2) > DEFAULT_NODE_SIZE);
+    }
+
+    #[test]
+    fn test_btree_map_random() {
+        let storage = mock_storage(4 * 1024 * 1024);
+        for order in [4, 6, 16] {
+            let mut map = StorageBTreeMap::<u32, u64>::create_with_order(&storage, order).unwrap();
+            let mut expected = BTreeMap::new();
+            let mut seed = order as u32;
+
+            for _ in 0..2000 {
+                let key = next_rand(&mut seed) % 1000;
+                let value = next_rand(&mut seed) as u64;
+                assert_eq!(expected.insert(key, value), map.insert(key, value).unwrap());
+            }
+            check_node(&map, map.header.root_offset, true);
+            assert_eq!(expected.len(), map.len());
+
+            for _ in 0..1500 {
+                let key = next_rand(&mut seed) % 1000;
+                assert_eq!(expected.remove(&key), map.remove(&key).unwrap());
+                assert_eq!(None, map.find(&key).unwrap());
+            }
+            check_node(&map, map.header.root_offset, true);
+            assert_eq!(expected.len(), map.len());
+
+            let items: Vec<(u32, u64)> = map.iter().map(|i| i.unwrap()).collect();
+            let expected_items: Vec<(u32, u64)> = expected.into_iter().collect();
+            assert_eq!(items, expected_items);
+
+            let keys: Vec<u32> = items.iter().map(|(k, _)| *k).collect();
+            for key in keys {
+                map.remove(&key).unwrap();
+            }
+            assert!(map.is_empty());
+            assert_eq!(0, map.header.root_offset);
+            assert_eq!(0, map.iter().count());
+        }
+    }
+
+    #[test]
+    fn test_btree_map_sequential() {
+        let storage = mock_storage(2 * 1024 * 1024);
+        let mut map = StorageBTreeMap::<u32, u64>::create(&storage).unwrap();
+
+        for key in 0..5_000 {
+            map.insert(key, key as u64).unwrap();
+        }
+        // 2 levels are enough for holding 5,000 entries in 4KB nodes.
+        assert_eq!(2, check_node(&map, map.header.root_offset, true));
+        assert_eq!(Some(4_999), map.find(&4_999).unwrap());
+        assert_eq!(None, map.find(&5_000).unwrap());
+    }
+
+    #[test]
+    fn test_btree_map_range() {
+        let storage = mock_storage(1024 * 1024);
+        let mut map = StorageBTreeMap::<u32, u64>::create_with_order(&storage, 4).unwrap();
+        for key in (0..100).rev() {
+            map.insert(key * 2, key as u64).unwrap();
+        }
+
+        let keys: Vec<u32> = map.range(10..20).map(|i| i.unwrap().0).collect();
+        assert_eq!(keys, [10, 12, 14, 16, 18]);
+
+        let keys: Vec<u32> = map.range(11..=20).map(|i| i.unwrap().0).collect();
+        assert_eq!(keys, [12, 14, 16, 18, 20]);
+
+        let keys: Vec<u32> = map
+            .range((Bound::Excluded(190), Bound::Unbounded))
+            .map(|i| i.unwrap().0)
+            .collect();
+        assert_eq!(keys, [192, 194, 196, 198]);
+
+        let keys: Vec<u32> = map.range(..5).map(|i| i.unwrap().0).collect();
+        assert_eq!(keys, [0, 2, 4]);
+
+        assert_eq!(0, map.range(200..).count());
+        assert_eq!(100, (&map).into_iter().count());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::storage::codec::{Codec, CodecError};
+use crate::storage::Offset;
+use alloc::vec::Vec;
+
+/// A node of the B-Tree.
+///
+/// A node of a tree with order `m` is packed as:
+/// `len: u16 | keys: [K; m - 1] | values: [V; m - 1] | children: [Offset; m]`.
+/// The unused slots are filled with zero and the leaf nodes have no children.
+pub(super) struct Node<K: Codec + Ord, V: Codec> {
+    pub keys: Vec<K>,
+    pub values: Vec<V>,
+    pub children: Vec<Offset>,
+}
+
+impl<K: Codec + Ord, V: Codec> Node<K, V> {
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns the size of the packed node for a tree with the given order.
+    pub fn packed_len(order: u16) -> usize {
+        let order = order as usize;
+        u16::PACKED_LEN + (order - 1) * (K::PACKED_LEN + V::PACKED_LEN) + order * Offset::PACKED_LEN
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    pub fn to_bytes(&self, order: u16) -> Vec<u8> {
+        let slots = order as usize - 1;
+        let mut bytes = Vec::with_capacity(Self::packed_len(order));
+        bytes.extend_from_slice(&(self.keys.len() as u16).to_bytes());
+        for key in &self.keys {
+            bytes.extend_from_slice(&key.to_bytes());
+        }
+        bytes.resize(bytes.len() + (slots - self.keys.len()) * K::PACKED_LEN, 0);
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_bytes());
+        }
+        bytes.resize(bytes.len() + (slots - self.values.len()) * V::PACKED_LEN, 0);
+        for child in &self.children {
+            bytes.extend_from_slice(&child.to_bytes());
+        }
+        bytes.resize(Self::packed_len(order), 0);
+        bytes
+    }
+
+    pub fn try_from_bytes(bytes: &[u8], order: u16) -> Result<Self, CodecError> {
+        if bytes.len() != Self::packed_len(order) {
+            return Err(CodecError::InvalidLength);
+        }
+        let slots = order as usize - 1;
+        let len = u16::try_from_bytes(&bytes[..u16::PACKED_LEN])? as usize;
+        if len > slots {
+            return Err(CodecError::InvalidData);
+        }
+
+        let keys_offset = u16::PACKED_LEN;
+        let values_offset = keys_offset + slots * K::PACKED_LEN;
+        let children_offset = values_offset + slots * V::PACKED_LEN;
+
+        let keys = (0..len)
+            .map(|i| K::try_from_bytes(&bytes[keys_offset + i * K::PACKED_LEN..][..K::PACKED_LEN]))
+            .collect::<Result<_, _>>()?;
+        let values = (0..len)
+            .map(|i| {
+                V::try_from_bytes(&bytes[values_offset + i * V::PACKED_LEN..][..V::PACKED_LEN])
+            })
+            .collect::<Result<_, _>>()?;
+
+        // A leaf node has no child. Otherwise the first child is never zero.
+        let child_at = |i: usize| {
+            Offset::try_from_bytes(
+                &bytes[children_offset + i * Offset::PACKED_LEN..][..Offset::PACKED_LEN],
+            )
+        };
+        let children = if child_at(0)? == 0 {
+            Vec::new()
+        } else {
+            (0..=len).map(child_at).collect::<Result<_, _>>()?
+        };
+
+        Ok(Self {
+            keys,
+            values,
+            children,
+        })
+    }
+}
@@ -0,0 +1,327 @@
+//! Page cache
+//!
+//! The page cache sits between the storage and the host. The storage file is divided into
+//! fixed-size pages. Reading a page is a single host call, and the next reads and writes of
+//! that page don't call the host anymore. Reads that span several pages, which are not all
+//! cached, are passed to the host in a single call.
+//!
+//! The written pages are kept dirty in memory until they are flushed. Flushing writes the
+//! consecutive dirty pages in a single host call. When the cache is full, the least recently
+//! used page is evicted, and it is written into the storage file if it is dirty.
+//!
+
+use crate::storage::Offset;
+use alloc::vec::Vec;
+use kelk_env::{HostError, StorageAPI};
+
+/// The configuration of the page cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// The size of the pages in bytes.
+    pub page_size: u32,
+    /// The maximum number of cached pages. Zero disables the cache.
+    pub capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            page_size: 256,
+            capacity: 64,
+        }
+    }
+}
+
+struct Page {
+    index: u32,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+/// `PageCache` keeps the recently used pages, from the least to the most recently used one.
+pub(crate) struct PageCache {
+    config: CacheConfig,
+    pages: Vec<Page>,
+}
+
+impl PageCache {
+    /// Creates an empty page cache.
+    pub fn new(config: CacheConfig) -> Self {
+        PageCache {
+            config,
+            pages: Vec::new(),
+        }
+    }
+
+    /// Returns the configuration of the cache.
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    /// Reads `length` bytes at the given `offset` through the cache.
+    /// If the bytes span several pages and some of them are not cached, they are read
+    /// in a single host call, without caching the pages.
+    pub fn read(
+        &mut self,
+        api: &dyn StorageAPI,
+        offset: Offset,
+        length: u32,
+    ) -> Result<Vec<u8>, HostError> {
+        if self.config.capacity == 0 {
+            return api.read(offset, length);
+        }
+        let parts = self.split(offset, length as usize);
+        if parts.len() > 1 && parts.iter().any(|(index, _, _)| !self.is_cached(*index)) {
+            return self.read_through(api, offset, length, &parts);
+        }
+        let mut data = Vec::with_capacity(length as usize);
+        for (index, from, to) in parts {
+            match self.page(api, index)? {
+                Some(page) => data.extend_from_slice(&page.data[from..to]),
+                // The page can't be read entirely, so only the requested bytes are read.
+                None => {
+                    let page_offset = self.page_offset(index, from);
+                    data.extend(api.read(page_offset, (to - from) as u32)?);
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Writes the `data` at the given `offset` through the cache.
+    /// The written pages are dirty until they are flushed.
+    pub fn write(
+        &mut self,
+        api: &dyn StorageAPI,
+        offset: Offset,
+        data: &[u8],
+    ) -> Result<(), HostError> {
+        if self.config.capacity == 0 {
+            return api.write(offset, data);
+        }
+        let mut written = 0;
+        for (index, from, to) in self.split(offset, data.len()) {
+            let part = &data[written..written + to - from];
+            match self.page(api, index)? {
+                Some(page) => {
+                    page.data[from..to].copy_from_slice(part);
+                    page.dirty = true;
+                }
+                None => api.write(self.page_offset(index, from), part)?,
+            }
+            written += to - from;
+        }
+        Ok(())
+    }
+
+    /// Writes the dirty pages into the storage file.
+    pub fn flush(&mut self, api: &dyn StorageAPI) -> Result<(), HostError> {
+        let mut dirty: Vec<&mut Page> = self.pages.iter_mut().filter(|p| p.dirty).collect();
+        dirty.sort_by_key(|page| page.index);
+
+        // Writing the consecutive pages at once
+        let mut start = 0;
+        while start < dirty.len() {
+            let mut end = start + 1;
+            while end < dirty.len() && dirty[end].index == dirty[end - 1].index + 1 {
+                end += 1;
+            }
+            let mut data = Vec::with_capacity((end - start) * self.config.page_size as usize);
+            for page in &dirty[start..end] {
+                data.extend_from_slice(&page.data);
+            }
+            let offset = dirty[start].index * self.config.page_size;
+            api.write(offset, &data)?;
+            for page in &mut dirty[start..end] {
+                page.dirty = false;
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    // Splits the range into the pages. It returns the page index and the range inside the page.
+    fn split(&self, offset: Offset, length: usize) -> Vec<(u32, usize, usize)> {
+        let page_size = self.config.page_size as u64;
+        let mut parts = Vec::new();
+        let mut pos = offset as u64;
+        let end = offset as u64 + length as u64;
+        while pos < end {
+            let index = pos / page_size;
+            let from = pos - index * page_size;
+            let to = page_size.min(end - index * page_size);
+            parts.push((index as u32, from as usize, to as usize));
+            pos = index * page_size + to;
+        }
+        parts
+    }
+
+    fn page_offset(&self, index: u32, from: usize) -> Offset {
+        index * self.config.page_size + from as Offset
+    }
+
+    fn is_cached(&self, index: u32) -> bool {
+        self.pages.iter().any(|p| p.index == index)
+    }
+
+    // Reads the bytes from the storage file and patches them by the cached pages,
+    // because the dirty pages are not written into the storage file yet.
+    fn read_through(
+        &self,
+        api: &dyn StorageAPI,
+        offset: Offset,
+        length: u32,
+        parts: &[(u32, usize, usize)],
+    ) -> Result<Vec<u8>, HostError> {
+        let mut data = api.read(offset, length)?;
+        let mut pos = 0;
+        for (index, from, to) in parts {
+            if let Some(page) = self.pages.iter().find(|p| p.index == *index) {
+                data[pos..pos + to - from].copy_from_slice(&page.data[*from..*to]);
+            }
+            pos += to - from;
+        }
+        Ok(data)
+    }
+
+    // Returns the page and marks it as the most recently used one.
+    // If the page is not cached, it is read from the storage file.
+    // It returns `None` if the page can't be read entirely, like the last page of the file.
+    fn page(&mut self, api: &dyn StorageAPI, index: u32) -> Result<Option<&mut Page>, HostError> {
+        match self.pages.iter().position(|p| p.index == index) {
+            Some(pos) => {
+                let page = self.pages.remove(pos);
+                self.pages.push(page);
+            }
+            None => {
+                let page_offset = self.page_offset(index, 0);
+                let data = match api.read(page_offset, self.config.page_size) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        // Only the last page of the file can be incomplete.
+                        return match api.size()? {
+                            size if page_offset as u64 + self.config.page_size as u64
+                                > size as u64 =>
+                            {
+                                Ok(None)
+                            }
+                            _ => Err(err),
+                        };
+                    }
+                };
+                if self.pages.len() >= self.config.capacity {
+                    let evicted = self.pages.remove(0);
+                    if evicted.dirty {
+                        api.write(evicted.index * self.config.page_size, &evicted.data)?;
+                    }
+                }
+                self.pages.push(Page {
+                    index,
+                    data,
+                    dirty: false,
+                });
+            }
+        }
+        Ok(self.pages.last_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::MockStorage;
+
+    #[test]
+    fn test_cache() {
+        let api = MockStorage::new(1000);
+        let mut cache = PageCache::new(CacheConfig {
+            page_size: 64,
+            capacity: 2,
+        });
+
+        cache.write(&api, 60, &[1; 10]).unwrap();
+        assert_eq!(api.read(60, 10).unwrap(), [0; 10]);
+        assert_eq!(
+            cache.read(&api, 58, 14).unwrap(),
+            [0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0]
+        );
+
+        // Evicting the first page
+        cache.read(&api, 128, 8).unwrap();
+        assert_eq!(api.read(60, 10).unwrap(), [1, 1, 1, 1, 0, 0, 0, 0, 0, 0]);
+
+        cache.flush(&api).unwrap();
+        assert_eq!(api.read(60, 10).unwrap(), [1; 10]);
+
+        // The last page is not complete
+        cache.write(&api, 990, &[2; 10]).unwrap();
+        assert_eq!(api.read(990, 10).unwrap(), [2; 10]);
+        assert_eq!(cache.read(&api, 990, 10).unwrap(), [2; 10]);
+        assert!(cache.write(&api, 995, &[2; 10]).is_err());
+    }
+
+    #[test]
+    fn test_host_calls() {
+        let api = MockStorage::new(1024);
+        let mut cache = PageCache::new(CacheConfig::default());
+
+        for i in 0..100 {
+            cache.write(&api, i * 4, &i.to_be_bytes()).unwrap();
+            cache.read(&api, 0, 4).unwrap();
+        }
+        cache.flush(&api).unwrap();
+        assert_eq!(api.read_calls(), 2);
+        assert_eq!(api.write_calls(), 1);
+    }
+
+    #[test]
+    fn test_read_through() {
+        let api = MockStorage::new(4096);
+        let mut cache = PageCache::new(CacheConfig::default());
+
+        cache.write(&api, 300, &[1; 4]).unwrap();
+        let reads = api.read_calls();
+        let data = cache.read(&api, 0, 2048).unwrap();
+        assert_eq!(api.read_calls(), reads + 1);
+        assert_eq!(data[300..304], [1; 4]);
+        assert_eq!(data[296..300], [0; 4]);
+
+        // The cached pages are read without calling the host
+        cache.write(&api, 600, &[2; 4]).unwrap();
+        let reads = api.read_calls();
+        let data = cache.read(&api, 300, 304).unwrap();
+        assert_eq!(api.read_calls(), reads);
+        assert_eq!(data[..4], [1; 4]);
+        assert_eq!(data[300..], [2; 4]);
+    }
+
+    #[test]
+    fn test_host_error() {
+        struct FailingStorage;
+
+        impl StorageAPI for FailingStorage {
+            fn read(&self, _offset: u32, _length: u32) -> Result<Vec<u8>, HostError> {
+                Err(HostError { code: -2 })
+            }
+            fn write(&self, _offset: u32, _data: &[u8]) -> Result<(), HostError> {
+                Ok(())
+            }
+            fn size(&self) -> Result<u32, HostError> {
+                Ok(1024)
+            }
+            fn grow(&self, _additional: u32) -> Result<u32, HostError> {
+                Ok(1024)
+            }
+            fn as_any(&mut self) -> &mut dyn core::any::Any {
+                self
+            }
+        }
+
+        let mut cache = PageCache::new(CacheConfig::default());
+        assert_eq!(cache.read(&FailingStorage, 0, 4).unwrap_err().code, -2);
+        assert_eq!(
+            cache.write(&FailingStorage, 0, &[1; 4]).unwrap_err().code,
+            -2
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Storage Cell
+//!
+//! Storage Cell keeps a single value inside the storage file. Each access reads or writes the
+//! value directly, therefore it never gets out of sync with the storage file.
+//!
+//! ```ignore
+//! let total_supply = storage.root::<StorageCell<i64>>("total_supply")?;
+//! total_supply.update(|supply| supply + amount)?;
+//! ```
+//!
+
+use crate::storage::codec::{Codec, CodecError};
+use crate::storage::error::Error;
+use crate::storage::verify::{Verifier, Verify};
+use crate::storage::{Offset, Storage};
+use core::marker::PhantomData;
+use core::result::Result;
+
+/// The instance of Storage Cell
+pub struct StorageCell<'a, T: Codec> {
+    storage: &'a Storage,
+    offset: Offset,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, T: Codec> StorageCell<'a, T> {
+    /// Creates a new instance of `StorageCell`.
+    /// The value is filled by zeros.
+    pub fn create(storage: &'a Storage) -> Result<Self, Error> {
+        let offset = storage.allocate(T::PACKED_LEN)?;
+        storage.write_bytes(offset, &alloc::vec![0; T::PACKED_LEN])?;
+        Ok(Self::load(storage, offset))
+    }
+
+    /// Creates a new instance of `StorageCell` with the given value.
+    pub fn create_with(storage: &'a Storage, value: &T) -> Result<Self, Error> {
+        let offset = storage.allocate(T::PACKED_LEN)?;
+        storage.write(offset, value)?;
+        Ok(Self::load(storage, offset))
+    }
+
+    /// Loads the `StorageCell` at the given offset.
+    pub fn load(storage: &'a Storage, offset: Offset) -> Self {
+        StorageCell {
+            storage,
+            offset,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Migrates the value at the given offset, that is written by the given older version
+    /// of `T`. The value is decoded by `Codec::try_from_version`. If the packed length of `T`
+    /// is changed, the value is moved into a new block, and the old block is freed.
+    pub fn migrate(storage: &'a Storage, offset: Offset, version: u8) -> Result<Self, Error> {
+        let old_len = T::packed_len_of(version).ok_or(CodecError::InvalidVersion(version))?;
+        let bytes = storage.read_bytes(offset, old_len as u32)?;
+        let value = T::try_from_version(version, &bytes)?;
+        if old_len == T::PACKED_LEN {
+            storage.write(offset, &value)?;
+            return Ok(Self::load(storage, offset));
+        }
+        let cell = Self::create_with(storage, &value)?;
+        storage.free(offset, old_len)?;
+        Ok(cell)
+    }
+
+    /// Returns the offset of `StorageCell` in the storage file.
+    pub fn offset(&self) -> Offset {
+        self.offset
+    }
+
+    /// Reads the value from the storage file.
+    pub fn get(&self) -> Result<T, Error> {
+        self.storage.read(self.offset)
+    }
+
+    /// Writes the value into the storage file.
+    pub fn set(&self, value: &T) -> Result<(), Error> {
+        self.storage.write(self.offset, value)
+    }
+
+    /// Writes the value into the storage file and returns the old value.
+    pub fn replace(&self, value: &T) -> Result<T, Error> {
+        let old = self.get()?;
+        self.set(value)?;
+        Ok(old)
+    }
+
+    /// Updates the value by `f` and returns the new value.
+    pub fn update<F: FnOnce(T) -> T>(&self, f: F) -> Result<T, Error> {
+        let value = f(self.get()?);
+        self.set(&value)?;
+        Ok(value)
+    }
+
+    /// Takes the value and leaves `Default::default()` in its place.
+    pub fn take(&self) -> Result<T, Error>
+    where
+        T: Default,
+    {
+        self.replace(&T::default())
+    }
+}
+
+impl<'a, T: Codec> Verify for StorageCell<'a, T> {
+    fn verify(&self, verifier: &mut Verifier) {
+        if verifier.block(self.offset, T::PACKED_LEN) {
+            verifier.read::<T>(self.offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::mock_storage;
+
+    #[test]
+    fn test_cell() {
+        let storage = mock_storage(1024 * 1024);
+        let cell = StorageCell::<i64>::create(&storage).unwrap();
+        assert_eq!(cell.get().unwrap(), 0);
+
+        cell.set(&10).unwrap();
+        assert_eq!(cell.update(|v| v * 2).unwrap(), 20);
+        assert_eq!(cell.replace(&5).unwrap(), 20);
+        assert_eq!(cell.take().unwrap(), 5);
+        assert_eq!(cell.get().unwrap(), 0);
+
+        let cell = StorageCell::<(u8, bool)>::create_with(&storage, &(1, true)).unwrap();
+        let cell = StorageCell::<(u8, bool)>::load(&storage, cell.offset());
+        assert_eq!(cell.get().unwrap(), (1, true));
+    }
+}
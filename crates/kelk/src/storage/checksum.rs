@@ -0,0 +1,172 @@
+//! Block checksums
+//!
+//! If the storage file is created with `FEATURE_BLOCK_CHECKSUMS`, the CRC-32 of the allocated
+//! blocks are kept in an index. The index is a B-Tree map from the end of the blocks to their
+//! start and checksum. Keying by the end of the blocks makes it possible to find the block
+//! that contains an offset by a forward range query.
+//!
+//! The written ranges are tracked in memory, and the checksums of the touched blocks are
+//! updated when the storage is flushed. The blocks of the index itself are not tracked.
+//!
+
+use crate::storage::btree::StorageBTreeMap;
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::header::FEATURE_BLOCK_CHECKSUMS;
+use crate::storage::verify::{Problem, Verifier};
+use crate::storage::{crc32, Offset, Storage};
+use crate::Codec;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+
+/// The order of the index nodes. The nodes are kept small, because they are rewritten
+/// on each flush.
+const INDEX_ORDER: u16 = 16;
+
+/// The start and the checksum of an allocated block.
+#[derive(Codec, Clone, Copy, Debug)]
+pub(crate) struct BlockInfo {
+    pub start: Offset,
+    pub crc: u32,
+}
+
+/// The index of the block checksums, keyed by the end of the blocks.
+pub(crate) type Index<'a> = StorageBTreeMap<'a, Offset, BlockInfo>;
+
+/// `Tracker` keeps the written ranges that their checksums are not updated yet.
+#[derive(Default)]
+pub(crate) struct Tracker {
+    suspended: Cell<bool>,
+    ranges: RefCell<Vec<(Offset, Offset)>>,
+}
+
+impl Storage {
+    /// Creates the index of the block checksums and returns its offset.
+    pub(crate) fn create_checksums(&self) -> Result<Offset, Error> {
+        self.untracked(|| Ok(Index::create_with_order(self, INDEX_ORDER)?.offset()))
+    }
+
+    /// Keeps the written range, so the checksums of its blocks are updated on flush.
+    pub(crate) fn track_write(&self, offset: Offset, length: usize) {
+        if self.is_tracking() && offset >= self.data_offset() {
+            let end = offset.saturating_add(length as Offset);
+            self.tracker.ranges.borrow_mut().push((offset, end));
+        }
+    }
+
+    /// Adds the allocated block into the index.
+    pub(crate) fn track_allocate(&self, offset: Offset, length: u32) -> Result<(), Error> {
+        if !self.is_tracking() {
+            return Ok(());
+        }
+        let info = BlockInfo {
+            start: offset,
+            crc: 0,
+        };
+        self.untracked(|| self.checksums()?.insert(offset + length, info))?;
+        // The checksum is computed on flush.
+        self.track_write(offset, length as usize);
+        Ok(())
+    }
+
+    /// Removes the freed block from the index.
+    pub(crate) fn track_free(&self, offset: Offset, length: u32) -> Result<(), Error> {
+        if !self.is_tracking() {
+            return Ok(());
+        }
+        self.untracked(|| self.checksums()?.remove(&(offset + length)))?;
+        Ok(())
+    }
+
+    /// Updates the checksums of the written blocks. The checksums are not updated inside
+    /// a transaction, because the writes can be rolled back.
+    pub(crate) fn update_checksums(&self) -> Result<(), Error> {
+        if !self.has_checksums() || self.in_transaction() {
+            return Ok(());
+        }
+        let mut ranges = core::mem::take(&mut *self.tracker.ranges.borrow_mut());
+        if ranges.is_empty() {
+            return Ok(());
+        }
+        ranges.sort_unstable();
+
+        self.untracked(|| {
+            let mut index = self.checksums()?;
+            let mut blocks = BTreeMap::new();
+            let mut pos = 0;
+            for (start, end) in ranges {
+                // Skipping the ranges that are covered by the previous ones
+                let start = start.max(pos);
+                if start >= end {
+                    continue;
+                }
+                for entry in index.range(start + 1..) {
+                    let (block_end, info) = entry?;
+                    if info.start >= end {
+                        break;
+                    }
+                    blocks.insert(block_end, info.start);
+                    pos = pos.max(block_end);
+                }
+                pos = pos.max(end);
+            }
+            for (end, start) in blocks {
+                let crc = crc32(&self.read_bytes(start, end - start)?);
+                index.insert(end, BlockInfo { start, crc })?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Checks the index and the checksums of the blocks.
+    pub(crate) fn verify_checksums(&self, verifier: &mut Verifier) -> Result<(), Error> {
+        if !self.has_checksums() {
+            return Ok(());
+        }
+        self.update_checksums()?;
+        let index = match self.checksums() {
+            Ok(index) => index,
+            Err(_) => {
+                verifier.problem(Problem::Corrupted(self.header.checksums_offset));
+                return Ok(());
+            }
+        };
+        verifier.check(&index);
+        if self.in_transaction() {
+            return Ok(());
+        }
+        for entry in index.iter() {
+            match entry {
+                Ok((end, info)) => {
+                    let crc = crc32(&self.read_bytes(info.start, end - info.start)?);
+                    if crc != info.crc {
+                        verifier.problem(Problem::ChecksumMismatch(info.start));
+                    }
+                }
+                Err(_) => verifier.problem(Problem::Corrupted(index.offset())),
+            }
+        }
+        Ok(())
+    }
+
+    fn has_checksums(&self) -> bool {
+        self.has_feature(FEATURE_BLOCK_CHECKSUMS)
+    }
+
+    fn is_tracking(&self) -> bool {
+        self.has_checksums() && !self.tracker.suspended.get()
+    }
+
+    fn checksums(&self) -> Result<Index<'_>, Error> {
+        Index::load(self, self.header.checksums_offset)
+    }
+
+    // Runs `f` without tracking the writes and the allocations.
+    fn untracked<R>(&self, f: impl FnOnce() -> R) -> R {
+        let suspended = self.tracker.suspended.replace(true);
+        let res = f();
+        self.tracker.suspended.set(suspended);
+        res
+    }
+}
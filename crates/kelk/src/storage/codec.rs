@@ -3,20 +3,70 @@
 //! In Kelk all multi-byte values are encoded in network byte order
 //! (that is, most significant byte first, also known as "big-endian").
 //!
+//! Types with a fixed size implement `Codec`. Types with a variable size, like `String` or
+//! `Vec<T>`, implement `DynCodec` and are encoded with a length prefix.
+//!
 
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::fmt::Debug;
+
+/// The error returned when decoding invalid bytes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CodecError {
+    /// The number of bytes doesn't match the packed length
+    InvalidLength,
+
+    /// The enum tag is not defined
+    InvalidTag(u8),
+
+    /// The bytes don't represent a valid value
+    InvalidData,
+
+    /// The layout version is not supported
+    InvalidVersion(u8),
+}
 
 /// `Codec` trait defines functions to serialize types as bytes and deserialize from bytes
 /// in big-endian (network) byte order.
-pub trait Codec {
+pub trait Codec: Sized {
     /// Represent the size of packed bytes in big-endian (network) byte order.
     const PACKED_LEN: usize;
 
+    /// The version of the packed layout.
+    /// It should be increased when the layout changes, for example when a field is added.
+    const VERSION: u8 = 1;
+
     /// Returns the memory representation of this type as a byte array in big-endian (network) byte order.
     fn to_bytes(&self) -> Vec<u8>;
 
     /// Creates a native endian value from its representation as a byte array in big-endian (network) byte order.
-    fn from_bytes(bytes: &[u8]) -> Self;
+    /// Returns an error if the bytes don't represent a valid value.
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError>;
+
+    /// Creates a native endian value from its representation as a byte array in big-endian (network) byte order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bytes don't represent a valid value. Use `try_from_bytes` for untrusted data.
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).expect("invalid data")
+    }
+
+    /// Returns the size of packed bytes for the given version of the layout,
+    /// or None if the version is not supported.
+    fn packed_len_of(version: u8) -> Option<usize> {
+        (version == Self::VERSION).then_some(Self::PACKED_LEN)
+    }
+
+    /// Creates a value from its representation in the given version of the layout.
+    /// Older versions are migrated to the current version.
+    fn try_from_version(version: u8, bytes: &[u8]) -> Result<Self, CodecError> {
+        if version != Self::VERSION {
+            return Err(CodecError::InvalidVersion(version));
+        }
+        Self::try_from_bytes(bytes)
+    }
 }
 
 macro_rules! impl_codec_for_integer {
@@ -30,29 +80,38 @@ macro_rules! impl_codec_for_integer {
             }
 
             #[inline]
-            fn from_bytes(bytes: &[u8]) -> Self {
-                let arr = bytes.try_into().expect("invalid data");
-                Self::from_be_bytes(arr)
+            fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+                let arr = bytes.try_into().map_err(|_| CodecError::InvalidLength)?;
+                Ok(Self::from_be_bytes(arr))
             }
         }
     };
 }
 
-macro_rules! impl_codec_for_array {
-    ($type:ty, $size:expr) => {
-        impl Codec for $type {
-            const PACKED_LEN: usize = $size;
+macro_rules! impl_codec_for_tuple {
+    ($($name:ident $index:tt),+) => {
+        impl<$($name: Codec),+> Codec for ($($name,)+) {
+            const PACKED_LEN: usize = 0 $(+ $name::PACKED_LEN)+;
 
             #[inline]
             fn to_bytes(&self) -> Vec<u8> {
-                self.to_vec()
+                let mut bytes = Vec::with_capacity(Self::PACKED_LEN);
+                $(bytes.extend_from_slice(&self.$index.to_bytes());)+
+                bytes
             }
 
             #[inline]
-            fn from_bytes(bytes: &[u8]) -> Self {
-                let mut arr = [0; Self::PACKED_LEN];
-                arr.copy_from_slice(bytes);
-                arr
+            #[allow(unused_assignments)]
+            fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+                if bytes.len() != Self::PACKED_LEN {
+                    return Err(CodecError::InvalidLength);
+                }
+                let mut offset = 0;
+                Ok(($({
+                    let item = $name::try_from_bytes(&bytes[offset..offset + $name::PACKED_LEN])?;
+                    offset += $name::PACKED_LEN;
+                    item
+                },)+))
             }
         }
     };
@@ -69,38 +128,106 @@ impl_codec_for_integer!(i64, 8);
 impl_codec_for_integer!(u128, 16);
 impl_codec_for_integer!(i128, 16);
 
-impl_codec_for_array!([u8; 1], 1);
-impl_codec_for_array!([u8; 2], 2);
-impl_codec_for_array!([u8; 3], 3);
-impl_codec_for_array!([u8; 4], 4);
-impl_codec_for_array!([u8; 5], 5);
-impl_codec_for_array!([u8; 6], 6);
-impl_codec_for_array!([u8; 7], 7);
-impl_codec_for_array!([u8; 8], 8);
-impl_codec_for_array!([u8; 9], 9);
-impl_codec_for_array!([u8; 10], 10);
-impl_codec_for_array!([u8; 11], 11);
-impl_codec_for_array!([u8; 12], 12);
-impl_codec_for_array!([u8; 13], 13);
-impl_codec_for_array!([u8; 14], 14);
-impl_codec_for_array!([u8; 15], 15);
-impl_codec_for_array!([u8; 16], 16);
-impl_codec_for_array!([u8; 17], 17);
-impl_codec_for_array!([u8; 18], 18);
-impl_codec_for_array!([u8; 19], 19);
-impl_codec_for_array!([u8; 20], 20);
-impl_codec_for_array!([u8; 21], 21);
-impl_codec_for_array!([u8; 22], 22);
-impl_codec_for_array!([u8; 23], 23);
-impl_codec_for_array!([u8; 24], 24);
-impl_codec_for_array!([u8; 25], 25);
-impl_codec_for_array!([u8; 26], 26);
-impl_codec_for_array!([u8; 27], 27);
-impl_codec_for_array!([u8; 28], 28);
-impl_codec_for_array!([u8; 29], 29);
-impl_codec_for_array!([u8; 30], 30);
-impl_codec_for_array!([u8; 31], 31);
-impl_codec_for_array!([u8; 32], 32);
+impl_codec_for_tuple!(A 0);
+impl_codec_for_tuple!(A 0, B 1);
+impl_codec_for_tuple!(A 0, B 1, C 2);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+impl_codec_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+impl<T: Codec, const N: usize> Codec for [T; N] {
+    const PACKED_LEN: usize = T::PACKED_LEN * N;
+
+    #[inline]
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::PACKED_LEN);
+        for item in self {
+            bytes.extend_from_slice(&item.to_bytes());
+        }
+        bytes
+    }
+
+    #[inline]
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        if bytes.len() != Self::PACKED_LEN {
+            return Err(CodecError::InvalidLength);
+        }
+        let mut items = Vec::with_capacity(N);
+        for i in 0..N {
+            let offset = i * T::PACKED_LEN;
+            items.push(T::try_from_bytes(&bytes[offset..offset + T::PACKED_LEN])?);
+        }
+        items.try_into().map_err(|_| CodecError::InvalidLength)
+    }
+}
+
+/// `Option<T>` is encoded as a presence byte followed by `T`.
+/// `None` is encoded as zeros, so it has the same size as `Some`.
+impl<T: Codec> Codec for Option<T> {
+    const PACKED_LEN: usize = 1 + T::PACKED_LEN;
+
+    #[inline]
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Some(value) => {
+                let mut bytes = Vec::with_capacity(Self::PACKED_LEN);
+                bytes.push(1);
+                bytes.extend_from_slice(&value.to_bytes());
+                bytes
+            }
+            None => [0; 1].repeat(Self::PACKED_LEN),
+        }
+    }
+
+    #[inline]
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        if bytes.len() != Self::PACKED_LEN {
+            return Err(CodecError::InvalidLength);
+        }
+        match bool::try_from_bytes(&bytes[..1])? {
+            true => Ok(Some(T::try_from_bytes(&bytes[1..])?)),
+            false => Ok(None),
+        }
+    }
+}
+
+impl Codec for () {
+    const PACKED_LEN: usize = 0;
+
+    #[inline]
+    fn to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    #[inline]
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        match bytes.is_empty() {
+            true => Ok(()),
+            false => Err(CodecError::InvalidLength),
+        }
+    }
+}
+
+/// `char` is encoded as its Unicode scalar value in 4 bytes.
+impl Codec for char {
+    const PACKED_LEN: usize = 4;
+
+    #[inline]
+    fn to_bytes(&self) -> Vec<u8> {
+        (*self as u32).to_bytes()
+    }
+
+    #[inline]
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        char::from_u32(u32::try_from_bytes(bytes)?).ok_or(CodecError::InvalidData)
+    }
+}
 
 impl Codec for bool {
     const PACKED_LEN: usize = 1;
@@ -114,15 +241,211 @@ impl Codec for bool {
     }
 
     #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        !matches!(bytes[0], 0)
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        match bytes {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [_] => Err(CodecError::InvalidData),
+            _ => Err(CodecError::InvalidLength),
+        }
+    }
+}
+
+/// Encodes the string as UTF-8 in exactly `len` bytes, padded by zeros.
+/// The string is truncated at the last char boundary that fits in `len` bytes.
+///
+/// It is used by `#[codec(fixed_str = N)]` attribute.
+pub fn encode_fixed_str(str: &str, len: usize) -> Vec<u8> {
+    let mut end = str.len().min(len);
+    while !str.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut bytes = Vec::with_capacity(len);
+    bytes.extend_from_slice(&str.as_bytes()[..end]);
+    bytes.resize(len, 0);
+    bytes
+}
+
+/// Decodes a string that is encoded by `encode_fixed_str`. The trailing zeros are removed.
+pub fn decode_fixed_str(bytes: &[u8]) -> Result<String, CodecError> {
+    let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |pos| pos + 1);
+    String::from_utf8(bytes[..end].to_vec()).map_err(|_| CodecError::InvalidData)
+}
+
+/// `DynCodec` trait defines functions to serialize variable-length types as bytes and
+/// deserialize from bytes in big-endian (network) byte order.
+///
+/// Sequences like `String` and `Vec<T>` are prefixed by their length as a `u32`,
+/// and `Option<T>` is prefixed by a presence byte.
+pub trait DynCodec: Sized {
+    /// Returns the size of packed bytes for this value.
+    fn packed_len(&self) -> usize;
+
+    /// Appends the packed bytes of this value to the given buffer.
+    fn encode(&self, bytes: &mut Vec<u8>);
+
+    /// Decodes a value from the beginning of the given buffer and advances it past the
+    /// decoded bytes.
+    fn decode(bytes: &mut &[u8]) -> Result<Self, CodecError>;
+
+    /// Returns the packed bytes of this value.
+    fn to_dyn_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.packed_len());
+        self.encode(&mut bytes);
+        bytes
+    }
+
+    /// Decodes a value from its packed bytes.
+    /// Returns an error if the bytes don't represent a valid value or some bytes are left.
+    fn from_dyn_bytes(mut bytes: &[u8]) -> Result<Self, CodecError> {
+        let value = Self::decode(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(CodecError::InvalidLength);
+        }
+        Ok(value)
+    }
+}
+
+// Splits the first `len` bytes from the buffer and advances it.
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], CodecError> {
+    if bytes.len() < len {
+        return Err(CodecError::InvalidLength);
+    }
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
+
+macro_rules! impl_dyn_codec_for_codec {
+    ($($type:ty),*) => {
+        $(
+            impl DynCodec for $type {
+                #[inline]
+                fn packed_len(&self) -> usize {
+                    <Self as Codec>::PACKED_LEN
+                }
+
+                #[inline]
+                fn encode(&self, bytes: &mut Vec<u8>) {
+                    bytes.extend_from_slice(&Codec::to_bytes(self));
+                }
+
+                #[inline]
+                fn decode(bytes: &mut &[u8]) -> Result<Self, CodecError> {
+                    <Self as Codec>::try_from_bytes(take(bytes, <Self as Codec>::PACKED_LEN)?)
+                }
+            }
+        )*
+    };
+}
+
+impl_dyn_codec_for_codec!(
+    u8,
+    i8,
+    u16,
+    i16,
+    u32,
+    i32,
+    u64,
+    i64,
+    u128,
+    i128,
+    bool,
+    char,
+    ()
+);
+
+impl<T: DynCodec, const N: usize> DynCodec for [T; N] {
+    fn packed_len(&self) -> usize {
+        self.iter().map(DynCodec::packed_len).sum()
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        for item in self {
+            item.encode(bytes);
+        }
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut items = Vec::with_capacity(N);
+        for _ in 0..N {
+            items.push(T::decode(bytes)?);
+        }
+        items.try_into().map_err(|_| CodecError::InvalidLength)
+    }
+}
+
+impl DynCodec for String {
+    fn packed_len(&self) -> usize {
+        u32::PACKED_LEN + self.len()
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        (self.len() as u32).encode(bytes);
+        bytes.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = u32::decode(bytes)? as usize;
+        String::from_utf8(take(bytes, len)?.to_vec()).map_err(|_| CodecError::InvalidData)
+    }
+}
+
+impl<T: DynCodec> DynCodec for Vec<T> {
+    fn packed_len(&self) -> usize {
+        u32::PACKED_LEN + self.iter().map(DynCodec::packed_len).sum::<usize>()
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        (self.len() as u32).encode(bytes);
+        for item in self {
+            item.encode(bytes);
+        }
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = u32::decode(bytes)? as usize;
+        // The length is not trusted for pre-allocating the vector.
+        let mut vec = Vec::with_capacity(len.min(bytes.len()));
+        for _ in 0..len {
+            vec.push(T::decode(bytes)?);
+        }
+        Ok(vec)
+    }
+}
+
+impl<T: DynCodec> DynCodec for Option<T> {
+    fn packed_len(&self) -> usize {
+        match self {
+            Some(value) => 1 + value.packed_len(),
+            None => 1,
+        }
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                bytes.push(1);
+                value.encode(bytes);
+            }
+            None => bytes.push(0),
+        }
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Result<Self, CodecError> {
+        match bool::decode(bytes)? {
+            true => Ok(Some(T::decode(bytes)?)),
+            false => Ok(None),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Codec;
+    use crate::{Codec, DynCodec};
+    use alloc::string::ToString;
+    use alloc::vec;
 
     #[test]
     fn codec_integer() {
@@ -130,6 +453,15 @@ mod tests {
         let v2 = 0xabcdefabcdef;
         assert_eq!(i32::from_bytes(&v1.to_bytes()), v1);
         assert_eq!(i64::from_bytes(&v2.to_bytes()), v2);
+        assert_eq!(i32::try_from_bytes(&[1, 2]), Err(CodecError::InvalidLength));
+    }
+
+    #[test]
+    fn codec_bool() {
+        assert_eq!(bool::try_from_bytes(&[0]), Ok(false));
+        assert_eq!(bool::try_from_bytes(&[1]), Ok(true));
+        assert_eq!(bool::try_from_bytes(&[2]), Err(CodecError::InvalidData));
+        assert_eq!(bool::try_from_bytes(&[]), Err(CodecError::InvalidLength));
     }
 
     #[test]
@@ -138,6 +470,53 @@ mod tests {
         let v2 = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
         assert_eq!(<[u8; 6]>::from_bytes(&v1.to_bytes()), v1);
         assert_eq!(<[u8; 12]>::from_bytes(&v2.to_bytes()), v2);
+
+        let v3: [u16; 3] = [1, 2, 0xabcd];
+        assert_eq!(v3.to_bytes(), [0, 1, 0, 2, 0xab, 0xcd]);
+        assert_eq!(<[u16; 3]>::from_bytes(&v3.to_bytes()), v3);
+        assert_eq!(<[u8; 64]>::PACKED_LEN, 64);
+        assert_eq!(<[(); 3]>::from_bytes(&[]), [(), (), ()]);
+        assert_eq!(
+            <[bool; 2]>::try_from_bytes(&[1, 2]),
+            Err(CodecError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn codec_tuple() {
+        let v1 = (1u8, -2i32, true);
+        assert_eq!(<(u8, i32, bool)>::PACKED_LEN, 6);
+        assert_eq!(<(u8, i32, bool)>::from_bytes(&v1.to_bytes()), v1);
+
+        type Tuple12 = (u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u16);
+        let v2: Tuple12 = (1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12);
+        assert_eq!(v2.to_bytes(), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 12]);
+        assert_eq!(Tuple12::from_bytes(&v2.to_bytes()), v2);
+    }
+
+    #[test]
+    fn codec_option() {
+        assert_eq!(<Option<u16>>::PACKED_LEN, 3);
+        assert_eq!(Some(0x0102u16).to_bytes(), [1, 1, 2]);
+        assert_eq!(None::<u16>.to_bytes(), [0, 0, 0]);
+        assert_eq!(<Option<u16>>::from_bytes(&[1, 1, 2]), Some(0x0102));
+        assert_eq!(<Option<u16>>::from_bytes(&[0, 0, 0]), None);
+        assert_eq!(
+            <Option<u16>>::try_from_bytes(&[2, 0, 0]),
+            Err(CodecError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn codec_char_and_unit() {
+        assert_eq!('a'.to_bytes(), [0, 0, 0, 0x61]);
+        assert_eq!(char::from_bytes(&'𝄞'.to_bytes()), '𝄞');
+        assert_eq!(
+            char::try_from_bytes(&[0, 0, 0xD8, 0]),
+            Err(CodecError::InvalidData)
+        );
+        assert!(().to_bytes().is_empty());
+        assert_eq!(<()>::try_from_bytes(&[0]), Err(CodecError::InvalidLength));
     }
 
     #[test]
@@ -155,5 +534,251 @@ mod tests {
 
         let bytes = foo.to_bytes();
         assert_eq!(foo, Foo::from_bytes(&bytes));
+        assert_eq!(
+            Foo::try_from_bytes(&bytes[1..]),
+            Err(CodecError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn codec_field_attributes() {
+        fn default_version() -> u8 {
+            3
+        }
+
+        #[derive(Codec, PartialEq, Eq, Debug)]
+        struct Record {
+            #[codec(le)]
+            id: u32,
+            #[codec(fixed_str = 8)]
+            name: String,
+            #[codec(skip)]
+            cached: u64,
+            #[codec(default = "default_version")]
+            version: u8,
+            #[codec(pad = 3)]
+            flag: bool,
+            #[codec(le, pad = 2)]
+            amount: i16,
+        }
+
+        assert_eq!(Record::PACKED_LEN, 4 + 8 + 1 + 3 + 2 + 2);
+        let record = Record {
+            id: 0x01020304,
+            name: "kelk".to_string(),
+            cached: 100,
+            version: 7,
+            flag: true,
+            amount: -2,
+        };
+        let bytes = record.to_bytes();
+        assert_eq!(
+            bytes,
+            [
+                4, 3, 2, 1, // id
+                b'k', b'e', b'l', b'k', 0, 0, 0, 0, // name
+                1, 0, 0, 0, // flag
+                0xfe, 0xff, 0, 0, // amount
+            ]
+        );
+        assert_eq!(
+            Record::from_bytes(&bytes),
+            Record {
+                cached: 0,
+                version: 3,
+                ..record
+            }
+        );
+
+        // Truncated at the char boundary
+        assert_eq!(encode_fixed_str("kelk-کلک", 6), b"kelk-\0");
+        assert_eq!(decode_fixed_str(&[0xd8, 0]), Err(CodecError::InvalidData));
+
+        #[derive(Codec, PartialEq, Eq, Debug)]
+        enum Event {
+            Renamed(#[codec(fixed_str = 4)] String),
+            Moved {
+                #[codec(skip)]
+                from: u16,
+                #[codec(le)]
+                to: u16,
+            },
+        }
+        assert_eq!(Event::PACKED_LEN, 5);
+        assert_eq!(Event::Moved { from: 1, to: 2 }.to_bytes(), [1, 2, 0, 0, 0]);
+        assert_eq!(
+            Event::from_bytes(&[1, 2, 0, 0, 0]),
+            Event::Moved { from: 0, to: 2 }
+        );
+        assert_eq!(
+            Event::from_bytes(&Event::Renamed("ab".to_string()).to_bytes()),
+            Event::Renamed("ab".to_string())
+        );
+    }
+
+    #[test]
+    fn codec_version() {
+        #[derive(Codec, PartialEq, Eq, Debug)]
+        struct AccountV1 {
+            balance: u32,
+        }
+
+        #[derive(Codec, PartialEq, Eq, Debug)]
+        struct AccountV2 {
+            balance: u64,
+        }
+
+        #[derive(Codec, PartialEq, Eq, Debug)]
+        #[codec(version = 3, migrate_from(v1 = "AccountV1", v2 = "AccountV2"))]
+        struct Account {
+            balance: u64,
+            frozen: bool,
+        }
+
+        impl Account {
+            fn migrate_from_v1(old: AccountV1) -> Self {
+                Self::migrate_from_v2(AccountV2 {
+                    balance: old.balance as u64,
+                })
+            }
+
+            fn migrate_from_v2(old: AccountV2) -> Self {
+                Self {
+                    balance: old.balance,
+                    frozen: false,
+                }
+            }
+        }
+
+        assert_eq!(AccountV1::VERSION, 1);
+        assert_eq!(Account::VERSION, 3);
+        assert_eq!(Account::packed_len_of(1), Some(4));
+        assert_eq!(Account::packed_len_of(2), Some(8));
+        assert_eq!(Account::packed_len_of(3), Some(9));
+        assert_eq!(Account::packed_len_of(4), None);
+
+        let expected = Account {
+            balance: 7,
+            frozen: false,
+        };
+        let v1 = AccountV1 { balance: 7 }.to_bytes();
+        let v2 = AccountV2 { balance: 7 }.to_bytes();
+        assert_eq!(Account::try_from_version(1, &v1), Ok(expected));
+        assert_eq!(
+            Account::try_from_version(2, &v2).unwrap().to_bytes(),
+            [0, 0, 0, 0, 0, 0, 0, 7, 0]
+        );
+        assert_eq!(
+            Account::try_from_version(0, &v1),
+            Err(CodecError::InvalidVersion(0))
+        );
+        assert_eq!(
+            AccountV1::try_from_version(2, &v2),
+            Err(CodecError::InvalidVersion(2))
+        );
+    }
+
+    #[test]
+    fn codec_enum() {
+        #[derive(Codec, PartialEq, Eq, Debug)]
+        #[repr(u8)]
+        enum Status {
+            Pending,
+            Paid(u32, u8),
+            #[codec(tag = 10)]
+            Shipped {
+                tracking: [u8; 4],
+            },
+            Cancelled = 20,
+            Refunded,
+        }
+
+        assert_eq!(Status::PACKED_LEN, 6);
+        assert_eq!(Status::Pending.to_bytes(), [0, 0, 0, 0, 0, 0]);
+        assert_eq!(Status::Paid(7, 8).to_bytes(), [1, 0, 0, 0, 7, 8]);
+        assert_eq!(
+            Status::Shipped { tracking: *b"abcd" }.to_bytes(),
+            [10, b'a', b'b', b'c', b'd', 0]
+        );
+        assert_eq!(Status::Cancelled.to_bytes(), [20, 0, 0, 0, 0, 0]);
+        assert_eq!(Status::Refunded.to_bytes(), [21, 0, 0, 0, 0, 0]);
+
+        for status in [
+            Status::Pending,
+            Status::Paid(1, 2),
+            Status::Shipped { tracking: [1; 4] },
+            Status::Cancelled,
+            Status::Refunded,
+        ] {
+            assert_eq!(Status::from_bytes(&status.to_bytes()), status);
+        }
+        assert_eq!(
+            Status::try_from_bytes(&[2, 0, 0, 0, 0, 0]),
+            Err(CodecError::InvalidTag(2))
+        );
+        assert_eq!(
+            Status::try_from_bytes(&[0, 0, 0, 0, 0]),
+            Err(CodecError::InvalidLength)
+        );
+
+        #[derive(Codec, PartialEq, Eq, Debug)]
+        enum Phase<T: Codec> {
+            Voting(T),
+            Closed,
+        }
+        assert_eq!(Phase::<u64>::PACKED_LEN, 9);
+        assert_eq!(
+            Phase::<u64>::from_bytes(&Phase::Voting(3u64).to_bytes()),
+            Phase::Voting(3)
+        );
+    }
+
+    #[test]
+    fn dyn_codec() {
+        let str = "kelk".to_string();
+        assert_eq!(str.to_dyn_bytes(), [0, 0, 0, 4, b'k', b'e', b'l', b'k']);
+        assert_eq!(String::from_dyn_bytes(&str.to_dyn_bytes()), Ok(str));
+        assert_eq!(
+            String::from_dyn_bytes(&[0, 0, 0, 4, b'k']),
+            Err(CodecError::InvalidLength)
+        );
+        assert_eq!(
+            String::from_dyn_bytes(&[0, 0, 0, 1, 0xFF]),
+            Err(CodecError::InvalidData)
+        );
+
+        let vec = vec![Some(1u16), None, Some(3)];
+        let bytes = vec.to_dyn_bytes();
+        assert_eq!(bytes, [0, 0, 0, 3, 1, 0, 1, 0, 1, 0, 3]);
+        assert_eq!(bytes.len(), vec.packed_len());
+        assert_eq!(Vec::<Option<u16>>::from_dyn_bytes(&bytes), Ok(vec));
+        assert_eq!(
+            Vec::<Option<u16>>::from_dyn_bytes(&[0, 0, 0, 1, 0, 0]),
+            Err(CodecError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn dyn_codec_struct() {
+        #[derive(DynCodec, PartialEq, Eq, Debug)]
+        struct Profile {
+            id: u32,
+            name: String,
+            tags: Vec<String>,
+            email: Option<String>,
+            key: [u8; 4],
+        }
+
+        let profile = Profile {
+            id: 1,
+            name: "alice".to_string(),
+            tags: vec!["admin".to_string(), "".to_string()],
+            email: None,
+            key: [1, 2, 3, 4],
+        };
+
+        let bytes = profile.to_dyn_bytes();
+        assert_eq!(bytes.len(), profile.packed_len());
+        assert_eq!(Ok(profile), Profile::from_dyn_bytes(&bytes));
     }
 }
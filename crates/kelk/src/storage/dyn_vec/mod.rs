@@ -0,0 +1,283 @@
+//! Storage Dynamic Vector
+//!
+//! Storage Dynamic Vector, is a Vector of variable-length values, like `String` or `Vec<T>`.
+//! Each value is encoded by `DynCodec` and kept in its own allocated space.
+//! The offset and length of the values are kept in a `StorageVec`.
+//!
+
+use crate::storage::codec::{Codec, DynCodec};
+use crate::storage::error::Error;
+use crate::storage::vec::{self, StorageVec};
+use crate::storage::verify::{Problem, Verifier, Verify};
+use crate::storage::Offset;
+use crate::storage::Storage;
+use crate::Codec;
+use core::marker::PhantomData;
+use core::result::Result;
+
+/// The instance of Storage Dynamic Vector
+pub struct StorageDynVec<'a, T: DynCodec> {
+    storage: &'a Storage,
+    entries: StorageVec<'a, Entry>,
+    _phantom: PhantomData<T>,
+}
+
+// The location of an encoded value inside the storage.
+#[derive(Codec)]
+struct Entry {
+    pub offset: Offset,
+    pub len: u32,
+}
+
+impl<'a, T: DynCodec> StorageDynVec<'a, T> {
+    /// creates and store a new instance of Storage Dynamic Vector with at least the specified capacity.
+    pub fn create(storage: &'a Storage, capacity: u32) -> Result<Self, Error> {
+        let entries = StorageVec::create(storage, capacity)?;
+
+        Ok(StorageDynVec {
+            storage,
+            entries,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// load the Storage Dynamic Vector
+    pub fn load(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        let entries = StorageVec::load(storage, offset)?;
+
+        Ok(StorageDynVec {
+            storage,
+            entries,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the offset of `StorageDynVec` in the storage file.
+    pub fn offset(&self) -> Offset {
+        self.entries.offset()
+    }
+
+    /// Returns the number of elements in the `StorageDynVec`.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the `StorageDynVec` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends an element to the back of a `StorageDynVec`.
+    pub fn push(&mut self, value: T) -> Result<(), Error> {
+        let entry = self.write_value(&value)?;
+        self.entries.push(entry)
+    }
+
+    /// Returns an element at the given index or None if out of bounds.
+    pub fn get(&self, index: u32) -> Result<Option<T>, Error> {
+        match self.entries.get(index)? {
+            Some(entry) => Ok(Some(read_value(self.storage, &entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the element at the given index.
+    /// If the length of the new element differs, it is moved to a newly allocated space.
+    pub fn set(&mut self, index: u32, value: T) -> Result<(), Error> {
+        let entry = self
+            .entries
+            .get(index)?
+            .ok_or(Error::IndexOutOfBounds(index))?;
+
+        if entry.len as usize == value.packed_len() {
+            return self
+                .storage
+                .write_bytes(entry.offset, &value.to_dyn_bytes());
+        }
+        let new_entry = self.write_value(&value)?;
+        self.entries.set(index, new_entry)?;
+        self.storage.free(entry.offset, entry.len as usize)
+    }
+
+    /// Removes the last element from the `StorageDynVec` and returns it, or None if it is empty.
+    pub fn pop(&mut self) -> Result<Option<T>, Error> {
+        match self.entries.pop()? {
+            Some(entry) => Ok(Some(self.take_value(&entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes and returns the element at position `index` within the `StorageDynVec`,
+    /// shifting all elements after it to the left.
+    pub fn remove(&mut self, index: u32) -> Result<T, Error> {
+        let entry = self.entries.remove(index)?;
+        self.take_value(&entry)
+    }
+
+    /// Clears the `StorageDynVec`, removing all values and freeing their spaces.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        for entry in self.entries.iter() {
+            let entry = entry?;
+            self.storage.free(entry.offset, entry.len as usize)?;
+        }
+        self.entries.clear()
+    }
+
+    /// Returns an iterator over the elements of the `StorageDynVec`.
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter {
+            storage: self.storage,
+            entries: self.entries.iter(),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn write_value(&self, value: &T) -> Result<Entry, Error> {
+        let bytes = value.to_dyn_bytes();
+        let offset = self.storage.allocate(bytes.len())?;
+        self.storage.write_bytes(offset, &bytes)?;
+
+        Ok(Entry {
+            offset,
+            len: bytes.len() as u32,
+        })
+    }
+
+    // Reads the value of a removed entry and frees its space.
+    fn take_value(&self, entry: &Entry) -> Result<T, Error> {
+        let value = read_value(self.storage, entry)?;
+        self.storage.free(entry.offset, entry.len as usize)?;
+        Ok(value)
+    }
+}
+
+fn read_value<T: DynCodec>(storage: &Storage, entry: &Entry) -> Result<T, Error> {
+    let bytes = storage.read_bytes(entry.offset, entry.len)?;
+    Ok(T::from_dyn_bytes(&bytes)?)
+}
+
+/// An iterator over the elements of a `StorageDynVec`.
+pub struct Iter<'a, T: DynCodec> {
+    storage: &'a Storage,
+    entries: vec::Iter<'a, Entry>,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, T: DynCodec> Iterator for Iter<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        Some(entry.and_then(|entry| read_value(self.storage, &entry)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, T: DynCodec> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next_back()?;
+        Some(entry.and_then(|entry| read_value(self.storage, &entry)))
+    }
+}
+
+impl<'a, T: DynCodec> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T: DynCodec> IntoIterator for &StorageDynVec<'a, T> {
+    type Item = Result<T, Error>;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T: DynCodec> Verify for StorageDynVec<'a, T> {
+    fn verify(&self, verifier: &mut Verifier) {
+        verifier.check(&self.entries);
+        for entry in self.entries.iter() {
+            match entry {
+                Ok(entry) => {
+                    verifier.block(entry.offset, entry.len as usize);
+                }
+                Err(_) => {
+                    verifier.problem(Problem::Corrupted(self.offset()));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::mock_storage;
+    use crate::DynCodec;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    #[derive(DynCodec, Debug, PartialEq, Eq)]
+    struct Profile {
+        id: u32,
+        name: String,
+        email: Option<String>,
+    }
+
+    fn profile(id: u32, name: &str) -> Profile {
+        Profile {
+            id,
+            name: name.to_string(),
+            email: None,
+        }
+    }
+
+    #[test]
+    fn test_dyn_vector() {
+        let storage = mock_storage(1024 * 1024);
+        let mut vec_1 = StorageDynVec::<Profile>::create(&storage, 2).unwrap();
+        vec_1.push(profile(1, "alice")).unwrap();
+        vec_1.push(profile(2, "bob")).unwrap();
+        vec_1.push(profile(3, "carol")).unwrap();
+
+        let mut vec_2 = StorageDynVec::<Profile>::load(&storage, vec_1.offset()).unwrap();
+        assert_eq!(vec_2.len(), 3);
+        assert_eq!(Some(profile(2, "bob")), vec_2.get(1).unwrap());
+        assert_eq!(None, vec_2.get(3).unwrap());
+
+        // Same length, written in place
+        vec_2.set(1, profile(2, "BOB")).unwrap();
+        // Different length, moved
+        let mut dave = profile(4, "dave");
+        dave.email = Some("dave@example.com".to_string());
+        vec_2.set(0, dave).unwrap();
+        assert!(vec_2.set(3, profile(5, "eve")).is_err());
+
+        let all: Vec<Profile> = vec_2.iter().map(|p| p.unwrap()).collect();
+        assert_eq!(all[0].email.as_deref(), Some("dave@example.com"));
+        assert_eq!(all[1].name, "BOB");
+        assert_eq!(all[2].name, "carol");
+        assert_eq!(vec_2.iter().next_back().unwrap().unwrap().id, 3);
+
+        assert_eq!(vec_2.remove(0).unwrap().id, 4);
+        assert_eq!(vec_2.pop().unwrap().unwrap().id, 3);
+        assert_eq!(vec_2.len(), 1);
+        vec_2.clear().unwrap();
+        assert!(vec_2.is_empty());
+        assert_eq!(None, vec_2.pop().unwrap());
+    }
+
+    #[test]
+    fn test_dyn_vector_strings() {
+        let storage = mock_storage(1024 * 1024);
+        let mut vec = StorageDynVec::<Vec<String>>::create(&storage, 0).unwrap();
+        vec.push(Vec::new()).unwrap();
+        vec.push(["a".to_string(), "".to_string()].to_vec())
+            .unwrap();
+
+        assert_eq!(Some(Vec::new()), vec.get(0).unwrap());
+        assert_eq!(vec.get(1).unwrap().unwrap()[0], "a");
+    }
+}
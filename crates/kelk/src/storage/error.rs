@@ -1,5 +1,6 @@
 //! Storage error types
 
+use super::codec::CodecError;
 use alloc::string::String;
 use core::fmt::Debug;
 
@@ -18,10 +19,29 @@ pub enum Error {
     /// Capacity is full
     OutOfCapacity,
 
+    /// Index is out of bounds
+    IndexOutOfBounds(u32),
+
+    /// Decoding the stored data failed
+    CodecError(CodecError),
+
+    /// The stored data is written by another version of the layout and should be migrated
+    VersionMismatch(u8),
+
+    /// The stored data has another type, like a root that is registered with another type,
+    /// or a header that is written for another layout
+    TypeMismatch,
+
     /// Generic error
     GenericError(String),
 }
 
+impl From<CodecError> for Error {
+    fn from(error: CodecError) -> Self {
+        Error::CodecError(error)
+    }
+}
+
 impl From<kelk_env::error::HostError> for Error {
     fn from(error: kelk_env::error::HostError) -> Self {
         Error::HostError(error.code)
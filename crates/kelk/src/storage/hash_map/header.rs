@@ -0,0 +1,24 @@
+use crate::storage::codec::Codec;
+use crate::storage::Offset;
+use crate::Codec;
+
+#[derive(Codec)]
+pub(super) struct Header {
+    pub key_len: u16,
+    pub value_len: u16,
+    pub count: u32,
+    pub capacity: u32,
+    pub table_offset: Offset,
+}
+
+impl Header {
+    pub fn new<K: Codec, V: Codec>(capacity: u32, table_offset: Offset) -> Self {
+        Self {
+            key_len: K::PACKED_LEN as u16,
+            value_len: V::PACKED_LEN as u16,
+            count: 0,
+            capacity,
+            table_offset,
+        }
+    }
+}
@@ -0,0 +1,500 @@
+//! Storage Hash Map
+//!
+//! Storage Hash Map, is a Hash Map that instead of using Random Access Memory (RAM),
+//! it uses storage file. Therefore it's permanently stored inside contract's storage.
+//!
+//! The entries are kept in a table of slots using open addressing with linear probing.
+//! The keys are hashed by [FNV-1a](http://www.isthe.com/chongo/tech/comp/fnv/) over their packed bytes.
+//! The hash is not randomized, therefore the layout of the table is the same on all the nodes.
+
+mod header;
+
+use self::header::Header;
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::verify::{Problem, Verifier, Verify};
+use crate::storage::{hash, Offset, Storage};
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::result::Result;
+
+/// The number of slots of the table, when the capacity is not specified.
+const DEFAULT_SLOTS: u32 = 16;
+
+/// The number of slots that the iterator reads in one storage call.
+const ITER_CHUNK_LEN: u32 = 32;
+
+/// The instance of Storage Hash Map
+pub struct StorageHashMap<'a, K, V>
+where
+    K: Codec + Eq,
+    V: Codec,
+{
+    storage: &'a Storage,
+    offset: Offset,
+    header: Header,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'a, K, V> StorageHashMap<'a, K, V>
+where
+    K: Codec + Eq,
+    V: Codec,
+{
+    /// Creates a new instance of `StorageHashMap`.
+    pub fn create(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create_with_slots(storage, DEFAULT_SLOTS)
+    }
+
+    /// Creates a new instance of `StorageHashMap` that can hold at least `capacity` elements
+    /// without rehashing.
+    pub fn create_with_capacity(storage: &'a Storage, capacity: u32) -> Result<Self, Error> {
+        let slots = (capacity as u64 * 4 / 3 + 1)
+            .max(DEFAULT_SLOTS as u64)
+            .next_power_of_two();
+        let slots = u32::try_from(slots).map_err(|_| Error::OutOfCapacity)?;
+        Self::create_with_slots(storage, slots)
+    }
+
+    fn create_with_slots(storage: &'a Storage, slots: u32) -> Result<Self, Error> {
+        let offset = storage.allocate(Header::PACKED_LEN)?;
+        let table_offset = allocate_table::<K, V>(storage, slots)?;
+        let header = Header::new::<K, V>(slots, table_offset);
+        storage.write(offset, &header)?;
+
+        Ok(StorageHashMap {
+            storage,
+            offset,
+            header,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Loads the Storage Hash Map
+    pub fn load(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        let header: Header = storage.read(offset)?;
+        if header.key_len != K::PACKED_LEN as u16 || header.value_len != V::PACKED_LEN as u16 {
+            return Err(Error::TypeMismatch);
+        }
+        // The slots are probed by masking the hash, so the capacity should be a power of two
+        if !header.capacity.is_power_of_two() {
+            return Err(Error::GenericError("invalid capacity".to_string()));
+        }
+
+        Ok(StorageHashMap {
+            storage,
+            offset,
+            header,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the offset of `StorageHashMap` in the storage file.
+    pub fn offset(&self) -> Offset {
+        self.offset
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements the map can hold without rehashing.
+    pub fn capacity(&self) -> usize {
+        self.header.capacity as usize * 3 / 4
+    }
+
+    /// Inserts a key-value pair into the map.
+    /// If the map did not have this key present, None is returned.
+    /// If the map did have this key present, the value is updated, and the old value is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, Error> {
+        let key_bytes = key.to_bytes();
+        let (mut index, slot) = self.probe(&key_bytes)?;
+        if slot_is_used(&slot) {
+            // key exists, update value
+            let old_value = V::try_from_bytes(&slot[1 + K::PACKED_LEN..])?;
+            self.storage.write(
+                self.slot_offset(index) + 1 + K::PACKED_LEN as Offset,
+                &value,
+            )?;
+            return Ok(Some(old_value));
+        }
+
+        // The table grows only when a new slot is taken
+        if self.len() >= self.capacity() {
+            self.rehash(self.header.capacity * 2)?;
+            index = self.probe(&key_bytes)?.0;
+        }
+        let slot_offset = self.slot_offset(index);
+
+        let mut slot = Vec::with_capacity(slot_len::<K, V>());
+        slot.push(1);
+        slot.extend_from_slice(&key_bytes);
+        slot.extend_from_slice(&value.to_bytes());
+        self.storage.write_bytes(slot_offset, &slot)?;
+
+        // update header
+        self.header.count += 1;
+        self.storage.write(self.offset, &self.header)?;
+        Ok(None)
+    }
+
+    /// Returns the value corresponding to the key. If the key doesn't exists, it returns None.
+    pub fn find(&self, key: &K) -> Result<Option<V>, Error> {
+        let (_, slot) = self.probe(&key.to_bytes())?;
+        if !slot_is_used(&slot) {
+            return Ok(None);
+        }
+        Ok(Some(V::try_from_bytes(&slot[1 + K::PACKED_LEN..])?))
+    }
+
+    /// Returns true if the map contains a value for the specified key.
+    pub fn contains_key(&self, key: &K) -> Result<bool, Error> {
+        let (_, slot) = self.probe(&key.to_bytes())?;
+        Ok(slot_is_used(&slot))
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was previously in the map.
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, Error> {
+        let (mut index, slot) = self.probe(&key.to_bytes())?;
+        if !slot_is_used(&slot) {
+            return Ok(None);
+        }
+        let old_value = V::try_from_bytes(&slot[1 + K::PACKED_LEN..])?;
+
+        // Shifting back the next entries of the probe sequence into the hole,
+        // so the lookups don't stop at the removed slot.
+        let mask = self.header.capacity - 1;
+        let mut next = index;
+        loop {
+            next = (next + 1) & mask;
+            let slot = self.read_slot(next)?;
+            if !slot_is_used(&slot) {
+                break;
+            }
+
+            // The entry can't move if its home slot is cyclically in (index, next].
+            let home = hash(&slot[1..1 + K::PACKED_LEN]) & mask;
+            let stays = if index <= next {
+                index < home && home <= next
+            } else {
+                index < home || home <= next
+            };
+            if !stays {
+                self.storage.write_bytes(self.slot_offset(index), &slot)?;
+                index = next;
+            }
+        }
+        self.storage
+            .write_bytes(self.slot_offset(index), &vec![0; slot_len::<K, V>()])?;
+
+        // update header
+        self.header.count -= 1;
+        self.storage.write(self.offset, &self.header)?;
+        Ok(Some(old_value))
+    }
+
+    /// Gets an iterator over the entries of the map, in arbitrary order.
+    /// It reads multiple slots in each storage call.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            storage: self.storage,
+            table_offset: self.header.table_offset,
+            capacity: self.header.capacity,
+            index: 0,
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    // Finds the slot of the key, or the empty slot that the key should be placed in.
+    // It returns the index and the packed bytes of the slot.
+    fn probe(&self, key_bytes: &[u8]) -> Result<(u32, Vec<u8>), Error> {
+        let mask = self.header.capacity - 1;
+        let mut index = hash(key_bytes) & mask;
+        loop {
+            let slot = self.read_slot(index)?;
+            if !slot_is_used(&slot) || slot[1..1 + K::PACKED_LEN].eq(key_bytes) {
+                return Ok((index, slot));
+            }
+            index = (index + 1) & mask;
+        }
+    }
+
+    // Moves all the entries into a new table with the given number of slots.
+    // The new table is built in memory and written by one storage call.
+    fn rehash(&mut self, capacity: u32) -> Result<(), Error> {
+        let slot_len = slot_len::<K, V>();
+        let old_len = self.header.capacity as usize * slot_len;
+        let old_table = self
+            .storage
+            .read_bytes(self.header.table_offset, old_len as u32)?;
+
+        let mask = capacity - 1;
+        let mut new_table = vec![0; capacity as usize * slot_len];
+        for slot in old_table.chunks(slot_len).filter(|slot| slot_is_used(slot)) {
+            let mut index = hash(&slot[1..1 + K::PACKED_LEN]) & mask;
+            while new_table[index as usize * slot_len] != 0 {
+                index = (index + 1) & mask;
+            }
+            new_table[index as usize * slot_len..][..slot_len].copy_from_slice(slot);
+        }
+
+        let table_offset = self.storage.allocate(new_table.len())?;
+        self.storage.write_bytes(table_offset, &new_table)?;
+        self.storage.free(self.header.table_offset, old_len)?;
+
+        // update header
+        self.header.capacity = capacity;
+        self.header.table_offset = table_offset;
+        self.storage.write(self.offset, &self.header)
+    }
+
+    fn slot_offset(&self, index: u32) -> Offset {
+        self.header.table_offset + index * slot_len::<K, V>() as Offset
+    }
+
+    fn read_slot(&self, index: u32) -> Result<Vec<u8>, Error> {
+        self.storage
+            .read_bytes(self.slot_offset(index), slot_len::<K, V>() as u32)
+    }
+}
+
+/// An iterator over the entries of a `StorageHashMap`, in arbitrary order.
+pub struct Iter<'a, K, V>
+where
+    K: Codec + Eq,
+    V: Codec,
+{
+    storage: &'a Storage,
+    table_offset: Offset,
+    capacity: u32,
+    // The index of the next slot to read from the storage
+    index: u32,
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V>
+where
+    K: Codec + Eq,
+    V: Codec,
+{
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot_len = slot_len::<K, V>();
+        loop {
+            if self.buffer_pos >= self.buffer.len() {
+                if self.index >= self.capacity {
+                    return None;
+                }
+                let count = ITER_CHUNK_LEN.min(self.capacity - self.index);
+                let offset = self.table_offset + self.index * slot_len as Offset;
+                match self.storage.read_bytes(offset, count * slot_len as u32) {
+                    Ok(buffer) => self.buffer = buffer,
+                    Err(err) => {
+                        self.index = self.capacity;
+                        self.buffer.clear();
+                        return Some(Err(err));
+                    }
+                }
+                self.buffer_pos = 0;
+                self.index += count;
+            }
+
+            let slot = &self.buffer[self.buffer_pos..self.buffer_pos + slot_len];
+            self.buffer_pos += slot_len;
+            if slot_is_used(slot) {
+                let key = K::try_from_bytes(&slot[1..1 + K::PACKED_LEN]);
+                let value = V::try_from_bytes(&slot[1 + K::PACKED_LEN..]);
+                return Some(match (key, value) {
+                    (Ok(key), Ok(value)) => Ok((key, value)),
+                    (Err(err), _) | (_, Err(err)) => Err(err.into()),
+                });
+            }
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a StorageHashMap<'_, K, V>
+where
+    K: Codec + Eq,
+    V: Codec,
+{
+    type Item = Result<(K, V), Error>;
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Each slot is packed as `used: u8 | key: K | value: V`
+fn slot_len<K: Codec, V: Codec>() -> usize {
+    1 + K::PACKED_LEN + V::PACKED_LEN
+}
+
+fn slot_is_used(slot: &[u8]) -> bool {
+    slot[0] != 0
+}
+
+fn allocate_table<K: Codec, V: Codec>(storage: &Storage, capacity: u32) -> Result<Offset, Error> {
+    let len = capacity as usize * slot_len::<K, V>();
+    let offset = storage.allocate(len)?;
+    // The allocated space might be reused, therefore it should be cleared.
+    storage.write_bytes(offset, &vec![0; len])?;
+    Ok(offset)
+}
+
+impl<'a, K, V> Verify for StorageHashMap<'a, K, V>
+where
+    K: Codec + Eq,
+    V: Codec,
+{
+    fn verify(&self, verifier: &mut Verifier) {
+        if !verifier.block(self.offset, Header::PACKED_LEN) {
+            return;
+        }
+        let table_len = self.header.capacity as usize * slot_len::<K, V>();
+        if !verifier.block(self.header.table_offset, table_len) {
+            return;
+        }
+        let table = match self
+            .storage
+            .read_bytes(self.header.table_offset, table_len as u32)
+        {
+            Ok(table) => table,
+            Err(_) => {
+                verifier.problem(Problem::Corrupted(self.header.table_offset));
+                return;
+            }
+        };
+        let count = table
+            .chunks(slot_len::<K, V>())
+            .filter(|slot| slot_is_used(slot))
+            .count() as u32;
+        if count != self.header.count {
+            verifier.problem(Problem::CountMismatch {
+                offset: self.offset,
+                expected: self.header.count,
+                found: count,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::mock_storage;
+    use alloc::collections::BTreeMap;
+
+    #[test]
+    fn test_hash() {
+        // Test vectors from the FNV reference
+        assert_eq!(hash(b""), 0x811c9dc5);
+        assert_eq!(hash(b"a"), 0xe40c292c);
+        assert_eq!(hash(b"foobar"), 0xbf9cf968);
+    }
+
+    #[test]
+    fn test_hash_map() {
+        let storage = mock_storage(1024 * 1024);
+        let mut map_1 = StorageHashMap::<i32, i64>::create(&storage).unwrap();
+
+        assert_eq!(None, map_1.insert(1, 10).unwrap());
+        assert_eq!(None, map_1.insert(3, 30).unwrap());
+        assert_eq!(None, map_1.insert(2, 20).unwrap());
+        assert_eq!(Some(10), map_1.insert(1, 100).unwrap());
+
+        let map_2 = StorageHashMap::<i32, i64>::load(&storage, map_1.offset()).unwrap();
+        assert_eq!(map_2.len(), 3);
+        assert_eq!(Some(20), map_2.find(&2).unwrap());
+        assert_eq!(None, map_2.find(&4).unwrap());
+        assert_eq!(Some(30), map_2.find(&3).unwrap());
+        assert_eq!(Some(100), map_2.find(&1).unwrap());
+        assert!(!map_2.contains_key(&-1).unwrap());
+        assert!(map_2.contains_key(&2).unwrap());
+    }
+
+    #[test]
+    fn test_hash_map_rehash_and_remove() {
+        let storage = mock_storage(1024 * 1024);
+        let mut map = StorageHashMap::<u32, u32>::create(&storage).unwrap();
+        let mut expected = BTreeMap::new();
+        assert_eq!(map.capacity(), 12);
+
+        for key in 0..1000 {
+            map.insert(key * 7, key).unwrap();
+            expected.insert(key * 7, key);
+        }
+        assert_eq!(map.len(), 1000);
+        assert!(map.capacity() >= 1000);
+
+        for key in (0..1000).step_by(2) {
+            assert_eq!(expected.remove(&(key * 7)), map.remove(&(key * 7)).unwrap());
+        }
+        assert_eq!(None, map.remove(&1).unwrap());
+        assert_eq!(map.len(), 500);
+
+        for key in 0..1000 {
+            assert_eq!(
+                expected.get(&(key * 7)).copied(),
+                map.find(&(key * 7)).unwrap()
+            );
+        }
+
+        let mut items: Vec<(u32, u32)> = map.iter().map(|i| i.unwrap()).collect();
+        items.sort();
+        let expected_items: Vec<(u32, u32)> = expected.into_iter().collect();
+        assert_eq!(items, expected_items);
+        assert_eq!(500, (&map).into_iter().count());
+    }
+
+    #[test]
+    fn test_hash_map_with_capacity() {
+        let storage = mock_storage(1024 * 1024);
+        let mut map = StorageHashMap::<u32, u32>::create_with_capacity(&storage, 100).unwrap();
+        let table_offset = map.header.table_offset;
+        for key in 0..100 {
+            map.insert(key, key).unwrap();
+        }
+        // No rehash happened
+        assert_eq!(table_offset, map.header.table_offset);
+
+        // Updating the existing keys of a full map doesn't rehash
+        let mut map = StorageHashMap::<u32, u32>::create(&storage).unwrap();
+        for key in 0..map.capacity() as u32 {
+            map.insert(key, key).unwrap();
+        }
+        let table_offset = map.header.table_offset;
+        assert_eq!(map.insert(0, 10).unwrap(), Some(0));
+        assert_eq!(table_offset, map.header.table_offset);
+        map.insert(100, 100).unwrap();
+        assert_ne!(table_offset, map.header.table_offset);
+        assert_eq!(map.find(&0).unwrap(), Some(10));
+        assert_eq!(map.find(&100).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn test_hash_map_invalid_capacity() {
+        let storage = mock_storage(1024 * 1024);
+        let map = StorageHashMap::<u32, u32>::create(&storage).unwrap();
+        for capacity in [0, 3, 24] {
+            let mut header: Header = storage.read(map.offset()).unwrap();
+            header.capacity = capacity;
+            storage.write(map.offset(), &header).unwrap();
+            assert!(StorageHashMap::<u32, u32>::load(&storage, map.offset()).is_err());
+        }
+    }
+}
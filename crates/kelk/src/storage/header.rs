@@ -0,0 +1,215 @@
+//! Storage file header
+//!
+//! The storage file starts with the header. The version 2 of the header is packed as:
+//!
+//! | Offset | Length | Field                                                 |
+//! |--------|--------|-------------------------------------------------------|
+//! | 0      | 4      | magic number: `KELK`                                  |
+//! | 4      | 2      | format version: 2                                     |
+//! | 6      | 2      | stack size                                            |
+//! | 8      | 4      | feature flags                                         |
+//! | 12     | 4      | offset of the root stack                              |
+//! | 16     | 4      | offset of the allocator metadata                      |
+//! | 20     | 4      | offset of the block checksums, or zero if not enabled |
+//! | 24     | 4      | offset of the allocatable space                       |
+//! | 28     | 4      | CRC-32 of the previous fields                         |
+//!
+//! The allocator metadata keeps the free storage position, the free lists and the offset of
+//! the root registry. In the new files the allocatable space starts right after the metadata.
+//!
+//! The version 1 of the header was only the version `[1, 0]` and the stack size `[0, 1]`,
+//! followed by the root stack at offset 4 and the free storage position at offset 1028.
+//! The allocated blocks started at offset 1032, and they were allocated by their exact length.
+//!
+//! Loading a version 1 file upgrades it. The blocks are kept at their place, and the allocator
+//! metadata is written at the end of the allocated space. Then the root stack is moved into
+//! a newly allocated block, and the version 2 header is written at the beginning of the file.
+//! The blocks between the allocatable space and the metadata are called legacy blocks.
+//!
+
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::{crc32, Offset, SIZE_CLASSES};
+use crate::Codec;
+use alloc::string::ToString;
+
+/// The magic number at the beginning of the storage file.
+pub const MAGIC: [u8; 4] = *b"KELK";
+
+/// The current format version of the storage file.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// The allocator keeps the freed blocks in free lists, one for each size class.
+/// Without it, the blocks are only allocated at the end of the allocated space and
+/// freeing a block doesn't reuse it.
+pub const FEATURE_FREE_LISTS: u32 = 1 << 0;
+
+/// The entry functions are run atomically, through the write journal.
+/// Without it, `Storage::atomic` writes directly and the writes are kept if it fails.
+pub const FEATURE_JOURNALING: u32 = 1 << 1;
+
+/// The stored data is compressed. It is reserved and not supported yet.
+pub const FEATURE_COMPRESSION: u32 = 1 << 2;
+
+/// The CRC-32 of the allocated blocks are kept and checked by the verifier.
+pub const FEATURE_BLOCK_CHECKSUMS: u32 = 1 << 3;
+
+/// The features that are enabled for the new storage files by default.
+pub const DEFAULT_FEATURES: u32 = FEATURE_FREE_LISTS | FEATURE_JOURNALING;
+
+/// The stack size of the new storage files by default.
+pub const DEFAULT_STACK_SIZE: u16 = 256;
+
+/// The features that are supported by this version of the library.
+const SUPPORTED_FEATURES: u32 = FEATURE_FREE_LISTS | FEATURE_JOURNALING | FEATURE_BLOCK_CHECKSUMS;
+
+/// The length of the allocator metadata:
+/// `free_pos: u32 | free_lists: [u32; SIZE_CLASSES] | roots: u32`
+pub(crate) const META_LEN: u32 = 4 + SIZE_CLASSES as u32 * 4 + 4;
+
+/// The stack size of the version 1 files.
+pub(crate) const V1_STACK_SIZE: u16 = 256;
+
+/// The offset of the root stack in the version 1 files.
+pub(crate) const V1_STACK_OFFSET: Offset = 4;
+
+/// The offset of the free storage position in the version 1 files.
+pub(crate) const V1_FREE_POS_OFFSET: Offset = 1028;
+
+/// The offset of the allocatable space in the version 1 files.
+pub(crate) const V1_DATA_OFFSET: Offset = 1032;
+
+/// The configuration of a new storage file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatConfig {
+    /// The number of the offsets that can be kept in the root stack.
+    pub stack_size: u16,
+    /// The feature flags.
+    pub features: u32,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        FormatConfig {
+            stack_size: DEFAULT_STACK_SIZE,
+            features: DEFAULT_FEATURES,
+        }
+    }
+}
+
+/// The header of the storage file.
+#[derive(Codec, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub magic: [u8; 4],
+    pub version: u16,
+    pub stack_size: u16,
+    pub features: u32,
+    pub stack_offset: Offset,
+    pub meta_offset: Offset,
+    pub checksums_offset: Offset,
+    pub data_offset: Offset,
+    pub checksum: u32,
+}
+
+impl Header {
+    /// Creates the header of a new storage file, where the stack and the metadata follow
+    /// the header.
+    pub fn new(config: FormatConfig) -> Result<Self, Error> {
+        check_features(config.features)?;
+        let stack_offset = Self::PACKED_LEN as Offset;
+        let meta_offset = stack_offset + config.stack_size as Offset * 4;
+        Ok(Header {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            stack_size: config.stack_size,
+            features: config.features,
+            stack_offset,
+            meta_offset,
+            checksums_offset: 0,
+            data_offset: meta_offset + META_LEN,
+            checksum: 0,
+        })
+    }
+
+    /// Creates the header of an upgraded version 1 file, where the metadata is written
+    /// at the given offset. The stack is still at its place, until it is moved.
+    pub fn upgrade_v1(meta_offset: Offset) -> Self {
+        Header {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            stack_size: V1_STACK_SIZE,
+            features: DEFAULT_FEATURES,
+            stack_offset: V1_STACK_OFFSET,
+            meta_offset,
+            checksums_offset: 0,
+            data_offset: V1_DATA_OFFSET,
+            checksum: 0,
+        }
+    }
+
+    /// Decodes the header and checks the magic number, the version, the checksum and
+    /// the feature flags.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let header = Self::try_from_bytes(bytes)?;
+        if header.magic != MAGIC || header.version != FORMAT_VERSION {
+            return Err(Error::GenericError("invalid storage file".to_string()));
+        }
+        if header.checksum != header.compute_checksum() {
+            return Err(Error::GenericError(
+                "invalid storage header checksum".to_string(),
+            ));
+        }
+        check_features(header.features)?;
+        Ok(header)
+    }
+
+    /// Encodes the header with an updated checksum.
+    pub fn encode(&mut self) -> alloc::vec::Vec<u8> {
+        self.checksum = self.compute_checksum();
+        self.to_bytes()
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let bytes = self.to_bytes();
+        crc32(&bytes[..Self::PACKED_LEN - 4])
+    }
+}
+
+fn check_features(features: u32) -> Result<(), Error> {
+    if features & !SUPPORTED_FEATURES != 0 {
+        return Err(Error::GenericError(alloc::format!(
+            "unsupported storage features: {:#x}",
+            features & !SUPPORTED_FEATURES
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let mut header = Header::new(FormatConfig {
+            stack_size: 16,
+            features: DEFAULT_FEATURES,
+        })
+        .unwrap();
+        assert_eq!(Header::PACKED_LEN, 32);
+        assert_eq!(header.meta_offset, 32 + 64);
+        assert_eq!(header.data_offset, 32 + 64 + META_LEN);
+
+        let mut bytes = header.encode();
+        assert_eq!(&bytes[..4], b"KELK");
+        assert_eq!(Header::decode(&bytes).unwrap(), header);
+
+        bytes[6] = 1;
+        assert!(Header::decode(&bytes).is_err());
+        assert!(Header::new(FormatConfig {
+            stack_size: 16,
+            features: FEATURE_COMPRESSION,
+        })
+        .is_err());
+    }
+}
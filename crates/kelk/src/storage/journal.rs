@@ -0,0 +1,205 @@
+//! Write journal
+//!
+//! The journal buffers the writes of the running transactions in memory.
+//! Each transaction has a layer in the journal. The layer keeps the written bytes as
+//! non-overlapping segments, sorted by their offsets.
+//! Reads are patched by the layers, from the outermost to the innermost one.
+//!
+//! Committing a nested transaction merges its layer into the parent layer.
+//! Committing the outermost transaction returns the segments, so they can be flushed into
+//! the storage file. Rolling back a transaction simply drops its layer.
+//!
+
+use crate::storage::Offset;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// The written bytes of a transaction, keyed by their offsets.
+pub(crate) type Layer = BTreeMap<Offset, Vec<u8>>;
+
+/// `Journal` keeps a layer for each running transaction.
+#[derive(Default)]
+pub(crate) struct Journal {
+    layers: Vec<Layer>,
+}
+
+impl Journal {
+    /// Returns true if there is a running transaction.
+    pub fn is_active(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    /// Starts a new transaction.
+    pub fn begin(&mut self) {
+        self.layers.push(Layer::new());
+    }
+
+    /// Commits the innermost transaction.
+    /// If it is the outermost transaction, its layer is returned to be flushed.
+    pub fn commit(&mut self) -> Option<Layer> {
+        let layer = self.layers.pop()?;
+        match self.layers.last_mut() {
+            Some(parent) => {
+                for (offset, data) in layer {
+                    write_layer(parent, offset, &data);
+                }
+                None
+            }
+            None => Some(layer),
+        }
+    }
+
+    /// Discards the writes of the innermost transaction.
+    pub fn rollback(&mut self) {
+        self.layers.pop();
+    }
+
+    /// Buffers the `data` in the innermost transaction.
+    /// It returns false if there is no running transaction.
+    pub fn write(&mut self, offset: Offset, data: &[u8]) -> bool {
+        match self.layers.last_mut() {
+            Some(layer) => {
+                write_layer(layer, offset, data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Overwrites the `buf`, read from the given `offset`, by the buffered writes.
+    pub fn patch(&self, offset: Offset, buf: &mut [u8]) {
+        for layer in &self.layers {
+            patch_layer(layer, offset, buf);
+        }
+    }
+}
+
+// Returns the end of a segment. The ends are kept in `u64` to avoid overflows.
+fn segment_end(offset: Offset, len: usize) -> u64 {
+    offset as u64 + len as u64
+}
+
+// Writes the `data` into the layer, merging it with the overlapping and adjacent segments.
+fn write_layer(layer: &mut Layer, offset: Offset, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    let end = segment_end(offset, data.len());
+
+    let mut starts = Vec::new();
+    if let Some((start, prev)) = layer.range(..offset).next_back() {
+        if segment_end(*start, prev.len()) >= offset as u64 {
+            starts.push(*start);
+        }
+    }
+    starts.extend(
+        layer
+            .range(offset..)
+            .take_while(|(start, _)| **start as u64 <= end)
+            .map(|(start, _)| *start),
+    );
+
+    let mut merged_start = offset;
+    let mut merged_end = end;
+    for start in &starts {
+        merged_start = merged_start.min(*start);
+        merged_end = merged_end.max(segment_end(*start, layer[start].len()));
+    }
+
+    let mut merged = alloc::vec![0; (merged_end - merged_start as u64) as usize];
+    for start in starts {
+        if let Some(segment) = layer.remove(&start) {
+            let from = (start - merged_start) as usize;
+            merged[from..from + segment.len()].copy_from_slice(&segment);
+        }
+    }
+    let from = (offset - merged_start) as usize;
+    merged[from..from + data.len()].copy_from_slice(data);
+    layer.insert(merged_start, merged);
+}
+
+// Overwrites the `buf` by the segments of the layer that overlap it.
+fn patch_layer(layer: &Layer, offset: Offset, buf: &mut [u8]) {
+    let end = segment_end(offset, buf.len());
+    let prev = layer.range(..offset).next_back();
+    let next = layer
+        .range(offset..)
+        .take_while(|(start, _)| (**start as u64) < end);
+    for (start, segment) in prev.into_iter().chain(next) {
+        let from = (*start as u64).max(offset as u64);
+        let to = segment_end(*start, segment.len()).min(end);
+        if from >= to {
+            continue;
+        }
+        let len = (to - from) as usize;
+        let buf_from = (from - offset as u64) as usize;
+        let segment_from = (from - *start as u64) as usize;
+        buf[buf_from..buf_from + len].copy_from_slice(&segment[segment_from..segment_from + len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(journal: &Journal, offset: Offset, len: usize) -> Vec<u8> {
+        let mut buf = alloc::vec![0; len];
+        journal.patch(offset, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_segments() {
+        let mut journal = Journal::default();
+        assert!(!journal.write(0, &[1]));
+
+        journal.begin();
+        assert!(journal.write(10, &[1, 2, 3]));
+        assert!(journal.write(20, &[4, 5]));
+        assert_eq!(
+            read(&journal, 8, 16),
+            [0, 0, 1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 4, 5, 0, 0]
+        );
+
+        // Overlapping and adjacent writes are merged
+        journal.write(12, &[6, 7, 8]);
+        journal.write(15, &[9]);
+        journal.write(19, &[1]);
+        assert_eq!(
+            read(&journal, 8, 16),
+            [0, 0, 1, 2, 6, 7, 8, 9, 0, 0, 0, 1, 4, 5, 0, 0]
+        );
+        journal.write(11, &[0; 10]);
+        assert_eq!(
+            read(&journal, 8, 16),
+            [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0]
+        );
+
+        let layer = journal.commit().unwrap();
+        assert_eq!(layer.len(), 1);
+        assert_eq!(layer[&10], [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5]);
+        assert!(!journal.is_active());
+    }
+
+    #[test]
+    fn test_nested() {
+        let mut journal = Journal::default();
+        journal.begin();
+        journal.write(0, &[1, 1]);
+
+        journal.begin();
+        journal.write(1, &[2, 2]);
+        assert_eq!(read(&journal, 0, 4), [1, 2, 2, 0]);
+        journal.rollback();
+        assert_eq!(read(&journal, 0, 4), [1, 1, 0, 0]);
+
+        journal.begin();
+        journal.write(3, &[3]);
+        assert!(journal.commit().is_none());
+        assert_eq!(read(&journal, 0, 4), [1, 1, 0, 3]);
+
+        let layer = journal.commit().unwrap();
+        assert_eq!(layer.len(), 2);
+        assert!(journal.commit().is_none());
+    }
+}
@@ -2,6 +2,10 @@ use crate::storage::codec::Codec;
 use crate::storage::Offset;
 use crate::Codec;
 
+/// The highest bit of `item_len` tags the doubly linked lists.
+/// The singly linked lists of the former versions don't have it and they should be migrated.
+pub(super) const LAYOUT_DOUBLY: u16 = 0x8000;
+
 #[derive(Codec)]
 pub(super) struct Header {
     pub count: u32,
@@ -14,9 +18,19 @@ impl Header {
     pub fn new<T: Codec>() -> Self {
         Self {
             count: 0,
-            item_len: T::PACKED_LEN as u16,
+            item_len: T::PACKED_LEN as u16 | LAYOUT_DOUBLY,
             head_offset: 0,
             tail_offset: 0,
         }
     }
+
+    /// Returns true if the list is a doubly linked list.
+    pub fn is_doubly(&self) -> bool {
+        self.item_len & LAYOUT_DOUBLY != 0
+    }
+
+    /// Returns the length of the items, without the layout tag.
+    pub fn item_len(&self) -> u16 {
+        self.item_len & !LAYOUT_DOUBLY
+    }
 }
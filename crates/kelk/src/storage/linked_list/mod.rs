@@ -1,14 +1,18 @@
 //! Storage Linked List
 //!
-//! Storage Linked List, is a singly linked list that instead of using Random Access Memory (RAM),
+//! Storage Linked List, is a doubly linked list that instead of using Random Access Memory (RAM),
 //! it uses storage file. Therefore it's permanently stored inside contract's storage.
 //!
+//! The lists that are created by the former versions are singly linked and their nodes
+//! don't keep the previous node. They should be upgraded once using [`StorageLinkedList::migrate`].
+//!
 
 mod header;
 
-use self::header::Header;
+use self::header::{Header, LAYOUT_DOUBLY};
 use crate::storage::codec::Codec;
 use crate::storage::error::Error;
+use crate::storage::verify::{Problem, Verifier, Verify};
 use crate::storage::Offset;
 use crate::storage::Storage;
 use crate::Codec;
@@ -16,6 +20,14 @@ use core::iter::IntoIterator;
 use core::marker::PhantomData;
 use core::result::Result;
 
+/// The offset of the `prev` field inside the packed node.
+const PREV_OFFSET: Offset = 0;
+/// The offset of the `next` field inside the packed node.
+pub(crate) const NEXT_OFFSET: Offset = 4;
+/// The offset of the `head_offset` field inside the packed header.
+#[cfg(test)]
+pub(crate) const HEAD_OFFSET: Offset = (u32::PACKED_LEN + u16::PACKED_LEN) as Offset;
+
 /// The instance of `StorageLinkedList`
 pub struct StorageLinkedList<'a, T: Codec> {
     storage: &'a Storage,
@@ -25,20 +37,31 @@ pub struct StorageLinkedList<'a, T: Codec> {
 }
 
 #[derive(Codec)]
-pub(self) struct Node<T: Codec> {
-    pub item: T,
+struct Node<T: Codec> {
+    pub prev: Offset,
     pub next: Offset,
+    pub item: T,
 }
 
 impl<T: Codec> Node<T> {
-    pub fn new(item: T) -> Self {
-        Self { item, next: 0 }
+    pub fn new(item: T, prev: Offset, next: Offset) -> Self {
+        Self { prev, next, item }
     }
 }
 
+// The node of the singly linked lists, that are created by the former versions.
+#[derive(Codec)]
+struct LegacyNode<T: Codec> {
+    pub item: T,
+    pub next: Offset,
+}
+
 impl<'a, T: Codec> StorageLinkedList<'a, T> {
     /// Creates a new instance of `StorageLinkedList`.
     pub fn create(storage: &'a Storage) -> Result<Self, Error> {
+        if T::PACKED_LEN >= LAYOUT_DOUBLY as usize {
+            return Err(Error::GenericError("the item is too large".into()));
+        }
         let offset = storage.allocate(Header::PACKED_LEN)?;
         let header = Header::new::<T>();
         storage.write(offset, &header)?;
@@ -52,10 +75,15 @@ impl<'a, T: Codec> StorageLinkedList<'a, T> {
     }
 
     /// Loads the Storage Linked List at the given offset
+    /// If the list is created by the former versions, it returns `VersionMismatch` error.
     pub fn load(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
         let header: Header = storage.read(offset)?;
-
-        debug_assert_eq!(header.item_len, T::PACKED_LEN as u16);
+        if !header.is_doubly() {
+            return Err(Error::VersionMismatch(0));
+        }
+        if header.item_len() != T::PACKED_LEN as u16 {
+            return Err(Error::TypeMismatch);
+        }
 
         Ok(StorageLinkedList {
             storage,
@@ -65,61 +93,374 @@ impl<'a, T: Codec> StorageLinkedList<'a, T> {
         })
     }
 
+    /// Migrates the singly linked list at the given offset, that is created by the former
+    /// versions, into a doubly linked list. The list stays at the same offset.
+    ///
+    /// The items are copied into new nodes. The old nodes are not freed, since the former
+    /// versions allocated them by their exact length.
+    /// If the list is migrated before, it is only loaded.
+    pub fn migrate(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        let header: Header = storage.read(offset)?;
+        if header.is_doubly() {
+            return Self::load(storage, offset);
+        }
+        if header.item_len != T::PACKED_LEN as u16 {
+            return Err(Error::TypeMismatch);
+        }
+
+        let mut list = StorageLinkedList {
+            storage,
+            header: Header::new::<T>(),
+            offset,
+            _phantom: PhantomData,
+        };
+        let mut node_offset = header.head_offset;
+        while node_offset != 0 {
+            if list.header.count == header.count {
+                return Err(Error::GenericError("invalid linked list".into()));
+            }
+            let node: LegacyNode<T> = storage.read(node_offset)?;
+            list.push_back(node.item)?;
+            node_offset = node.next;
+        }
+        if list.header.count != header.count {
+            return Err(Error::GenericError("invalid linked list".into()));
+        }
+        storage.write(offset, &list.header)?;
+
+        Ok(list)
+    }
+
     /// Returns the offset of `StorageLinkedList` in the storage file.
     pub fn offset(&self) -> Offset {
         self.offset
     }
 
+    /// Returns the number of items in the linked list.
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    /// Returns `true` if the linked list contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.header.count == 0
+    }
+
+    /// Returns the first item of the linked list, or None if it is empty.
+    pub fn front(&self) -> Result<Option<T>, Error> {
+        self.item_at(self.header.head_offset)
+    }
+
+    /// Returns the last item of the linked list, or None if it is empty.
+    pub fn back(&self) -> Result<Option<T>, Error> {
+        self.item_at(self.header.tail_offset)
+    }
+
+    /// Pushes an item at the beginning of linked list.
+    pub fn push_front(&mut self, item: T) -> Result<(), Error> {
+        self.link_between(item, 0, self.header.head_offset)?;
+        Ok(())
+    }
+
     /// Pushes an item at the end of linked list.
     pub fn push_back(&mut self, item: T) -> Result<(), Error> {
+        self.link_between(item, self.header.tail_offset, 0)?;
+        Ok(())
+    }
+
+    /// Removes the first item of the linked list and returns it, or None if it is empty.
+    pub fn pop_front(&mut self) -> Result<Option<T>, Error> {
+        match self.header.head_offset {
+            0 => Ok(None),
+            offset => Ok(Some(self.unlink(offset)?.item)),
+        }
+    }
+
+    /// Removes the last item of the linked list and returns it, or None if it is empty.
+    pub fn pop_back(&mut self) -> Result<Option<T>, Error> {
+        match self.header.tail_offset {
+            0 => Ok(None),
+            offset => Ok(Some(self.unlink(offset)?.item)),
+        }
+    }
+
+    /// Removes all the items that the predicate returns `true` for them.
+    /// Returns the number of removed items.
+    pub fn remove_if<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> Result<usize, Error> {
+        let mut removed = 0;
+        let mut offset = self.header.head_offset;
+        while offset != 0 {
+            let node: Node<T> = self.storage.read(offset)?;
+            if f(&node.item) {
+                self.unlink(offset)?;
+                removed += 1;
+            }
+            offset = node.next;
+        }
+        Ok(removed)
+    }
+
+    /// Returns an iterator over the items of the linked list.
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter {
+            storage: self.storage,
+            front_offset: self.header.head_offset,
+            back_offset: self.header.tail_offset,
+            remaining: self.header.count,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns a cursor pointing to the first item of the linked list.
+    /// If the list is empty, the cursor points to the "ghost" non-item.
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, 'a, T> {
+        CursorMut {
+            current: self.header.head_offset,
+            list: self,
+        }
+    }
+
+    /// Returns a cursor pointing to the last item of the linked list.
+    /// If the list is empty, the cursor points to the "ghost" non-item.
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, 'a, T> {
+        CursorMut {
+            current: self.header.tail_offset,
+            list: self,
+        }
+    }
+
+    fn item_at(&self, offset: Offset) -> Result<Option<T>, Error> {
+        if offset == 0 {
+            return Ok(None);
+        }
+        let node: Node<T> = self.storage.read(offset)?;
+        Ok(Some(node.item))
+    }
+
+    // Writes a new node between `prev` and `next` nodes and returns its offset.
+    // Zero for `prev` or `next` means the head or tail of the list.
+    fn link_between(&mut self, item: T, prev: Offset, next: Offset) -> Result<Offset, Error> {
         let offset = self.storage.allocate(Node::<T>::PACKED_LEN)?;
-        let node = Node::new(item);
+        self.storage.write(offset, &Node::new(item, prev, next))?;
 
-        if self.header.count == 0 {
-            self.header.head_offset = offset;
-        } else {
-            let mut tail: Node<T> = self.storage.read(self.header.tail_offset)?;
-            tail.next = offset;
-            self.storage.write(self.header.tail_offset, &tail)?;
+        match prev {
+            0 => self.header.head_offset = offset,
+            _ => self.storage.write_u32(prev + NEXT_OFFSET, &offset)?,
+        }
+        match next {
+            0 => self.header.tail_offset = offset,
+            _ => self.storage.write_u32(next + PREV_OFFSET, &offset)?,
         }
-        self.storage.write(offset, &node)?;
 
         self.header.count += 1;
-        self.header.tail_offset = offset;
-        self.storage.write(self.offset, &self.header)
+        self.storage.write(self.offset, &self.header)?;
+        Ok(offset)
+    }
+
+    // Removes the node at the given offset from the list, frees it and returns it.
+    fn unlink(&mut self, offset: Offset) -> Result<Node<T>, Error> {
+        let node: Node<T> = self.storage.read(offset)?;
+
+        match node.prev {
+            0 => self.header.head_offset = node.next,
+            prev => self.storage.write_u32(prev + NEXT_OFFSET, &node.next)?,
+        }
+        match node.next {
+            0 => self.header.tail_offset = node.prev,
+            next => self.storage.write_u32(next + PREV_OFFSET, &node.prev)?,
+        }
+        self.storage.free(offset, Node::<T>::PACKED_LEN)?;
+
+        self.header.count -= 1;
+        self.storage.write(self.offset, &self.header)?;
+        Ok(node)
     }
 }
 
+/// A cursor over a `StorageLinkedList` with editing operations.
 ///
-pub struct StorageLinkedListIter<'a, T> {
+/// Like `alloc::collections::linked_list::CursorMut`, the cursor points either to an item or to
+/// a "ghost" non-item that sits between the tail and the head of the list.
+pub struct CursorMut<'b, 'a, T: Codec> {
+    list: &'b mut StorageLinkedList<'a, T>,
+    // The offset of the current node, or zero for the "ghost" non-item.
+    current: Offset,
+}
+
+impl<'b, 'a, T: Codec> CursorMut<'b, 'a, T> {
+    /// Returns the item that the cursor is pointing to, or None for the "ghost" non-item.
+    pub fn current(&self) -> Result<Option<T>, Error> {
+        self.list.item_at(self.current)
+    }
+
+    /// Replaces the item that the cursor is pointing to.
+    /// Returns an error if the cursor is pointing to the "ghost" non-item.
+    pub fn set_current(&mut self, item: T) -> Result<(), Error> {
+        if self.current == 0 {
+            return Err(Error::InvalidOffset(0));
+        }
+        let node: Node<T> = self.list.storage.read(self.current)?;
+        self.list
+            .storage
+            .write(self.current, &Node::new(item, node.prev, node.next))
+    }
+
+    /// Moves the cursor to the next item.
+    /// If the cursor is pointing to the "ghost" non-item, it moves to the first item.
+    pub fn move_next(&mut self) -> Result<(), Error> {
+        self.current = match self.current {
+            0 => self.list.header.head_offset,
+            current => self.list.storage.read_u32(current + NEXT_OFFSET)?,
+        };
+        Ok(())
+    }
+
+    /// Moves the cursor to the previous item.
+    /// If the cursor is pointing to the "ghost" non-item, it moves to the last item.
+    pub fn move_prev(&mut self) -> Result<(), Error> {
+        self.current = match self.current {
+            0 => self.list.header.tail_offset,
+            current => self.list.storage.read_u32(current + PREV_OFFSET)?,
+        };
+        Ok(())
+    }
+
+    /// Inserts a new item after the current one.
+    /// If the cursor is pointing to the "ghost" non-item, the new item is inserted at the front.
+    pub fn insert_after(&mut self, item: T) -> Result<(), Error> {
+        let next = match self.current {
+            0 => self.list.header.head_offset,
+            current => self.list.storage.read_u32(current + NEXT_OFFSET)?,
+        };
+        self.list.link_between(item, self.current, next)?;
+        Ok(())
+    }
+
+    /// Inserts a new item before the current one.
+    /// If the cursor is pointing to the "ghost" non-item, the new item is inserted at the end.
+    pub fn insert_before(&mut self, item: T) -> Result<(), Error> {
+        let prev = match self.current {
+            0 => self.list.header.tail_offset,
+            current => self.list.storage.read_u32(current + PREV_OFFSET)?,
+        };
+        self.list.link_between(item, prev, self.current)?;
+        Ok(())
+    }
+
+    /// Removes the current item and returns it. The cursor is moved to the next item.
+    /// If the cursor is pointing to the "ghost" non-item, nothing is removed and None is returned.
+    pub fn remove_current(&mut self) -> Result<Option<T>, Error> {
+        if self.current == 0 {
+            return Ok(None);
+        }
+        let node = self.list.unlink(self.current)?;
+        self.current = node.next;
+        Ok(Some(node.item))
+    }
+}
+
+/// The former name of [`Iter`].
+pub type StorageLinkedListIter<'a, T> = Iter<'a, T>;
+
+/// An iterator over the items of a `StorageLinkedList`.
+pub struct Iter<'a, T> {
     storage: &'a Storage,
-    cur_offset: Offset,
+    front_offset: Offset,
+    back_offset: Offset,
+    remaining: u32,
     _phantom: PhantomData<T>,
 }
 
-impl<'a, T: Codec + 'a> Iterator for StorageLinkedListIter<'a, T> {
-    type Item = T;
+impl<'a, T: Codec> Iter<'a, T> {
+    fn read_node(&mut self, offset: Offset) -> Result<Node<T>, Error> {
+        let node = self.storage.read(offset);
+        match node {
+            Ok(node) => {
+                self.remaining -= 1;
+                Ok(node)
+            }
+            Err(err) => {
+                // Stop iterating after the first error
+                self.remaining = 0;
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<'a, T: Codec> Iterator for Iter<'a, T> {
+    type Item = Result<T, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur_offset == 0 {
-            None
-        } else {
-            let node: Node<T> = self.storage.read(self.cur_offset).unwrap();
-            self.cur_offset = node.next;
-            Some(node.item)
+        if self.remaining == 0 {
+            return None;
         }
+        Some(self.read_node(self.front_offset).map(|node| {
+            self.front_offset = node.next;
+            node.item
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
     }
 }
 
-impl<'a, T: Codec> IntoIterator for &'a mut StorageLinkedList<'a, T> {
-    type Item = T;
-    type IntoIter = StorageLinkedListIter<'a, T>;
+impl<'a, T: Codec> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        Some(self.read_node(self.back_offset).map(|node| {
+            self.back_offset = node.prev;
+            node.item
+        }))
+    }
+}
+
+impl<'a, T: Codec> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T: Codec> IntoIterator for &StorageLinkedList<'a, T> {
+    type Item = Result<T, Error>;
+    type IntoIter = Iter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        Self::IntoIter {
-            storage: self.storage,
-            cur_offset: self.header.head_offset,
-            _phantom: PhantomData,
+        self.iter()
+    }
+}
+
+impl<'a, T: Codec> Verify for StorageLinkedList<'a, T> {
+    fn verify(&self, verifier: &mut Verifier) {
+        if !verifier.block(self.offset, Header::PACKED_LEN) {
+            return;
+        }
+        let mut count = 0;
+        let mut prev = 0;
+        let mut offset = self.header.head_offset;
+        while offset != 0 {
+            if !verifier.block(offset, Node::<T>::PACKED_LEN) {
+                return;
+            }
+            let node: Node<T> = match verifier.read(offset) {
+                Some(node) => node,
+                None => return,
+            };
+            if node.prev != prev {
+                verifier.problem(Problem::Corrupted(offset));
+            }
+            count += 1;
+            prev = offset;
+            offset = node.next;
+        }
+        if prev != self.header.tail_offset {
+            verifier.problem(Problem::Corrupted(self.offset));
+        }
+        if count != self.header.count {
+            verifier.problem(Problem::CountMismatch {
+                offset: self.offset,
+                expected: self.header.count,
+                found: count,
+            });
         }
     }
 }
@@ -130,6 +471,10 @@ mod tests {
     use crate::storage::mock::mock_storage;
     use alloc::vec::Vec;
 
+    fn to_vec(list: &StorageLinkedList<i32>) -> Vec<i32> {
+        list.iter().map(|item| item.unwrap()).collect()
+    }
+
     #[test]
     fn test_linked_list() {
         let storage = mock_storage(4 * 1024);
@@ -138,9 +483,158 @@ mod tests {
         list_1.push_back(2).unwrap();
         list_1.push_back(3).unwrap();
 
-        let mut list_2 = StorageLinkedList::<i32>::load(&storage, list_1.offset()).unwrap();
+        let list_2 = StorageLinkedList::<i32>::load(&storage, list_1.offset()).unwrap();
         let iter = list_2.into_iter();
-        let all_items: Vec<i32> = iter.collect();
+        let all_items: Vec<i32> = iter.map(|item| item.unwrap()).collect();
         assert!(all_items.eq(&[1, 2, 3]));
     }
+
+    #[test]
+    fn test_push_and_pop() {
+        let storage = mock_storage(4 * 1024);
+        let mut list = StorageLinkedList::<i32>::create(&storage).unwrap();
+        assert!(list.is_empty());
+        assert_eq!(list.front().unwrap(), None);
+        assert_eq!(list.pop_back().unwrap(), None);
+
+        list.push_front(2).unwrap();
+        list.push_back(3).unwrap();
+        list.push_front(1).unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.front().unwrap(), Some(1));
+        assert_eq!(list.back().unwrap(), Some(3));
+        assert_eq!(to_vec(&list), [1, 2, 3]);
+        assert_eq!(
+            list.iter()
+                .rev()
+                .map(|item| item.unwrap())
+                .collect::<Vec<_>>(),
+            [3, 2, 1]
+        );
+
+        assert_eq!(list.pop_back().unwrap(), Some(3));
+        assert_eq!(list.pop_front().unwrap(), Some(1));
+        assert_eq!(list.pop_front().unwrap(), Some(2));
+        assert_eq!(list.pop_front().unwrap(), None);
+        assert!(list.is_empty());
+
+        // The freed nodes are reused
+        let free_pos = storage.read_u32(storage.free_pos_offset()).unwrap();
+        for i in 0..3 {
+            list.push_back(i).unwrap();
+        }
+        assert_eq!(
+            free_pos,
+            storage.read_u32(storage.free_pos_offset()).unwrap()
+        );
+
+        let list_2 = StorageLinkedList::<i32>::load(&storage, list.offset()).unwrap();
+        assert_eq!(to_vec(&list_2), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_remove_if() {
+        let storage = mock_storage(4 * 1024);
+        let mut list = StorageLinkedList::<i32>::create(&storage).unwrap();
+        for i in 0..10 {
+            list.push_back(i).unwrap();
+        }
+
+        assert_eq!(list.remove_if(|item| item % 3 == 0).unwrap(), 4);
+        assert_eq!(to_vec(&list), [1, 2, 4, 5, 7, 8]);
+        assert_eq!(list.remove_if(|_| true).unwrap(), 6);
+        assert!(list.is_empty());
+        assert_eq!(list.back().unwrap(), None);
+    }
+
+    #[test]
+    fn test_cursor() {
+        let storage = mock_storage(4 * 1024);
+        let mut list = StorageLinkedList::<i32>::create(&storage).unwrap();
+
+        let mut cursor = list.cursor_front_mut();
+        assert_eq!(cursor.current().unwrap(), None);
+        assert!(cursor.set_current(0).is_err());
+        cursor.insert_after(2).unwrap();
+        cursor.insert_before(4).unwrap();
+        cursor.move_next().unwrap();
+        cursor.insert_before(1).unwrap();
+        cursor.insert_after(3).unwrap();
+        assert_eq!(to_vec(&list), [1, 2, 3, 4]);
+
+        let mut cursor = list.cursor_back_mut();
+        cursor.move_prev().unwrap();
+        assert_eq!(cursor.current().unwrap(), Some(3));
+        cursor.set_current(30).unwrap();
+        cursor.move_prev().unwrap();
+        assert_eq!(cursor.remove_current().unwrap(), Some(2));
+        assert_eq!(cursor.current().unwrap(), Some(30));
+        cursor.move_next().unwrap();
+        assert_eq!(cursor.remove_current().unwrap(), Some(4));
+        assert_eq!(cursor.current().unwrap(), None);
+        assert_eq!(cursor.remove_current().unwrap(), None);
+        cursor.move_next().unwrap();
+        assert_eq!(cursor.current().unwrap(), Some(1));
+
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.back().unwrap(), Some(30));
+        assert_eq!(to_vec(&list), [1, 30]);
+    }
+
+    #[test]
+    fn test_migrate() {
+        use super::header::Header;
+        use super::LegacyNode;
+        use crate::storage::codec::Codec;
+        use crate::storage::error::Error;
+        use crate::storage::mock::MockV1Storage;
+        use crate::storage::{Offset, Storage};
+
+        // A version 1 file with a singly linked list of 1, 2 and 3
+        let v1 = MockV1Storage::new(2048);
+        let node_len = LegacyNode::<i32>::PACKED_LEN as Offset;
+        let nodes: Vec<Offset> = (0..3)
+            .map(|i| 1032 + Header::PACKED_LEN as Offset + i * node_len)
+            .collect();
+        let header = Header {
+            count: 3,
+            item_len: 4,
+            head_offset: nodes[0],
+            tail_offset: nodes[2],
+        };
+        let offset = v1.allocate(&header.to_bytes());
+        for (i, item) in (1..=3).enumerate() {
+            let node = LegacyNode {
+                item,
+                next: nodes.get(i + 1).copied().unwrap_or(0),
+            };
+            assert_eq!(v1.allocate(&node.to_bytes()), nodes[i]);
+        }
+        v1.fill_stack_at(0, offset);
+        let storage = Storage::load(v1.into_api()).unwrap();
+        assert!(matches!(
+            StorageLinkedList::<i32>::load(&storage, offset),
+            Err(Error::VersionMismatch(_))
+        ));
+        assert!(matches!(
+            StorageLinkedList::<i64>::migrate(&storage, offset),
+            Err(Error::TypeMismatch)
+        ));
+
+        let mut list = StorageLinkedList::<i32>::migrate(&storage, offset).unwrap();
+        list.push_front(0).unwrap();
+        let list = StorageLinkedList::<i32>::load(&storage, offset).unwrap();
+        assert_eq!(to_vec(&list), [0, 1, 2, 3]);
+        assert_eq!(list.iter().next_back().unwrap().unwrap(), 3);
+        assert_eq!(
+            StorageLinkedList::<i32>::migrate(&storage, offset)
+                .unwrap()
+                .len(),
+            4
+        );
+
+        let mut verifier = storage.verifier().unwrap();
+        verifier.stack::<StorageLinkedList<i32>>(0).unwrap();
+        assert!(verifier.finish().is_empty());
+    }
 }
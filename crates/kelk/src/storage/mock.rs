@@ -1,36 +1,67 @@
 //! Mocking the storage for testing purpose
 
 use super::Storage;
+#[cfg(test)]
+use super::{codec::Codec, Offset};
 use alloc::boxed::Box;
 use alloc::vec::Vec;
-use core::{any::Any, cell::RefCell, result::Result};
+use core::{
+    any::Any,
+    cell::{Cell, RefCell},
+    result::Result,
+};
 use kelk_env::{HostError, StorageAPI};
 
 /// mocks the storage for testing purpose.
 pub struct MockStorage {
     storage: RefCell<Vec<u8>>,
+    max_size: usize,
+    read_calls: Cell<usize>,
+    write_calls: Cell<usize>,
 }
 
 impl MockStorage {
     /// instantiates a new storage mock
     pub fn new(size: usize) -> Self {
+        Self::with_max_size(size, u32::MAX as usize)
+    }
+
+    /// instantiates a new storage mock that can't grow more than `max_size`
+    pub fn with_max_size(size: usize, max_size: usize) -> Self {
         let storage = RefCell::new(alloc::vec![0; size].to_vec());
-        Self { storage }
+        Self {
+            storage,
+            max_size,
+            read_calls: Cell::new(0),
+            write_calls: Cell::new(0),
+        }
+    }
+
+    /// returns the number of read calls
+    pub fn read_calls(&self) -> usize {
+        self.read_calls.get()
+    }
+
+    /// returns the number of write calls
+    pub fn write_calls(&self) -> usize {
+        self.write_calls.get()
     }
 }
 
 impl StorageAPI for MockStorage {
     fn write(&self, offset: u32, data: &[u8]) -> Result<(), HostError> {
-        if offset as usize + data.len() > self.storage.borrow().len() {
+        self.write_calls.set(self.write_calls.get() + 1);
+        let mut storage = self.storage.borrow_mut();
+        let offset = offset as usize;
+        if offset + data.len() > storage.len() {
             return Err(HostError { code: -1 });
         }
-        for (i, d) in data.iter().enumerate() {
-            self.storage.borrow_mut()[i + offset as usize] = *d;
-        }
+        storage[offset..offset + data.len()].copy_from_slice(data);
         Ok(())
     }
 
     fn read(&self, offset: u32, length: u32) -> Result<Vec<u8>, HostError> {
+        self.read_calls.set(self.read_calls.get() + 1);
         if (offset + length) as usize > self.storage.borrow().len() {
             return Err(HostError { code: -1 });
         }
@@ -38,6 +69,21 @@ impl StorageAPI for MockStorage {
         Ok(c.into())
     }
 
+    fn size(&self) -> Result<u32, HostError> {
+        Ok(self.storage.borrow().len() as u32)
+    }
+
+    fn grow(&self, additional: u32) -> Result<u32, HostError> {
+        let mut storage = self.storage.borrow_mut();
+        let size = storage
+            .len()
+            .checked_add(additional as usize)
+            .filter(|size| *size <= self.max_size)
+            .ok_or(HostError { code: -1 })?;
+        storage.resize(size, 0);
+        Ok(size as u32)
+    }
+
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
@@ -47,3 +93,44 @@ impl StorageAPI for MockStorage {
 pub fn mock_storage(storage_size: usize) -> Storage {
     Storage::create(Box::new(MockStorage::new(storage_size))).unwrap()
 }
+
+/// mocks a storage file of version 1, that is written by `Storage::create` and
+/// `Storage::allocate` of that version.
+#[cfg(test)]
+pub(crate) struct MockV1Storage(MockStorage);
+
+#[cfg(test)]
+impl MockV1Storage {
+    /// instantiates a new storage file of version 1
+    pub fn new(size: usize) -> Self {
+        let v1 = MockV1Storage(MockStorage::new(size));
+        v1.write(0, &[1, 0]); // version = 1
+        v1.write(2, &[0, 1]); // stack size = 256
+        v1.write(4, &[0; 256 * 4]); // stack
+        v1.write(1028, &[0, 0, 4, 8]); // free storage pos
+        v1
+    }
+
+    /// allocates the exact length of `data` and writes it
+    pub fn allocate(&self, data: &[u8]) -> Offset {
+        let free_pos = Offset::from_bytes(&self.0.read(1028, 4).unwrap());
+        self.write(1028, &(free_pos + data.len() as Offset).to_bytes());
+        self.write(free_pos, data);
+        free_pos
+    }
+
+    /// writes `data` at the given `offset`
+    pub fn write(&self, offset: Offset, data: &[u8]) {
+        self.0.write(offset, data).unwrap();
+    }
+
+    /// writes the `offset` into the stack at the given `stack_index`
+    pub fn fill_stack_at(&self, stack_index: u16, offset: Offset) {
+        self.write(4 + stack_index as Offset * 4, &offset.to_bytes());
+    }
+
+    /// returns the storage file
+    pub fn into_api(self) -> Box<dyn StorageAPI> {
+        Box::new(self.0)
+    }
+}
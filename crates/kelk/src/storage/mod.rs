@@ -1,21 +1,43 @@
 //! Modules definition for storage libraries
 
+pub mod boxed;
 pub mod bst;
+pub mod btree;
+pub mod cache;
+pub mod cell;
+mod checksum;
 pub mod codec;
+pub mod dyn_vec;
 pub mod error;
+pub mod hash_map;
+pub mod header;
+mod journal;
 pub mod linked_list;
 pub mod mock;
+pub mod multi_map;
+pub mod root;
+pub mod set;
+pub mod state;
 pub mod str;
 pub mod vec;
+pub mod verify;
 
 /// is an alias for representing the offset of the allocated space inside the storage file.
 pub type Offset = u32;
 
+use self::cache::{CacheConfig, PageCache};
+use self::checksum::Tracker;
 use self::codec::Codec;
 use self::error::Error;
+use self::header::{
+    FormatConfig, Header, FEATURE_BLOCK_CHECKSUMS, FEATURE_FREE_LISTS, FEATURE_JOURNALING,
+    META_LEN, V1_DATA_OFFSET, V1_FREE_POS_OFFSET, V1_STACK_OFFSET,
+};
+use self::journal::Journal;
 use alloc::boxed::Box;
 use alloc::string::ToString;
 use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
 use core::result::Result;
 use kelk_env::StorageAPI;
 
@@ -41,79 +63,422 @@ macro_rules! impl_num {
     };
 }
 
+/// The number of size classes.
+/// The size class `n` holds blocks of `MIN_BLOCK_LEN << n` bytes.
+const SIZE_CLASSES: usize = 29;
+
+/// The minimum length of an allocated block.
+/// A freed block should be large enough to keep the offset of the next freed block.
+const MIN_BLOCK_LEN: usize = 8;
+
+/// returns the size class for the given length.
+fn size_class(length: usize) -> Result<usize, Error> {
+    let class = length
+        .max(MIN_BLOCK_LEN)
+        .checked_next_power_of_two()
+        .ok_or(Error::OutOfCapacity)?
+        .trailing_zeros() as usize
+        - MIN_BLOCK_LEN.trailing_zeros() as usize;
+    if class >= SIZE_CLASSES {
+        return Err(Error::OutOfCapacity);
+    }
+    Ok(class)
+}
+
+/// returns the length of blocks in the given size class.
+fn block_len(class: usize) -> u32 {
+    (MIN_BLOCK_LEN << class) as u32
+}
+
+/// Returns the 32-bit FNV-1a hash of the given bytes.
+pub(crate) fn hash(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// Returns the CRC-32 (IEEE) of the given bytes.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
 /// Storage object
 pub struct Storage {
     /// Storage APIs that are provided by the host
     api: Box<dyn StorageAPI>,
 
-    stack_size: u16,
+    /// The header of the storage file
+    header: Header,
+
+    /// The buffered writes of the running transactions
+    journal: RefCell<Journal>,
+
+    /// The cached pages of the storage file
+    cache: RefCell<PageCache>,
+
+    /// The size of the storage file, that is queried from the host on the first use
+    size: Cell<Option<u32>>,
+
+    /// The written ranges that their block checksums are not updated yet
+    tracker: Tracker,
+}
+
+/// The space usage of the storage file in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// The size of the storage file
+    pub size: u32,
+    /// The space that is allocated so far, including the freed blocks
+    pub allocated: u32,
+    /// The space of the freed blocks, that can be reused by the next allocations
+    pub freed: u32,
+    /// The space after the allocated space, that is not allocated yet
+    pub available: u32,
+}
+
+impl Usage {
+    /// Returns the free space of the storage file, including the freed blocks.
+    pub fn free_space(&self) -> u32 {
+        self.freed + self.available
+    }
 }
 
 impl Storage {
-    /// creates a new instance of storage
+    /// creates a new instance of storage with the default configuration
     pub fn create(api: Box<dyn StorageAPI>) -> Result<Self, Error> {
-        api.write(0, &[1, 0])?; // version = 1
-        api.write(2, &[0, 1])?; // stack size = 256
-        api.write(4, &[0; 256 * 4])?; // stack
-        api.write(1028, &[0, 0, 4, 8])?; // free storage pos
+        Self::create_with_config(api, FormatConfig::default())
+    }
 
-        let storage = Storage {
-            api,
-            stack_size: 256,
-        };
-        // let freed = StorageLinkedList::create(&storage, 0)?;
-        // storage.freed = Some(freed);
+    /// creates a new instance of storage with the given stack size and feature flags.
+    /// They are kept in the header of the storage file, and they are honoured by `load`.
+    /// The feature flags are defined in the [`header`] module.
+    pub fn create_with_config(
+        api: Box<dyn StorageAPI>,
+        config: FormatConfig,
+    ) -> Result<Self, Error> {
+        let header = Header::new(config)?;
+        let data_offset = header.data_offset;
+        let size = api.size()?;
+        if size < data_offset {
+            api.grow(data_offset - size)
+                .map_err(|_| Error::OutOfCapacity)?;
+        }
+        // Stack and allocator metadata
+        api.write(
+            header.stack_offset,
+            &alloc::vec![0; (data_offset - header.stack_offset) as usize],
+        )?;
+        api.write(header.meta_offset, &data_offset.to_bytes())?; // free storage pos
+
+        let mut storage = Storage::new(api, header);
+        if config.features & FEATURE_BLOCK_CHECKSUMS != 0 {
+            storage.header.checksums_offset = storage.create_checksums()?;
+        }
+        storage.write_header()?;
 
         Ok(storage)
     }
 
-    ///
+    /// loads the storage from an existing storage file.
+    /// The storage files of version 1 are upgraded to the current version.
     pub fn load(api: Box<dyn StorageAPI>) -> Result<Self, Error> {
-        let ver = api.read(0, 2)?;
-        let stack_size = api.read(2, 2)?;
-        if !ver.eq(&[1, 0]) || !stack_size.eq(&[0, 1]) {
-            return Err(Error::GenericError("invalid storage file".to_string()));
+        let bytes = api.read(0, Header::PACKED_LEN as u32)?;
+        if bytes[..4] == [1, 0, 0, 1] {
+            return Self::upgrade_v1(api);
         }
+        let header = Header::decode(&bytes)?;
+
+        Ok(Storage::new(api, header))
+    }
+
+    /// Returns the format version of the storage file.
+    pub fn format_version(&self) -> u16 {
+        self.header.version
+    }
+
+    /// Returns the number of the offsets that can be kept in the root stack.
+    pub fn stack_size(&self) -> u16 {
+        self.header.stack_size
+    }
+
+    /// Returns the feature flags of the storage file.
+    pub fn features(&self) -> u32 {
+        self.header.features
+    }
+
+    /// Returns true if the given feature is enabled for the storage file.
+    pub fn has_feature(&self, feature: u32) -> bool {
+        self.header.features & feature != 0
+    }
 
-        let storage = Storage {
+    fn new(api: Box<dyn StorageAPI>, header: Header) -> Self {
+        Storage {
             api,
-            stack_size: 256,
-        };
+            header,
+            journal: RefCell::new(Journal::default()),
+            cache: RefCell::new(PageCache::new(CacheConfig::default())),
+            size: Cell::new(None),
+            tracker: Tracker::default(),
+        }
+    }
+
+    // Upgrades a storage file of version 1. The allocated blocks are kept at their place,
+    // and the allocator metadata is written after them. Then the root stack is moved into
+    // a new block, so the header can be written at its place.
+    fn upgrade_v1(api: Box<dyn StorageAPI>) -> Result<Self, Error> {
+        let size = api.size()?;
+        let meta_offset = u32::try_from_bytes(&api.read(V1_FREE_POS_OFFSET, 4)?)?;
+        if meta_offset < V1_DATA_OFFSET || meta_offset > size {
+            return Err(Error::InvalidOffset(meta_offset));
+        }
+        let free_pos = meta_offset
+            .checked_add(META_LEN)
+            .ok_or(Error::OutOfCapacity)?;
+        if size < free_pos {
+            api.grow(free_pos - size)
+                .map_err(|_| Error::OutOfCapacity)?;
+        }
+        // Empty free lists and no root registry
+        api.write(meta_offset, &alloc::vec![0; META_LEN as usize])?;
+        api.write(meta_offset, &free_pos.to_bytes())?;
+
+        let mut storage = Storage::new(api, Header::upgrade_v1(meta_offset));
+        let stack_len = storage.header.stack_size as usize * Offset::PACKED_LEN;
+        let stack = storage.read_bytes(V1_STACK_OFFSET, stack_len as u32)?;
+        let stack_offset = storage.allocate(stack_len)?;
+        storage.write_bytes(stack_offset, &stack)?;
+        storage.header.stack_offset = stack_offset;
+        storage.write_header()?;
 
         Ok(storage)
     }
 
-    pub(crate) fn api_mut(&mut self) -> &mut Box<dyn StorageAPI> {
-        &mut self.api
+    fn write_header(&mut self) -> Result<(), Error> {
+        let bytes = self.header.encode();
+        self.write_bytes(0, &bytes)
+    }
+
+    /// Returns the offset of the free storage position.
+    pub(crate) fn free_pos_offset(&self) -> Offset {
+        self.header.meta_offset
+    }
+
+    /// Returns the offset of the free list for the given size class.
+    /// Each free list keeps the offset of the first freed block of its size class.
+    pub(crate) fn free_list_offset(&self, class: usize) -> Offset {
+        self.header.meta_offset + ((class + 1) * 4) as Offset
+    }
+
+    /// Returns the offset of the root registry slot.
+    /// It keeps the offset of the registry of named roots, or zero if it is not created yet.
+    pub(crate) fn roots_offset(&self) -> Offset {
+        self.free_list_offset(SIZE_CLASSES)
+    }
+
+    /// Returns the offset of the first allocatable byte in the storage file.
+    pub(crate) fn data_offset(&self) -> Offset {
+        self.header.data_offset
+    }
+
+    /// Returns true if the block at the given offset is allocated before the storage file
+    /// is upgraded from version 1. The legacy blocks are allocated by their exact length.
+    pub(crate) fn is_legacy(&self, offset: Offset) -> bool {
+        offset >= self.data_offset() && offset < self.free_pos_offset()
+    }
+
+    /// Returns the host APIs. The cache is flushed and dropped, because the storage file
+    /// can be changed directly.
+    pub(crate) fn api_mut(&mut self) -> Result<&mut Box<dyn StorageAPI>, Error> {
+        self.flush()?;
+        self.cache = RefCell::new(PageCache::new(self.cache_config()));
+        Ok(&mut self.api)
     }
 
+    /// Returns the configuration of the page cache.
+    pub fn cache_config(&self) -> CacheConfig {
+        self.cache.borrow().config()
+    }
+
+    /// Sets the configuration of the page cache. The dirty pages are flushed before.
+    pub fn set_cache_config(&mut self, config: CacheConfig) -> Result<(), Error> {
+        if config.page_size == 0 {
+            return Err(Error::GenericError("invalid page size".to_string()));
+        }
+        self.flush()?;
+        self.cache = RefCell::new(PageCache::new(config));
+        Ok(())
+    }
+
+    /// Writes the dirty pages of the cache into the storage file.
     ///
+    /// The storage is flushed at the end of the entry functions that are defined
+    /// by `kelk_derive`.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.update_checksums()?;
+        Ok(self.cache.borrow_mut().flush(self.api.as_ref())?)
+    }
+
+    /// allocates `length` bytes in the storage file and returns the offset of the allocated space.
+    ///
+    /// If there is no space in the storage file, the host is requested to grow it,
+    /// and if it can't, `OutOfCapacity` error is returned.
+    ///
+    /// The requested length is rounded up to its size class. If there is a freed block
+    /// in that class it will be reused, otherwise a new block is taken from the end of the
+    /// allocated space. The freed blocks are not reused if `FEATURE_FREE_LISTS` is not set.
     pub fn allocate(&self, length: usize) -> Result<Offset, Error> {
-        let cur_free_pos = self.read_u32(1028)?;
-        let next_free_pos = cur_free_pos + length as u32;
+        let class = size_class(length)?;
+        let head_offset = self.free_list_offset(class);
+        let head = match self.has_feature(FEATURE_FREE_LISTS) {
+            true => self.read_u32(head_offset)?,
+            false => 0,
+        };
+        if head != 0 {
+            // Popping the freed block from the free list
+            let next = self.read_u32(head)?;
+            self.write_u32(head_offset, &next)?;
+            self.track_allocate(head, block_len(class))?;
+
+            return Ok(head);
+        }
+
+        let cur_free_pos = self.read_u32(self.free_pos_offset())?;
+        let next_free_pos = cur_free_pos
+            .checked_add(block_len(class))
+            .ok_or(Error::OutOfCapacity)?;
+        self.ensure_size(next_free_pos)?;
 
         // Updating allocation pos
-        self.write_u32(1028, &next_free_pos)?;
+        self.write_u32(self.free_pos_offset(), &next_free_pos)?;
+        self.track_allocate(cur_free_pos, block_len(class))?;
 
         Ok(cur_free_pos)
     }
 
+    /// frees the space at the given `offset` that was allocated with the given `length`,
+    /// so it can be reused by the next allocations.
+    ///
+    /// The `length` should be the same length that is passed to `allocate`, and the block
+    /// should not be freed before. Freeing the last freed block of its size class again
+    /// returns an error, but the other double frees are not detected, and they are reported
+    /// as cycles by the verifier.
+    /// If `FEATURE_FREE_LISTS` is not set, the block is only checked and it is not reused.
+    /// The legacy blocks of an upgraded storage file are not reused, since they are not
+    /// aligned to the size classes.
+    pub fn free(&self, offset: Offset, length: usize) -> Result<(), Error> {
+        if self.is_legacy(offset) {
+            if offset as u64 + length as u64 > self.free_pos_offset() as u64 {
+                return Err(Error::InvalidOffset(offset));
+            }
+            return Ok(());
+        }
+        let class = size_class(length)?;
+        let free_pos = self.read_u32(self.free_pos_offset())?;
+        let end = offset.saturating_add(block_len(class));
+        let meta_offset = self.free_pos_offset();
+        if offset < self.data_offset()
+            || end > free_pos
+            || (offset < meta_offset + META_LEN && end > meta_offset)
+        {
+            return Err(Error::InvalidOffset(offset));
+        }
+        if !self.has_feature(FEATURE_FREE_LISTS) {
+            return Ok(());
+        }
+        let head_offset = self.free_list_offset(class);
+        let head = self.read_u32(head_offset)?;
+        if head == offset {
+            return Err(Error::InvalidOffset(offset));
+        }
+        self.track_free(offset, block_len(class))?;
+
+        // Pushing the block into the free list
+        self.write_u32(offset, &head)?;
+        self.write_u32(head_offset, &offset)
+    }
+
+    /// Returns the size of the storage file in bytes.
+    pub fn size(&self) -> Result<u32, Error> {
+        match self.size.get() {
+            Some(size) => Ok(size),
+            None => {
+                let size = self.api.size()?;
+                self.size.set(Some(size));
+                Ok(size)
+            }
+        }
+    }
+
+    /// Returns the space usage of the storage file.
+    pub fn usage(&self) -> Result<Usage, Error> {
+        let size = self.size()?;
+        let free_pos = self.read_u32(self.free_pos_offset())?;
+        let allocated = free_pos - self.data_offset();
+
+        let mut freed = 0;
+        for class in 0..SIZE_CLASSES {
+            let mut offset = self.read_u32(self.free_list_offset(class))?;
+            while offset != 0 {
+                freed += block_len(class);
+                // A free list can't be larger than the allocated space, unless it has a cycle.
+                if freed > allocated {
+                    return Err(Error::InvalidOffset(offset));
+                }
+                offset = self.read_u32(offset)?;
+            }
+        }
+
+        Ok(Usage {
+            size,
+            allocated,
+            freed,
+            available: size.saturating_sub(free_pos),
+        })
+    }
+
+    // Makes sure that the storage file is at least `end` bytes,
+    // otherwise it requests the host to grow the storage file.
+    fn ensure_size(&self, end: u32) -> Result<(), Error> {
+        let size = self.size()?;
+        if end <= size {
+            return Ok(());
+        }
+        // Trying to double the size of the storage file first, to reduce the grow requests.
+        let additional = end - size;
+        let new_size = self
+            .api
+            .grow(additional.max(size))
+            .or_else(|_| self.api.grow(additional))
+            .map_err(|_| Error::OutOfCapacity)?;
+        self.size.set(Some(new_size));
+        if new_size < end {
+            return Err(Error::OutOfCapacity);
+        }
+        Ok(())
+    }
+
     fn stack_offset(&self, stack_index: u16) -> Result<Offset, Error> {
-        if stack_index > self.stack_size {
+        if stack_index >= self.header.stack_size {
             return Err(Error::StackOverflow);
         }
 
-        // stack_offset = (stack_index * 4) + 4
-        let header_size = 4;
-        Ok(((stack_index as usize * Offset::PACKED_LEN) + header_size) as Offset)
+        Ok(self.header.stack_offset + (stack_index as usize * Offset::PACKED_LEN) as Offset)
     }
 
-    ///
+    /// writes the `offset` into the stack at the given `stack_index`.
     pub fn fill_stack_at(&self, stack_index: u16, offset: Offset) -> Result<(), Error> {
         self.write_u32(self.stack_offset(stack_index)?, &offset)
     }
 
-    ///
+    /// reads the offset stored in the stack at the given `stack_index`.
     pub fn read_stack_at(&self, stack_index: u16) -> Result<Offset, Error> {
         self.read_u32(self.stack_offset(stack_index)?)
     }
@@ -132,12 +497,81 @@ impl Storage {
 
     impl_num!(bool, 1, read_bool, write_bool);
 
+    /// Runs `f` inside a transaction.
+    ///
+    /// The writes inside the transaction are buffered in memory. They are flushed into the
+    /// storage file if `f` returns `Ok`, and discarded if it returns `Err`.
+    /// Transactions can be nested. A nested transaction works as a savepoint: rolling it back
+    /// only discards its own writes, and committing it keeps the writes in the parent
+    /// transaction until the parent is committed.
+    ///
+    /// ```ignore
+    /// storage.transaction(|tx| {
+    ///     let mut balances = tx.root::<StorageBST<Address, i64>>("balances")?;
+    ///     balances.insert(from, from_balance - amount)?;
+    ///     balances.insert(to, to_balance + amount)?;
+    ///     Ok(())
+    /// })?;
+    /// ```
+    pub fn transaction<R, E, F>(&self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&Storage) -> Result<R, E>,
+        E: From<Error>,
+    {
+        self.journal.borrow_mut().begin();
+        match f(self) {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.journal.borrow_mut().rollback();
+                Err(err)
+            }
+        }
+    }
+
+    /// Runs `f` atomically. It is the same as `transaction`, but `f` doesn't take the storage,
+    /// so it can borrow the context that holds it.
+    /// If the writes can't be flushed into the storage file, the error is returned.
+    /// If `FEATURE_JOURNALING` is not set, `f` writes directly into the storage file,
+    /// and its writes are kept even if it returns `Err`.
+    ///
+    /// The entry functions that are defined by `kelk_derive` are run atomically.
+    pub fn atomic<R, E, F>(&self, f: F) -> Result<R, E>
+    where
+        F: FnOnce() -> Result<R, E>,
+        E: From<Error>,
+    {
+        if !self.has_feature(FEATURE_JOURNALING) {
+            return f();
+        }
+        self.transaction(|_| f())
+    }
+
+    /// Returns true if there is a running transaction.
+    pub fn in_transaction(&self) -> bool {
+        self.journal.borrow().is_active()
+    }
+
+    // Commits the innermost transaction, and flushes the writes if it is the outermost one.
+    fn commit(&self) -> Result<(), Error> {
+        let layer = self.journal.borrow_mut().commit();
+        if let Some(layer) = layer {
+            let mut cache = self.cache.borrow_mut();
+            for (offset, data) in layer {
+                cache.write(self.api.as_ref(), offset, &data)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Reads `T` from the storage file at the given `offset`.
     /// Note that `T` should be `Codec`.
     #[inline]
     pub(crate) fn read<T: Codec>(&self, offset: u32) -> Result<T, Error> {
-        let data = self.api.read(offset, T::PACKED_LEN as u32)?;
-        let value = T::from_bytes(&data);
+        let data = self.read_bytes(offset, T::PACKED_LEN as u32)?;
+        let value = T::try_from_bytes(&data)?;
         Ok(value)
     }
 
@@ -146,24 +580,38 @@ impl Storage {
     #[inline]
     pub(crate) fn write<T: Codec>(&self, offset: Offset, value: &T) -> Result<(), Error> {
         let data = value.to_bytes();
-        Ok(self.api.write(offset, &data)?)
+        self.write_bytes(offset, &data)
     }
 
     /// Reads slice of bytes of size `length` from the storage file at the given `offset`.
+    /// The bytes are patched by the buffered writes of the running transactions.
     #[inline]
     pub(crate) fn read_bytes(&self, offset: u32, length: u32) -> Result<Vec<u8>, Error> {
-        Ok(self.api.read(offset, length)?)
+        let mut data = self
+            .cache
+            .borrow_mut()
+            .read(self.api.as_ref(), offset, length)?;
+        self.journal.borrow().patch(offset, &mut data);
+        Ok(data)
     }
 
     /// Writes bytes slice to the storage file at the given `offset`.
+    /// Inside a transaction, the bytes are buffered until the transaction is committed.
     #[inline]
     pub(crate) fn write_bytes(&self, offset: Offset, data: &[u8]) -> Result<(), Error> {
-        Ok(self.api.write(offset, data)?)
+        self.track_write(offset, data.len());
+        if self.journal.borrow_mut().write(offset, data) {
+            return Ok(());
+        }
+        Ok(self
+            .cache
+            .borrow_mut()
+            .write(self.api.as_ref(), offset, data)?)
     }
 }
 
 #[cfg(test)]
-pub mod tests {
+mod tests {
     use crate::storage::codec::Codec;
     use crate::storage::mock::mock_storage;
     use kelk_derive::Codec;
@@ -226,6 +674,79 @@ pub mod tests {
 
         assert!(storage.read_bool(offset1).unwrap());
         assert!(!storage.read_bool(offset2).unwrap());
+
+        // Corrupted data
+        storage.write_u8(offset1, &2).unwrap();
+        assert!(matches!(
+            storage.read_bool(offset1),
+            Err(super::Error::CodecError(
+                super::codec::CodecError::InvalidData
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_allocate_and_free() {
+        let storage = mock_storage(1024 * 1024);
+
+        let offset1 = storage.allocate(3).unwrap();
+        let offset2 = storage.allocate(8).unwrap();
+        let offset3 = storage.allocate(100).unwrap();
+        assert_eq!(offset2, offset1 + 8);
+        assert_eq!(offset3, offset2 + 8);
+
+        storage.free(offset2, 8).unwrap();
+        storage.free(offset1, 3).unwrap();
+        assert!(matches!(
+            storage.free(offset1, 3),
+            Err(super::Error::InvalidOffset(offset)) if offset == offset1
+        ));
+
+        // Freed blocks are reused in LIFO order
+        assert_eq!(storage.allocate(5).unwrap(), offset1);
+        assert_eq!(storage.allocate(1).unwrap(), offset2);
+        assert_eq!(storage.allocate(1).unwrap(), offset3 + 128);
+
+        storage.free(offset3, 100).unwrap();
+        assert_eq!(storage.allocate(65).unwrap(), offset3);
+
+        assert!(storage.free(0, 8).is_err());
+        assert!(storage.free(offset3 + 1024, 8).is_err());
+        assert!(storage.allocate(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_capacity() {
+        use super::Storage;
+        use crate::storage::error::Error;
+        use crate::storage::mock::MockStorage;
+        use alloc::boxed::Box;
+
+        let storage = Storage::create(Box::new(MockStorage::with_max_size(0, 4096))).unwrap();
+        let data_offset = storage.data_offset();
+        assert_eq!(storage.size().unwrap(), data_offset);
+
+        // Growing the storage file
+        let offset1 = storage.allocate(1024).unwrap();
+        assert_eq!(storage.size().unwrap(), data_offset * 2);
+        let offset2 = storage.allocate(1024).unwrap();
+        assert_eq!(storage.size().unwrap(), data_offset + 2048);
+        assert!(matches!(storage.allocate(1024), Err(Error::OutOfCapacity)));
+
+        let usage = storage.usage().unwrap();
+        assert_eq!(usage.size, data_offset + 2048);
+        assert_eq!(usage.allocated, 2048);
+        assert_eq!(usage.free_space(), 0);
+
+        storage.free(offset1, 1024).unwrap();
+        storage.allocate(8).unwrap();
+        let usage = storage.usage().unwrap();
+        assert_eq!(usage.freed, 1024);
+        assert_eq!(usage.available, 0);
+        assert_eq!(usage.free_space(), 1024);
+
+        storage.free(offset2, 1024).unwrap();
+        assert_eq!(storage.allocate(1024).unwrap(), offset2);
     }
 
     #[test]
@@ -252,4 +773,342 @@ pub mod tests {
         let foo_2 = storage.read::<Test>(offset).unwrap();
         assert_eq!(foo_1, foo_2);
     }
+
+    #[test]
+    fn test_transaction() {
+        use crate::storage::bst::StorageBST;
+        use crate::storage::error::Error;
+
+        let storage = mock_storage(1024 * 1024);
+        let offset = storage.allocate(u32::PACKED_LEN).unwrap();
+        storage.write_u32(offset, &1).unwrap();
+
+        // Rolling back the transaction
+        let res: Result<(), Error> = storage.transaction(|tx| {
+            tx.write_u32(offset, &2)?;
+            assert_eq!(tx.read_u32(offset)?, 2);
+            Err(Error::GenericError("rollback".into()))
+        });
+        assert!(res.is_err());
+        assert_eq!(storage.read_u32(offset).unwrap(), 1);
+
+        // Committing the transaction
+        let bst_offset = storage
+            .transaction::<_, Error, _>(|tx| {
+                let mut bst = StorageBST::<u32, i64>::create(tx)?;
+                bst.insert(1, 100)?;
+                tx.write_u32(offset, &3)?;
+                assert!(tx.in_transaction());
+                Ok(bst.offset())
+            })
+            .unwrap();
+        assert!(!storage.in_transaction());
+        assert_eq!(storage.read_u32(offset).unwrap(), 3);
+        let bst = StorageBST::<u32, i64>::load(&storage, bst_offset).unwrap();
+        assert_eq!(bst.find(&1).unwrap(), Some(100));
+
+        // Nested transactions
+        storage
+            .transaction::<_, Error, _>(|tx| {
+                tx.write_u32(offset, &4)?;
+                let res: Result<(), Error> = tx.transaction(|tx| {
+                    tx.write_u32(offset, &5)?;
+                    Err(Error::GenericError("rollback".into()))
+                });
+                assert!(res.is_err());
+                assert_eq!(tx.read_u32(offset)?, 4);
+
+                tx.transaction::<_, Error, _>(|tx| tx.write_u32(offset, &6))?;
+                assert_eq!(tx.read_u32(offset)?, 6);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(storage.read_u32(offset).unwrap(), 6);
+
+        // Running atomically
+        let res: Result<(), Error> = storage.atomic(|| {
+            storage.write_u32(offset, &7)?;
+            Err(Error::GenericError("rollback".into()))
+        });
+        assert!(res.is_err());
+        assert_eq!(storage.read_u32(offset).unwrap(), 6);
+
+        // Failing to flush the writes
+        let res: Result<(), Error> = storage.atomic(|| storage.write_u32(1024 * 1024, &8));
+        assert!(res.is_err());
+        assert!(!storage.in_transaction());
+    }
+
+    #[test]
+    fn test_cache() {
+        use crate::storage::bst::StorageBST;
+        use crate::storage::mock::MockStorage;
+        use kelk_env::StorageAPI;
+
+        let mut storage = mock_storage(1024 * 1024);
+        let mut bst = StorageBST::<u32, u32>::create(&storage).unwrap();
+        for i in 0..100 {
+            bst.insert(i, i).unwrap();
+        }
+        let bst_offset = bst.offset();
+        let free_pos_offset = storage.free_pos_offset();
+        let free_pos = storage.read_u32(free_pos_offset).unwrap();
+
+        let mock = storage
+            .api_mut()
+            .unwrap()
+            .as_any()
+            .downcast_mut::<MockStorage>()
+            .unwrap();
+        assert!(mock.read_calls() < 20);
+        assert!(mock.write_calls() < 20);
+        assert_eq!(mock.read(free_pos_offset, 4).unwrap(), free_pos.to_bytes());
+
+        let bst = StorageBST::<u32, u32>::load(&storage, bst_offset).unwrap();
+        assert_eq!(bst.find(&50).unwrap(), Some(50));
+
+        assert!(storage
+            .set_cache_config(super::CacheConfig {
+                page_size: 0,
+                capacity: 0
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(super::crc32(b""), 0);
+        assert_eq!(super::crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_format() {
+        use super::header::{FormatConfig, FEATURE_COMPRESSION, FORMAT_VERSION};
+        use super::Storage;
+        use crate::storage::mock::MockStorage;
+        use crate::storage::vec::StorageVec;
+        use alloc::boxed::Box;
+
+        let mut storage = mock_storage(1024 * 1024);
+        let mut vec = storage.root::<StorageVec<u32>>("vec").unwrap();
+        vec.push(1).unwrap();
+        storage.fill_stack_at(0, vec.offset()).unwrap();
+        let api = core::mem::replace(storage.api_mut().unwrap(), Box::new(MockStorage::new(0)));
+        assert_eq!(&api.read(0, 4).unwrap(), b"KELK");
+
+        let mut storage = Storage::load(api).unwrap();
+        assert_eq!(storage.format_version(), FORMAT_VERSION);
+        let vec = storage.root::<StorageVec<u32>>("vec").unwrap();
+        assert_eq!(vec.get(0).unwrap(), Some(1));
+        assert_eq!(storage.read_stack_at(0).unwrap(), vec.offset());
+
+        // Corrupted header
+        let api = storage.api_mut().unwrap();
+        api.write(6, &[0, 2]).unwrap();
+        let api = core::mem::replace(api, Box::new(MockStorage::new(0)));
+        assert!(Storage::load(api).is_err());
+
+        // Unsupported features
+        let api = Box::new(MockStorage::new(1024 * 1024));
+        let config = FormatConfig {
+            features: FEATURE_COMPRESSION,
+            ..Default::default()
+        };
+        assert!(Storage::create_with_config(api, config).is_err());
+    }
+
+    #[test]
+    fn test_upgrade_v1() {
+        use super::header::{FORMAT_VERSION, META_LEN, V1_DATA_OFFSET};
+        use super::Storage;
+        use crate::storage::error::Error;
+        use crate::storage::mock::{MockStorage, MockV1Storage};
+        use alloc::boxed::Box;
+
+        // Version 1 file, that is full and should grow for the metadata
+        let v1 = MockV1Storage::new(1050);
+        let offset1 = v1.allocate(&7u32.to_bytes());
+        let offset2 = v1.allocate(&[1, 2, 3]);
+        let offset3 = v1.allocate(&9u64.to_bytes());
+        v1.fill_stack_at(0, offset1);
+        v1.fill_stack_at(5, offset3);
+        assert_eq!((offset1, offset2, offset3), (1032, 1036, 1039));
+
+        let mut storage = Storage::load(v1.into_api()).unwrap();
+        assert_eq!(storage.format_version(), FORMAT_VERSION);
+        assert_eq!(storage.data_offset(), V1_DATA_OFFSET);
+        assert_eq!(storage.free_pos_offset(), 1047);
+        assert_eq!(storage.read_stack_at(0).unwrap(), offset1);
+        assert_eq!(storage.read_stack_at(5).unwrap(), offset3);
+
+        // The new blocks are allocated after the metadata
+        let offset4 = storage.allocate(8).unwrap();
+        assert!(offset4 >= 1047 + META_LEN);
+        storage.write_u64(offset4, &11).unwrap();
+        assert!(storage.free(1047, 8).is_err());
+        assert!(storage.verify().unwrap().is_empty());
+
+        let api = core::mem::replace(storage.api_mut().unwrap(), Box::new(MockStorage::new(0)));
+        let storage = Storage::load(api).unwrap();
+        assert_eq!(storage.format_version(), FORMAT_VERSION);
+        assert_eq!(storage.read_u32(offset1).unwrap(), 7);
+        assert_eq!(storage.read_bytes(offset2, 3).unwrap(), [1, 2, 3]);
+        assert_eq!(storage.read_u64(offset3).unwrap(), 9);
+        assert_eq!(storage.read_u64(offset4).unwrap(), 11);
+        assert!(storage.verify().unwrap().is_empty());
+
+        // Invalid free storage position
+        let v1 = MockV1Storage::new(2048);
+        v1.write(1028, &4000u32.to_bytes());
+        assert!(matches!(
+            Storage::load(v1.into_api()),
+            Err(Error::InvalidOffset(4000))
+        ));
+    }
+
+    #[test]
+    fn test_free_v1_blocks() {
+        use super::Storage;
+        use crate::storage::mock::MockV1Storage;
+
+        let v1 = MockV1Storage::new(2048);
+        let offset1 = v1.allocate(&[1, 2, 3]);
+        let offset2 = v1.allocate(&[4, 5, 6]);
+        let storage = Storage::load(v1.into_api()).unwrap();
+
+        // Legacy blocks are registered by their exact length
+        let mut verifier = storage.verifier().unwrap();
+        assert!(verifier.block(offset1, 3));
+        assert!(verifier.block(offset2, 3));
+        assert!(verifier.finish().is_empty());
+
+        // Legacy blocks are leaked
+        storage.free(offset1, 3).unwrap();
+        assert!(storage.free(offset2, 8).is_err());
+        assert_eq!(storage.usage().unwrap().freed, 0);
+        assert!(storage.allocate(3).unwrap() > storage.free_pos_offset());
+        assert_eq!(storage.read_bytes(offset2, 3).unwrap(), [4, 5, 6]);
+    }
+
+    #[test]
+    fn test_features() {
+        use super::header::{FormatConfig, FEATURE_FREE_LISTS, FEATURE_JOURNALING};
+        use super::Storage;
+        use crate::storage::error::Error;
+        use crate::storage::mock::MockStorage;
+        use alloc::boxed::Box;
+
+        // Without free lists the freed blocks are not reused
+        let api = Box::new(MockStorage::new(1024 * 1024));
+        let config = FormatConfig {
+            features: FEATURE_JOURNALING,
+            ..Default::default()
+        };
+        let storage = Storage::create_with_config(api, config).unwrap();
+        assert!(!storage.has_feature(FEATURE_FREE_LISTS));
+        let offset1 = storage.allocate(8).unwrap();
+        storage.free(offset1, 8).unwrap();
+        storage.free(offset1, 8).unwrap();
+        let offset2 = storage.allocate(8).unwrap();
+        assert_eq!(offset2, offset1 + 8);
+        assert_eq!(storage.usage().unwrap().freed, 0);
+        assert!(storage.free(0, 8).is_err());
+        assert!(storage.verify().unwrap().is_empty());
+
+        // Without journaling the writes of a failed atomic run are kept
+        let api = Box::new(MockStorage::new(1024 * 1024));
+        let config = FormatConfig {
+            features: FEATURE_FREE_LISTS,
+            ..Default::default()
+        };
+        let storage = Storage::create_with_config(api, config).unwrap();
+        assert!(!storage.has_feature(FEATURE_JOURNALING));
+        let offset = storage.allocate(4).unwrap();
+        let res: Result<(), Error> = storage.atomic(|| {
+            assert!(!storage.in_transaction());
+            storage.write_u32(offset, &1)?;
+            Err(Error::GenericError("rollback".into()))
+        });
+        assert!(res.is_err());
+        assert_eq!(storage.read_u32(offset).unwrap(), 1);
+
+        // Freed blocks are reused with free lists
+        storage.free(offset, 4).unwrap();
+        assert_eq!(storage.allocate(4).unwrap(), offset);
+    }
+
+    #[test]
+    fn test_block_checksums() {
+        use super::header::{FormatConfig, DEFAULT_FEATURES, FEATURE_BLOCK_CHECKSUMS};
+        use super::Storage;
+        use crate::storage::bst::StorageBST;
+        use crate::storage::mock::MockStorage;
+        use crate::storage::verify::Problem;
+        use alloc::boxed::Box;
+
+        let api = Box::new(MockStorage::new(1024 * 1024));
+        let config = FormatConfig {
+            features: DEFAULT_FEATURES | FEATURE_BLOCK_CHECKSUMS,
+            ..Default::default()
+        };
+        let mut storage = Storage::create_with_config(api, config).unwrap();
+        let mut bst = storage.root::<StorageBST<u32, u32>>("bst").unwrap();
+        for i in 0..50 {
+            bst.insert(i, i).unwrap();
+        }
+        for i in 0..20 {
+            bst.remove(&i).unwrap();
+        }
+        let offset = bst.offset();
+        storage.flush().unwrap();
+        assert_eq!(storage.verify().unwrap(), []);
+
+        // Changing the data behind the storage
+        storage.api_mut().unwrap().write(offset, &[0xFF]).unwrap();
+        let mut verifier = storage.verifier().unwrap();
+        verifier.root::<StorageBST<u32, u32>>("bst").unwrap();
+        assert!(verifier
+            .finish()
+            .contains(&Problem::ChecksumMismatch(offset)));
+    }
+
+    #[test]
+    fn test_stack_size() {
+        use super::header::FormatConfig;
+        use super::Storage;
+        use crate::storage::error::Error;
+        use crate::storage::mock::MockStorage;
+        use alloc::boxed::Box;
+
+        let storage = mock_storage(1024 * 1024);
+        assert_eq!(storage.stack_size(), 256);
+        assert!(storage.fill_stack_at(255, 1).is_ok());
+        assert!(matches!(
+            storage.fill_stack_at(256, 1),
+            Err(Error::StackOverflow)
+        ));
+
+        let config = FormatConfig {
+            stack_size: 4,
+            ..Default::default()
+        };
+        let api = Box::new(MockStorage::new(1024 * 1024));
+        let mut storage = Storage::create_with_config(api, config).unwrap();
+        let offset = storage.allocate(8).unwrap();
+        for index in 0..4 {
+            storage.fill_stack_at(index, offset).unwrap();
+        }
+        assert!(matches!(
+            storage.read_stack_at(4),
+            Err(Error::StackOverflow)
+        ));
+        assert_eq!(storage.allocate(8).unwrap(), offset + 8);
+
+        let api = core::mem::replace(storage.api_mut().unwrap(), Box::new(MockStorage::new(0)));
+        let storage = Storage::load(api).unwrap();
+        assert_eq!(storage.stack_size(), 4);
+        assert_eq!(storage.read_stack_at(3).unwrap(), offset);
+        assert!(storage.fill_stack_at(4, 1).is_err());
+        assert_eq!(storage.verify().unwrap(), []);
+    }
 }
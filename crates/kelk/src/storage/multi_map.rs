@@ -0,0 +1,231 @@
+//! Storage Multi Map
+//!
+//! Storage Multi Map keeps several values for each key, and it is built on top of the
+//! Storage B-Tree Map. Each pair of key and value is an entry of the tree, keyed by
+//! `(key, Some(value))`. Since `None` is less than any value, the values of a key are found
+//! by a range query that starts from `(key, None)`.
+//!
+//! The values of each key are unique and sorted.
+//!
+
+use crate::storage::btree::{self, StorageBTreeMap};
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::verify::{Verifier, Verify};
+use crate::storage::{Offset, Storage};
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeFull};
+use core::result::Result;
+
+/// The key of the entries in the underlying tree.
+type EntryKey<K, V> = (K, Option<V>);
+
+/// The instance of Storage Multi Map
+pub struct StorageMultiMap<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    map: StorageBTreeMap<'a, EntryKey<K, V>, ()>,
+}
+
+impl<'a, K, V> StorageMultiMap<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    /// Creates a new instance of `StorageMultiMap`.
+    pub fn create(storage: &'a Storage) -> Result<Self, Error> {
+        Ok(StorageMultiMap {
+            map: StorageBTreeMap::create(storage)?,
+        })
+    }
+
+    /// Creates a new instance of `StorageMultiMap` with the given order for the underlying tree.
+    pub fn create_with_order(storage: &'a Storage, order: u16) -> Result<Self, Error> {
+        Ok(StorageMultiMap {
+            map: StorageBTreeMap::create_with_order(storage, order)?,
+        })
+    }
+
+    /// Loads the `StorageMultiMap` at the given offset.
+    pub fn load(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Ok(StorageMultiMap {
+            map: StorageBTreeMap::load(storage, offset)?,
+        })
+    }
+
+    /// Returns the offset of `StorageMultiMap` in the storage file.
+    pub fn offset(&self) -> Offset {
+        self.map.offset()
+    }
+
+    /// Returns the number of the key-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map has no entry.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Adds the value to the values of the key.
+    /// It returns false if the key already had the value.
+    pub fn insert(&mut self, key: K, value: V) -> Result<bool, Error> {
+        Ok(self.map.insert((key, Some(value)), ())?.is_none())
+    }
+
+    /// Removes the value from the values of the key.
+    /// It returns false if the key didn't have the value.
+    pub fn remove(&mut self, key: &K, value: &V) -> Result<bool, Error> {
+        let entry_key = (key.clone(), Some(value.clone()));
+        Ok(self.map.remove(&entry_key)?.is_some())
+    }
+
+    /// Removes all the values of the key, and returns the number of the removed values.
+    pub fn remove_all(&mut self, key: &K) -> Result<usize, Error> {
+        let values = self.get(key).collect::<Result<Vec<_>, _>>()?;
+        for value in &values {
+            self.remove(key, value)?;
+        }
+        Ok(values.len())
+    }
+
+    /// Returns true if the key has the value.
+    pub fn contains(&self, key: &K, value: &V) -> Result<bool, Error> {
+        self.map.contains_key(&(key.clone(), Some(value.clone())))
+    }
+
+    /// Returns true if the key has at least one value.
+    pub fn contains_key(&self, key: &K) -> Result<bool, Error> {
+        self.get(key)
+            .next()
+            .transpose()
+            .map(|value| value.is_some())
+    }
+
+    /// Gets an iterator that visits the values of the key, in ascending order.
+    pub fn get(&self, key: &K) -> Values<'_, K, V> {
+        let start = Bound::Included((key.clone(), None));
+        Values {
+            key: key.clone(),
+            inner: self.map.range((start, Bound::Unbounded)),
+        }
+    }
+
+    /// Gets an iterator that visits all the key-value pairs, sorted by key and value.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.map.iter(),
+        }
+    }
+}
+
+type EntryBounds<K, V> = (Bound<EntryKey<K, V>>, Bound<EntryKey<K, V>>);
+
+/// An iterator over the values of a key in a `StorageMultiMap`, in ascending order.
+pub struct Values<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    key: K,
+    inner: btree::Range<'a, EntryKey<K, V>, (), EntryBounds<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    type Item = Result<V, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next()? {
+            Ok(((key, Some(value)), _)) if key == self.key => Some(Ok(value)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// An iterator over the key-value pairs of a `StorageMultiMap`, sorted by key and value.
+pub struct Iter<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    inner: btree::Range<'a, EntryKey<K, V>, (), RangeFull>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next()? {
+            Ok(((key, Some(value)), _)) => Some(Ok((key, value))),
+            Ok(_) => Some(Err(Error::GenericError("invalid multi map entry".into()))),
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl<'a, K, V> Verify for StorageMultiMap<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    fn verify(&self, verifier: &mut Verifier) {
+        verifier.check(&self.map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::mock_storage;
+
+    #[test]
+    fn test_multi_map() {
+        let storage = mock_storage(1024 * 1024);
+        let mut map = StorageMultiMap::<u32, u64>::create_with_order(&storage, 4).unwrap();
+        assert!(map.is_empty());
+
+        for key in 0..10 {
+            for value in (0..key as u64).rev() {
+                assert!(map.insert(key, value * 10).unwrap());
+            }
+        }
+        assert!(!map.insert(5, 40).unwrap());
+        assert_eq!(map.len(), 45);
+
+        let values = |map: &StorageMultiMap<u32, u64>, key| -> Vec<u64> {
+            map.get(&key).collect::<Result<_, _>>().unwrap()
+        };
+        assert_eq!(values(&map, 0), []);
+        assert_eq!(values(&map, 3), [0, 10, 20]);
+        assert_eq!(values(&map, 20), []);
+        assert!(map.contains(&4, &30).unwrap());
+        assert!(!map.contains(&4, &40).unwrap());
+        assert!(!map.contains_key(&0).unwrap());
+        assert!(map.contains_key(&1).unwrap());
+
+        assert!(map.remove(&3, &10).unwrap());
+        assert!(!map.remove(&3, &10).unwrap());
+        assert_eq!(values(&map, 3), [0, 20]);
+        assert_eq!(map.remove_all(&9).unwrap(), 9);
+        assert_eq!(values(&map, 9), []);
+
+        let map = StorageMultiMap::<u32, u64>::load(&storage, map.offset()).unwrap();
+        assert_eq!(map.len(), 35);
+        let pairs = map.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(pairs.len(), 35);
+        assert_eq!(pairs[..3], [(1, 0), (2, 0), (2, 10)]);
+        assert_eq!(values(&map, 8), [0, 10, 20, 30, 40, 50, 60, 70]);
+    }
+}
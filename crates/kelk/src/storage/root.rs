@@ -0,0 +1,591 @@
+//! Named roots
+//!
+//! A root is a top-level collection that contracts can find by its name, instead of
+//! keeping its offset in a stack slot. The roots are kept in a registry, that maps names to
+//! the offset and the type id of the roots.
+//!
+//! ```ignore
+//! let balances = storage.root::<StorageBST<Address, i64>>("balances")?;
+//! ```
+//!
+//! The root is created on the first use and loaded afterward. Loading a root with another
+//! type returns `TypeMismatch` error.
+//!
+//! The type id changes when the layout version of the elements is increased. Then the root
+//! should be loaded once by [`Storage::migrate_root`], that migrates the elements and
+//! registers the new type id.
+//!
+
+use crate::storage::boxed::StorageBox;
+use crate::storage::bst::StorageBST;
+use crate::storage::btree::StorageBTreeMap;
+use crate::storage::cell::StorageCell;
+use crate::storage::codec::{Codec, DynCodec};
+use crate::storage::dyn_vec::StorageDynVec;
+use crate::storage::error::Error;
+use crate::storage::hash_map::StorageHashMap;
+use crate::storage::linked_list::StorageLinkedList;
+use crate::storage::multi_map::StorageMultiMap;
+use crate::storage::set::StorageSet;
+use crate::storage::str::StorageString;
+use crate::storage::vec::StorageVec;
+use crate::storage::{hash, Offset, Storage};
+use crate::Codec;
+use alloc::vec::Vec;
+
+/// The maximum length of root names in bytes.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// `Root` trait is implemented by the collections that can be used as named roots.
+pub trait Root<'a>: Sized {
+    /// Returns the type id of the collection.
+    ///
+    /// The type id is built from the structure of the collection, like the kind of
+    /// the collection and the packed length of its elements, so it doesn't change when
+    /// the contract is recompiled.
+    fn type_id() -> u32;
+
+    /// Creates a new instance of the collection.
+    fn create_root(storage: &'a Storage) -> Result<Self, Error>;
+
+    /// Loads the collection at the given offset.
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error>;
+
+    /// Returns the offset of the collection in the storage file.
+    fn root_offset(&self) -> Offset;
+
+    /// Migrates the collection at the given offset, that is registered with the given
+    /// type id by an older version of its elements.
+    ///
+    /// The collections that can't be migrated return `TypeMismatch` error.
+    fn migrate_root(storage: &'a Storage, offset: Offset, type_id: u32) -> Result<Self, Error> {
+        let _ = (storage, offset, type_id);
+        Err(Error::TypeMismatch)
+    }
+}
+
+/// The registry of roots, that maps the names to the entries.
+pub(crate) type Registry<'a> = StorageHashMap<'a, [u8; MAX_NAME_LEN], Entry>;
+
+/// The registry entry of a root.
+#[derive(Codec)]
+pub(crate) struct Entry {
+    pub type_id: u32,
+    pub offset: Offset,
+}
+
+/// Builds a type id for a collection of the given `kind`, from the layouts of its elements.
+/// Each layout is the packed length and the version of an element type.
+pub fn type_id(kind: &str, layouts: &[(usize, u8)]) -> u32 {
+    let mut bytes = Vec::from(kind.as_bytes());
+    for (packed_len, version) in layouts {
+        bytes.extend_from_slice(&(*packed_len as u32).to_bytes());
+        bytes.push(*version);
+    }
+    hash(&bytes)
+}
+
+// Returns the older version of `T` that the type id of a `kind` collection is built from.
+fn older_version<T: Codec>(kind: &str, id: u32) -> Option<u8> {
+    (1..T::VERSION).find(|version| {
+        T::packed_len_of(*version).map(|len| type_id(kind, &[(len, *version)])) == Some(id)
+    })
+}
+
+fn name_key(name: &str) -> Result<[u8; MAX_NAME_LEN], Error> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(Error::GenericError(alloc::format!(
+            "invalid root name: {}",
+            name
+        )));
+    }
+    let mut key = [0; MAX_NAME_LEN];
+    key[..name.len()].copy_from_slice(name.as_bytes());
+    Ok(key)
+}
+
+impl Storage {
+    /// Returns the root with the given name.
+    /// If the root doesn't exist, it is created and registered under this name.
+    pub fn root<'a, T: Root<'a>>(&'a self, name: &str) -> Result<T, Error> {
+        let key = name_key(name)?;
+        let mut registry = self.root_registry()?;
+        match registry.find(&key)? {
+            Some(entry) => {
+                if entry.type_id != T::type_id() {
+                    return Err(Error::TypeMismatch);
+                }
+                T::load_root(self, entry.offset)
+            }
+            None => {
+                let root = T::create_root(self)?;
+                let entry = Entry {
+                    type_id: T::type_id(),
+                    offset: root.root_offset(),
+                };
+                registry.insert(key, entry)?;
+                Ok(root)
+            }
+        }
+    }
+
+    /// Returns the root with the given name, like `root`. If the root is registered by
+    /// an older version of its elements, it is migrated and registered with the new type id.
+    /// The migration is run in a transaction.
+    pub fn migrate_root<'a, T: Root<'a>>(&'a self, name: &str) -> Result<T, Error> {
+        let key = name_key(name)?;
+        match self.root_registry()?.find(&key)? {
+            Some(entry) if entry.type_id != T::type_id() => self.transaction(|_| {
+                let root = T::migrate_root(self, entry.offset, entry.type_id)?;
+                let entry = Entry {
+                    type_id: T::type_id(),
+                    offset: root.root_offset(),
+                };
+                self.root_registry()?.insert(key, entry)?;
+                Ok(root)
+            }),
+            _ => self.root(name),
+        }
+    }
+
+    /// Returns true if a root with the given name exists.
+    pub fn has_root(&self, name: &str) -> Result<bool, Error> {
+        Ok(self.root_entry(name)?.is_some())
+    }
+
+    // Returns the type id and the offset of the root with the given name, if it exists.
+    pub(crate) fn root_entry(&self, name: &str) -> Result<Option<(u32, Offset)>, Error> {
+        let key = name_key(name)?;
+        match self.root_registry_if_exists()? {
+            Some(registry) => Ok(registry
+                .find(&key)?
+                .map(|entry| (entry.type_id, entry.offset))),
+            None => Ok(None),
+        }
+    }
+
+    // Reads the offset of the registry of roots, or zero if it is not created yet.
+    // The registry is never a legacy block, since the slot is added by the version 2.
+    fn registry_offset(&self) -> Result<Offset, Error> {
+        let offset = self.read_u32(self.roots_offset())?;
+        let free_pos = self.read_u32(self.free_pos_offset())?;
+        if offset != 0
+            && (offset < self.data_offset() || offset >= free_pos || self.is_legacy(offset))
+        {
+            return Err(Error::InvalidOffset(offset));
+        }
+        Ok(offset)
+    }
+
+    // Loads the registry of roots, if it is created.
+    pub(crate) fn root_registry_if_exists(&self) -> Result<Option<Registry<'_>>, Error> {
+        match self.registry_offset()? {
+            0 => Ok(None),
+            offset => Ok(Some(StorageHashMap::load(self, offset)?)),
+        }
+    }
+
+    // Loads the registry of roots, or creates it on the first use.
+    fn root_registry(&self) -> Result<Registry<'_>, Error> {
+        match self.registry_offset()? {
+            0 => {
+                let registry = StorageHashMap::create(self)?;
+                self.write_u32(self.roots_offset(), &registry.offset())?;
+                Ok(registry)
+            }
+            offset => StorageHashMap::load(self, offset),
+        }
+    }
+}
+
+impl<'a, T: Codec> Root<'a> for StorageCell<'a, T> {
+    fn type_id() -> u32 {
+        type_id("StorageCell", &[(T::PACKED_LEN, T::VERSION)])
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Ok(Self::load(storage, offset))
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+
+    fn migrate_root(storage: &'a Storage, offset: Offset, type_id: u32) -> Result<Self, Error> {
+        let version = older_version::<T>("StorageCell", type_id).ok_or(Error::TypeMismatch)?;
+        Self::migrate(storage, offset, version)
+    }
+}
+
+/// Note that the new root of `StorageBox` keeps the default value.
+impl<'a, T: Codec + Default> Root<'a> for StorageBox<'a, T> {
+    fn type_id() -> u32 {
+        type_id("StorageBox", &[(T::PACKED_LEN, T::VERSION)])
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage, T::default())
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+impl<'a, T: Codec> Root<'a> for StorageVec<'a, T> {
+    fn type_id() -> u32 {
+        type_id("StorageVec", &[(T::PACKED_LEN, T::VERSION)])
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage, 0)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+
+    fn migrate_root(storage: &'a Storage, offset: Offset, type_id: u32) -> Result<Self, Error> {
+        older_version::<T>("StorageVec", type_id).ok_or(Error::TypeMismatch)?;
+        Self::migrate(storage, offset)
+    }
+}
+
+impl<'a> Root<'a> for StorageString<'a> {
+    fn type_id() -> u32 {
+        type_id("StorageString", &[])
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage, 0)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+/// Note that the type id of `StorageDynVec` doesn't depend on `T`,
+/// because variable-length types have no packed length.
+impl<'a, T: DynCodec> Root<'a> for StorageDynVec<'a, T> {
+    fn type_id() -> u32 {
+        type_id("StorageDynVec", &[])
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage, 0)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+impl<'a, T: Codec> Root<'a> for StorageLinkedList<'a, T> {
+    fn type_id() -> u32 {
+        type_id("StorageLinkedList", &[(T::PACKED_LEN, T::VERSION)])
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+impl<'a, K: Codec + Ord, V: Codec> Root<'a> for StorageBST<'a, K, V> {
+    fn type_id() -> u32 {
+        type_id(
+            "StorageBST",
+            &[(K::PACKED_LEN, K::VERSION), (V::PACKED_LEN, V::VERSION)],
+        )
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+impl<'a, K: Codec + Ord, V: Codec> Root<'a> for StorageBTreeMap<'a, K, V> {
+    fn type_id() -> u32 {
+        type_id(
+            "StorageBTreeMap",
+            &[(K::PACKED_LEN, K::VERSION), (V::PACKED_LEN, V::VERSION)],
+        )
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+impl<'a, T: Codec + Ord> Root<'a> for StorageSet<'a, T> {
+    fn type_id() -> u32 {
+        type_id("StorageSet", &[(T::PACKED_LEN, T::VERSION)])
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+impl<'a, K, V> Root<'a> for StorageMultiMap<'a, K, V>
+where
+    K: Codec + Ord + Clone,
+    V: Codec + Ord + Clone,
+{
+    fn type_id() -> u32 {
+        type_id(
+            "StorageMultiMap",
+            &[(K::PACKED_LEN, K::VERSION), (V::PACKED_LEN, V::VERSION)],
+        )
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+impl<'a, K: Codec + Eq, V: Codec> Root<'a> for StorageHashMap<'a, K, V> {
+    fn type_id() -> u32 {
+        type_id(
+            "StorageHashMap",
+            &[(K::PACKED_LEN, K::VERSION), (V::PACKED_LEN, V::VERSION)],
+        )
+    }
+
+    fn create_root(storage: &'a Storage) -> Result<Self, Error> {
+        Self::create(storage)
+    }
+
+    fn load_root(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Self::load(storage, offset)
+    }
+
+    fn root_offset(&self) -> Offset {
+        self.offset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::address::{Address, ADDRESS_SIZE};
+    use crate::storage::mock::mock_storage;
+
+    #[test]
+    fn test_roots() {
+        let storage = mock_storage(1024 * 1024);
+        let addr = <Address as Codec>::from_bytes(&[1; ADDRESS_SIZE]);
+
+        assert!(!storage.has_root("balances").unwrap());
+        let mut balances = storage
+            .root::<StorageBST<Address, i64>>("balances")
+            .unwrap();
+        balances.insert(addr.clone(), 100).unwrap();
+        assert!(storage.has_root("balances").unwrap());
+
+        let mut name = storage.root::<StorageString>("name").unwrap();
+        name.set_string("kelk").unwrap();
+
+        let balances = storage
+            .root::<StorageBST<Address, i64>>("balances")
+            .unwrap();
+        assert_eq!(balances.find(&addr).unwrap(), Some(100));
+        let name = storage.root::<StorageString>("name").unwrap();
+        assert_eq!(name.get_string().unwrap(), "kelk");
+
+        assert!(matches!(
+            storage.root::<StorageBST<Address, i32>>("balances"),
+            Err(Error::TypeMismatch)
+        ));
+        assert!(matches!(
+            storage.root::<StorageHashMap<Address, i64>>("balances"),
+            Err(Error::TypeMismatch)
+        ));
+        assert!(storage.root::<StorageString>("").is_err());
+        assert!(storage
+            .root::<StorageString>("a name longer than thirty two bytes")
+            .is_err());
+    }
+
+    #[test]
+    fn test_many_roots() {
+        let storage = mock_storage(1024 * 1024);
+        for i in 0..100u32 {
+            let mut vec = storage
+                .root::<StorageVec<u32>>(&alloc::format!("vec_{}", i))
+                .unwrap();
+            vec.push(i).unwrap();
+        }
+        for i in 0..100u32 {
+            let vec = storage
+                .root::<StorageVec<u32>>(&alloc::format!("vec_{}", i))
+                .unwrap();
+            assert_eq!(vec.get(0).unwrap(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_migrate_root() {
+        use crate::Codec;
+
+        #[derive(Codec)]
+        struct ProfileV1 {
+            age: u8,
+        }
+
+        #[derive(Codec, Debug, PartialEq, Eq)]
+        #[codec(version = 2, migrate_from(v1 = "ProfileV1"))]
+        struct Profile {
+            age: u8,
+            score: u32,
+        }
+
+        impl Profile {
+            fn migrate_from_v1(old: ProfileV1) -> Self {
+                Profile {
+                    age: old.age,
+                    score: 100,
+                }
+            }
+        }
+
+        let storage = mock_storage(1024 * 1024);
+        let mut profiles = storage.root::<StorageVec<ProfileV1>>("profiles").unwrap();
+        profiles.push(ProfileV1 { age: 1 }).unwrap();
+        let owner = storage.root::<StorageCell<ProfileV1>>("owner").unwrap();
+        owner.set(&ProfileV1 { age: 2 }).unwrap();
+        let count = storage.root::<StorageCell<u32>>("count").unwrap();
+        count.set(&3).unwrap();
+
+        assert!(matches!(
+            storage.root::<StorageVec<Profile>>("profiles"),
+            Err(Error::TypeMismatch)
+        ));
+        assert!(matches!(
+            storage.root::<StorageCell<Profile>>("owner"),
+            Err(Error::TypeMismatch)
+        ));
+
+        let profiles = storage
+            .migrate_root::<StorageVec<Profile>>("profiles")
+            .unwrap();
+        assert_eq!(
+            profiles.get(0).unwrap(),
+            Some(Profile { age: 1, score: 100 })
+        );
+        let owner = storage
+            .migrate_root::<StorageCell<Profile>>("owner")
+            .unwrap();
+        assert_eq!(owner.get().unwrap(), Profile { age: 2, score: 100 });
+
+        // The migrated roots are loaded with the new type id
+        let profiles = storage.root::<StorageVec<Profile>>("profiles").unwrap();
+        assert_eq!(profiles.len(), 1);
+        let owner = storage.root::<StorageCell<Profile>>("owner").unwrap();
+        assert_eq!(owner.get().unwrap(), Profile { age: 2, score: 100 });
+        assert!(storage.verify().unwrap().is_empty());
+
+        // Roots of another type are not migrated
+        assert!(matches!(
+            storage.migrate_root::<StorageVec<Profile>>("count"),
+            Err(Error::TypeMismatch)
+        ));
+        assert!(matches!(
+            storage.migrate_root::<StorageBST<u32, Profile>>("owner"),
+            Err(Error::TypeMismatch)
+        ));
+        assert_eq!(
+            storage
+                .migrate_root::<StorageCell<u32>>("count")
+                .unwrap()
+                .get()
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_roots_v1() {
+        use crate::storage::mock::MockV1Storage;
+
+        // The version 1 files have no root registry
+        let v1 = MockV1Storage::new(2048);
+        let offset = v1.allocate(&7u32.to_bytes());
+        v1.fill_stack_at(0, offset);
+        let storage = Storage::load(v1.into_api()).unwrap();
+        assert!(!storage.has_root("vec").unwrap());
+
+        let mut vec = storage.root::<StorageVec<u32>>("vec").unwrap();
+        vec.push(1).unwrap();
+        let vec = storage.root::<StorageVec<u32>>("vec").unwrap();
+        assert_eq!(vec.get(0).unwrap(), Some(1));
+        assert_eq!(storage.read_u32(offset).unwrap(), 7);
+
+        // The registry can't point to the legacy blocks
+        storage.write_u32(storage.roots_offset(), &offset).unwrap();
+        assert!(matches!(
+            storage.has_root("vec"),
+            Err(Error::InvalidOffset(_))
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+//! Storage Set
+//!
+//! Storage Set is an ordered set, that is built on top of the Storage B-Tree Map.
+//! It is useful for access lists, like allowlists, voters or approved operators.
+//!
+//! The set operations, like `union` and `intersection`, walk both sets in order at the same
+//! time. Therefore they read each node only once, and they don't keep the sets in memory.
+//!
+
+use crate::storage::btree::{self, StorageBTreeMap};
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::verify::{Verifier, Verify};
+use crate::storage::{Offset, Storage};
+use core::cmp::Ordering;
+use core::ops::{RangeBounds, RangeFull};
+use core::result::Result;
+
+/// The instance of Storage Set
+pub struct StorageSet<'a, T>
+where
+    T: Codec + Ord,
+{
+    map: StorageBTreeMap<'a, T, ()>,
+}
+
+impl<'a, T> StorageSet<'a, T>
+where
+    T: Codec + Ord,
+{
+    /// Creates a new instance of `StorageSet`.
+    pub fn create(storage: &'a Storage) -> Result<Self, Error> {
+        Ok(StorageSet {
+            map: StorageBTreeMap::create(storage)?,
+        })
+    }
+
+    /// Creates a new instance of `StorageSet` with the given order for the underlying tree.
+    pub fn create_with_order(storage: &'a Storage, order: u16) -> Result<Self, Error> {
+        Ok(StorageSet {
+            map: StorageBTreeMap::create_with_order(storage, order)?,
+        })
+    }
+
+    /// Loads the `StorageSet` at the given offset.
+    pub fn load(storage: &'a Storage, offset: Offset) -> Result<Self, Error> {
+        Ok(StorageSet {
+            map: StorageBTreeMap::load(storage, offset)?,
+        })
+    }
+
+    /// Returns the offset of `StorageSet` in the storage file.
+    pub fn offset(&self) -> Offset {
+        self.map.offset()
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the set has no element.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Adds a value to the set.
+    /// It returns false if the set already had the value.
+    pub fn insert(&mut self, value: T) -> Result<bool, Error> {
+        Ok(self.map.insert(value, ())?.is_none())
+    }
+
+    /// Removes a value from the set.
+    /// It returns false if the set didn't have the value.
+    pub fn remove(&mut self, value: &T) -> Result<bool, Error> {
+        Ok(self.map.remove(value)?.is_some())
+    }
+
+    /// Returns true if the set has the value.
+    pub fn contains(&self, value: &T) -> Result<bool, Error> {
+        self.map.contains_key(value)
+    }
+
+    /// Gets an iterator that visits the elements of the set, in ascending order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.range(..)
+    }
+
+    /// Constructs an iterator over a sub-range of elements in the set, in ascending order.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Iter<'_, T, R> {
+        Iter {
+            inner: self.map.range(range),
+        }
+    }
+
+    /// Visits the elements that are in `self` or `other`, without duplicates,
+    /// in ascending order.
+    pub fn union<'b>(&'b self, other: &'b StorageSet<'_, T>) -> Union<'b, T> {
+        Union {
+            merge: Merge::new(self.iter(), other.iter()),
+        }
+    }
+
+    /// Visits the elements that are both in `self` and `other`, in ascending order.
+    pub fn intersection<'b>(&'b self, other: &'b StorageSet<'_, T>) -> Intersection<'b, T> {
+        Intersection {
+            merge: Merge::new(self.iter(), other.iter()),
+        }
+    }
+
+    /// Visits the elements that are in `self` but not in `other`, in ascending order.
+    pub fn difference<'b>(&'b self, other: &'b StorageSet<'_, T>) -> Difference<'b, T> {
+        Difference {
+            merge: Merge::new(self.iter(), other.iter()),
+        }
+    }
+}
+
+/// An iterator over the elements of a `StorageSet`, in ascending order.
+pub struct Iter<'a, T, R = RangeFull>
+where
+    T: Codec + Ord,
+    R: RangeBounds<T>,
+{
+    inner: btree::Range<'a, T, (), R>,
+}
+
+impl<'a, T, R> Iterator for Iter<'a, T, R>
+where
+    T: Codec + Ord,
+    R: RangeBounds<T>,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|res| res.map(|(value, _)| value))
+    }
+}
+
+impl<'a, T> IntoIterator for &'a StorageSet<'_, T>
+where
+    T: Codec + Ord,
+{
+    type Item = Result<T, Error>;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Walks two sets in order, keeping the next element of each one.
+struct Merge<'a, T: Codec + Ord> {
+    left: Iter<'a, T>,
+    right: Iter<'a, T>,
+    next_left: Option<T>,
+    next_right: Option<T>,
+    failed: bool,
+}
+
+impl<'a, T: Codec + Ord> Merge<'a, T> {
+    fn new(left: Iter<'a, T>, right: Iter<'a, T>) -> Self {
+        Merge {
+            left,
+            right,
+            next_left: None,
+            next_right: None,
+            failed: false,
+        }
+    }
+
+    // Reads the next elements, if they are taken, and compares them.
+    // `None` means one of the sets is exhausted.
+    fn peek(&mut self) -> Result<Option<Ordering>, Error> {
+        if self.next_left.is_none() {
+            self.next_left = self.left.next().transpose()?;
+        }
+        if self.next_right.is_none() {
+            self.next_right = self.right.next().transpose()?;
+        }
+        Ok(match (&self.next_left, &self.next_right) {
+            (Some(left), Some(right)) => Some(left.cmp(right)),
+            _ => None,
+        })
+    }
+
+    // Runs `step` until it returns an element, and stops after the first error.
+    fn next_by<F>(&mut self, mut step: F) -> Option<Result<T, Error>>
+    where
+        F: FnMut(&mut Self, Option<Ordering>) -> Option<Option<T>>,
+    {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let ordering = match self.peek() {
+                Ok(ordering) => ordering,
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            };
+            if let Some(next) = step(self, ordering) {
+                return next.map(Ok);
+            }
+        }
+    }
+}
+
+/// A lazy iterator over the union of two `StorageSet`s, in ascending order.
+pub struct Union<'a, T: Codec + Ord> {
+    merge: Merge<'a, T>,
+}
+
+impl<'a, T: Codec + Ord> Iterator for Union<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.merge.next_by(|merge, ordering| match ordering {
+            Some(Ordering::Less) => Some(merge.next_left.take()),
+            Some(Ordering::Greater) => Some(merge.next_right.take()),
+            Some(Ordering::Equal) => {
+                merge.next_right = None;
+                Some(merge.next_left.take())
+            }
+            None => Some(merge.next_left.take().or_else(|| merge.next_right.take())),
+        })
+    }
+}
+
+/// A lazy iterator over the intersection of two `StorageSet`s, in ascending order.
+pub struct Intersection<'a, T: Codec + Ord> {
+    merge: Merge<'a, T>,
+}
+
+impl<'a, T: Codec + Ord> Iterator for Intersection<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.merge.next_by(|merge, ordering| match ordering {
+            Some(Ordering::Less) => {
+                merge.next_left = None;
+                None
+            }
+            Some(Ordering::Greater) => {
+                merge.next_right = None;
+                None
+            }
+            Some(Ordering::Equal) => {
+                merge.next_right = None;
+                Some(merge.next_left.take())
+            }
+            None => Some(None),
+        })
+    }
+}
+
+/// A lazy iterator over the difference of two `StorageSet`s, in ascending order.
+pub struct Difference<'a, T: Codec + Ord> {
+    merge: Merge<'a, T>,
+}
+
+impl<'a, T: Codec + Ord> Iterator for Difference<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.merge.next_by(|merge, ordering| match ordering {
+            Some(Ordering::Less) => Some(merge.next_left.take()),
+            Some(Ordering::Greater) => {
+                merge.next_right = None;
+                None
+            }
+            Some(Ordering::Equal) => {
+                merge.next_left = None;
+                merge.next_right = None;
+                None
+            }
+            None => Some(merge.next_left.take()),
+        })
+    }
+}
+
+impl<'a, T> Verify for StorageSet<'a, T>
+where
+    T: Codec + Ord,
+{
+    fn verify(&self, verifier: &mut Verifier) {
+        verifier.check(&self.map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::mock_storage;
+    use alloc::vec::Vec;
+
+    fn collect<I: Iterator<Item = Result<u32, Error>>>(iter: I) -> Vec<u32> {
+        iter.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_set() {
+        let storage = mock_storage(1024 * 1024);
+        let mut set = StorageSet::<u32>::create_with_order(&storage, 4).unwrap();
+        assert!(set.is_empty());
+
+        for i in (0..100).rev() {
+            assert!(set.insert(i).unwrap());
+        }
+        assert!(!set.insert(50).unwrap());
+        assert_eq!(set.len(), 100);
+
+        for i in (0..100).step_by(2) {
+            assert!(set.remove(&i).unwrap());
+        }
+        assert!(!set.remove(&0).unwrap());
+        assert!(set.contains(&1).unwrap());
+        assert!(!set.contains(&2).unwrap());
+
+        let set = StorageSet::<u32>::load(&storage, set.offset()).unwrap();
+        assert_eq!(set.len(), 50);
+        assert_eq!(collect(set.iter()), (1..100).step_by(2).collect::<Vec<_>>());
+        assert_eq!(collect(set.range(10..16)), [11, 13, 15]);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let storage = mock_storage(1024 * 1024);
+        let mut a = StorageSet::<u32>::create_with_order(&storage, 4).unwrap();
+        let mut b = StorageSet::<u32>::create_with_order(&storage, 4).unwrap();
+        let empty = StorageSet::<u32>::create(&storage).unwrap();
+        for i in [1, 3, 5, 7, 9, 11] {
+            a.insert(i).unwrap();
+        }
+        for i in [3, 4, 5, 6, 11, 12] {
+            b.insert(i).unwrap();
+        }
+
+        assert_eq!(collect(a.union(&b)), [1, 3, 4, 5, 6, 7, 9, 11, 12]);
+        assert_eq!(collect(a.intersection(&b)), [3, 5, 11]);
+        assert_eq!(collect(a.difference(&b)), [1, 7, 9]);
+        assert_eq!(collect(b.difference(&a)), [4, 6, 12]);
+
+        assert_eq!(collect(a.union(&empty)), collect(a.iter()));
+        assert_eq!(collect(empty.union(&b)), collect(b.iter()));
+        assert_eq!(collect(a.intersection(&empty)), []);
+        assert_eq!(collect(empty.difference(&a)), []);
+    }
+}
@@ -0,0 +1,143 @@
+//! Contract state
+//!
+//! The `#[kelk::state]` attribute turns a struct into the state of a contract.
+//! Each field of the struct is kept as a named root, with the name of the field.
+//!
+//! ```ignore
+//! #[kelk::state]
+//! pub struct Token<'a> {
+//!     name: StorageString<'a>,
+//!     balances: StorageBST<'a, Address, i64>,
+//!     total_supply: i64,
+//! }
+//!
+//! let token = Token::init(storage)?;
+//! token.set_total_supply(&1000)?;
+//! token.balances_mut()?.insert(owner, 1000)?;
+//! ```
+//!
+//! Fields with a lifetime, like `StorageBST<'a, K, V>`, are collections and should implement
+//! the [`Root`] trait. The generated accessors return a reference to the collection.
+//! Other fields are scalars that implement the `Codec` trait. They are kept in a
+//! [`StorageCell`](crate::storage::cell::StorageCell), and the generated accessors read and
+//! write the value directly from the storage file.
+//!
+//! `init` creates all the roots, and it should be called once in `instantiate`.
+//! `load` doesn't touch the storage file. The roots are loaded on the first access.
+//!
+
+use crate::storage::error::Error;
+use crate::storage::root::Root;
+use crate::storage::Storage;
+use core::cell::OnceCell;
+
+/// `Lazy` is a named root that is loaded on the first access.
+pub struct Lazy<'a, T: Root<'a>> {
+    storage: &'a Storage,
+    name: &'static str,
+    cell: OnceCell<T>,
+}
+
+impl<'a, T: Root<'a>> Lazy<'a, T> {
+    /// Creates a new instance of `Lazy` without loading the root.
+    pub fn new(storage: &'a Storage, name: &'static str) -> Self {
+        Lazy {
+            storage,
+            name,
+            cell: OnceCell::new(),
+        }
+    }
+
+    /// Creates a new instance of `Lazy` and loads the root.
+    /// The root is created if it doesn't exist.
+    pub fn init(storage: &'a Storage, name: &'static str) -> Result<Self, Error> {
+        let root = storage.root::<T>(name)?;
+        Ok(Lazy {
+            storage,
+            name,
+            cell: OnceCell::from(root),
+        })
+    }
+
+    /// Returns the name of the root.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns a reference to the root, and loads it if it is not loaded yet.
+    pub fn get(&self) -> Result<&T, Error> {
+        if let Some(root) = self.cell.get() {
+            return Ok(root);
+        }
+        let root = self.storage.root::<T>(self.name)?;
+        Ok(self.cell.get_or_init(|| root))
+    }
+
+    /// Returns a mutable reference to the root, and loads it if it is not loaded yet.
+    pub fn get_mut(&mut self) -> Result<&mut T, Error> {
+        if self.cell.get().is_none() {
+            self.cell = OnceCell::from(self.storage.root::<T>(self.name)?);
+        }
+        Ok(self.cell.get_mut().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockchain::address::{Address, ADDRESS_SIZE};
+    use crate::storage::bst::StorageBST;
+    use crate::storage::codec::Codec;
+    use crate::storage::error::Error;
+    use crate::storage::mock::mock_storage;
+    use crate::storage::str::StorageString;
+    use crate::storage::vec::StorageVec;
+
+    #[kelk::state]
+    struct Token<'a> {
+        name: StorageString<'a>,
+        balances: StorageBST<'a, Address, i64>,
+        holders: StorageVec<'a, Address>,
+        total_supply: i64,
+        paused: bool,
+    }
+
+    #[test]
+    fn test_state() {
+        let storage = mock_storage(1024 * 1024);
+        let addr = <Address as Codec>::from_bytes(&[1; ADDRESS_SIZE]);
+
+        let mut token = Token::init(&storage).unwrap();
+        assert_eq!(token.total_supply().unwrap(), 0);
+        assert!(!token.paused().unwrap());
+        token.name_mut().unwrap().set_string("kelk").unwrap();
+        token
+            .balances_mut()
+            .unwrap()
+            .insert(addr.clone(), 1000)
+            .unwrap();
+        token.holders_mut().unwrap().push(addr.clone()).unwrap();
+        token.set_total_supply(&900).unwrap();
+        assert_eq!(token.update_total_supply(|v| v + 100).unwrap(), 1000);
+
+        let token = Token::load(&storage);
+        assert_eq!(token.name().unwrap().get_string().unwrap(), "kelk");
+        assert_eq!(token.balances().unwrap().find(&addr).unwrap(), Some(1000));
+        assert_eq!(token.holders().unwrap().len(), 1);
+        assert_eq!(token.total_supply().unwrap(), 1000);
+        assert!(storage.has_root("total_supply").unwrap());
+    }
+
+    #[test]
+    fn test_state_type_mismatch() {
+        #[kelk::state]
+        struct Other<'a> {
+            name: StorageVec<'a, u8>,
+        }
+
+        let storage = mock_storage(1024 * 1024);
+        Token::init(&storage).unwrap();
+
+        let other = Other::load(&storage);
+        assert!(matches!(other.name(), Err(Error::TypeMismatch)));
+    }
+}
@@ -4,7 +4,12 @@
 
 use alloc::string::{String, ToString};
 
-use super::{error::Error, vec::StorageVec, Offset, Storage};
+use super::{
+    error::Error,
+    vec::{Iter, StorageVec},
+    verify::{Verifier, Verify},
+    Offset, Storage,
+};
 
 /// Storage String
 pub struct StorageString<'a> {
@@ -12,7 +17,7 @@ pub struct StorageString<'a> {
 }
 
 impl<'a> StorageString<'a> {
-    /// creates and store a new instance of Storage Vector at the given offset
+    /// creates and store a new instance of Storage String with at least the specified capacity.
     pub fn create(storage: &'a Storage, capacity: u32) -> Result<Self, Error> {
         let vec = StorageVec::create(storage, capacity)?;
 
@@ -40,23 +45,82 @@ impl<'a> StorageString<'a> {
         self.len() == 0
     }
 
-    ///
+    /// Writes the given string into the `StorageString`.
     pub fn set_string(&mut self, str: &str) -> Result<(), Error> {
         self.vec.set_bytes(str.as_bytes())
     }
 
-    ///
+    /// Reads the whole string from the `StorageString`.
     pub fn get_string(&self) -> Result<String, Error> {
         let bytes = self.vec.get_bytes()?;
         let str = String::from_utf8(bytes).map_err(|err| Error::GenericError(err.to_string()))?;
         Ok(str)
     }
+
+    /// Returns an iterator over the bytes of the `StorageString`.
+    pub fn bytes(&self) -> Iter<'a, u8> {
+        self.vec.iter()
+    }
+
+    /// Returns an iterator over the chars of the `StorageString`.
+    pub fn chars(&self) -> Chars<'a> {
+        Chars {
+            bytes: self.vec.iter(),
+        }
+    }
+}
+
+/// An iterator over the chars of a `StorageString`.
+pub struct Chars<'a> {
+    bytes: Iter<'a, u8>,
+}
+
+impl<'a> Chars<'a> {
+    fn next_char(&mut self, first: u8) -> Result<char, Error> {
+        let width = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return Err(invalid_utf8()),
+        };
+        let mut buf = [first, 0, 0, 0];
+        for b in buf.iter_mut().take(width).skip(1) {
+            *b = self.bytes.next().ok_or_else(invalid_utf8)??;
+        }
+        core::str::from_utf8(&buf[..width])
+            .ok()
+            .and_then(|str| str.chars().next())
+            .ok_or_else(invalid_utf8)
+    }
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = Result<char, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.bytes.next()? {
+            Ok(first) => Some(self.next_char(first)),
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+fn invalid_utf8() -> Error {
+    Error::GenericError("invalid utf-8 sequence".to_string())
+}
+
+impl<'a> Verify for StorageString<'a> {
+    fn verify(&self, verifier: &mut Verifier) {
+        self.vec.verify(verifier)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::storage::mock::mock_storage;
+    use alloc::vec::Vec;
 
     #[test]
     fn test_string() {
@@ -64,7 +128,35 @@ mod tests {
         let mut str_1 = StorageString::create(&storage, 16).unwrap();
         str_1.set_string("foo").unwrap();
 
-        let str_2 = StorageString::load(&storage, str_1.offset()).unwrap();
+        let mut str_2 = StorageString::load(&storage, str_1.offset()).unwrap();
         assert_eq!("foo", str_2.get_string().unwrap());
+
+        str_2
+            .set_string("a string longer than the capacity")
+            .unwrap();
+        let str_3 = StorageString::load(&storage, str_1.offset()).unwrap();
+        assert_eq!(
+            "a string longer than the capacity",
+            str_3.get_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_string_iter() {
+        let storage = mock_storage(1024 * 1024);
+        let mut str = StorageString::create(&storage, 0).unwrap();
+        let text = "kelk: کلک, 𝄞 and more than thirty-two bytes";
+        str.set_string(text).unwrap();
+
+        let bytes: Vec<u8> = str.bytes().map(|b| b.unwrap()).collect();
+        assert_eq!(bytes, text.as_bytes());
+        let chars: String = str.chars().map(|c| c.unwrap()).collect();
+        assert_eq!(chars, text);
+
+        str.set_string("\u{e9}").unwrap();
+        let mut vec = StorageVec::<u8>::load(&storage, str.offset()).unwrap();
+        vec.truncate(1).unwrap();
+        let str = StorageString::load(&storage, str.offset()).unwrap();
+        assert!(str.chars().next().unwrap().is_err());
     }
 }
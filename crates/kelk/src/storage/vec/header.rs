@@ -1,5 +1,7 @@
 use crate::storage::codec::Codec;
-use crate::{storage::Offset, Codec};
+use crate::storage::error::Error;
+use crate::storage::{Offset, Storage};
+use crate::Codec;
 
 #[derive(Codec)]
 pub(super) struct Header {
@@ -7,15 +9,73 @@ pub(super) struct Header {
     pub capacity: u32,
     pub value_len: u16,
     pub data_offset: Offset,
+    pub value_version: u8,
+}
+
+/// The layout version of the elements in the vectors of the version 1 files.
+/// It is the first version of any `Codec` type.
+const LEGACY_VERSION: u8 = 1;
+
+// The header of the vectors that are created in the version 1 of the storage file.
+// It doesn't keep the layout version of the elements.
+#[derive(Codec)]
+struct LegacyHeader {
+    pub count: u32,
+    pub capacity: u32,
+    pub value_len: u16,
+    pub data_offset: Offset,
 }
 
 impl Header {
     pub fn new<T: Codec>(capacity: u32, data_offset: Offset) -> Self {
         Self {
             value_len: T::PACKED_LEN as u16,
+            value_version: T::VERSION,
             count: 0,
             capacity,
             data_offset,
         }
     }
+
+    /// Returns the length of the header at the given offset.
+    pub fn packed_len_at(storage: &Storage, offset: Offset) -> usize {
+        if storage.is_legacy(offset) {
+            LegacyHeader::PACKED_LEN
+        } else {
+            Header::PACKED_LEN
+        }
+    }
+
+    /// Reads the header at the given offset.
+    pub fn read(storage: &Storage, offset: Offset) -> Result<Self, Error> {
+        if !storage.is_legacy(offset) {
+            return storage.read(offset);
+        }
+        let header: LegacyHeader = storage.read(offset)?;
+        Ok(Header {
+            count: header.count,
+            capacity: header.capacity,
+            value_len: header.value_len,
+            data_offset: header.data_offset,
+            value_version: LEGACY_VERSION,
+        })
+    }
+
+    /// Writes the header at the given offset.
+    /// The legacy headers can't keep a layout version other than the first one.
+    pub fn write(&self, storage: &Storage, offset: Offset) -> Result<(), Error> {
+        if !storage.is_legacy(offset) {
+            return storage.write(offset, self);
+        }
+        if self.value_version != LEGACY_VERSION {
+            return Err(Error::VersionMismatch(self.value_version));
+        }
+        let header = LegacyHeader {
+            count: self.count,
+            capacity: self.capacity,
+            value_len: self.value_len,
+            data_offset: self.data_offset,
+        };
+        storage.write(offset, &header)
+    }
 }
@@ -3,19 +3,36 @@
 //! Storage Vector, is a Vector or Array that instead of using Random Access Memory (RAM),
 //! it uses storage file. Therefore it's permanently stored inside contract's storage.
 //!
+//! Like `alloc::vec::Vec`, the elements are kept in a contiguous space. When the space is full,
+//! it is reallocated with twice the capacity and the old space is freed.
+//!
+//! The header keeps the layout version of the elements. If the layout of `T` changes,
+//! the vector should be loaded by `migrate` to rewrite the elements in the new layout.
+//! The vectors of the version 1 files keep their former header, that doesn't keep the version.
+//!
 
 mod header;
 
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 
 use self::header::Header;
-use crate::storage::codec::Codec;
+use crate::storage::codec::{Codec, CodecError};
 use crate::storage::error::Error;
+use crate::storage::verify::{Problem, Verifier, Verify};
 use crate::storage::Offset;
 use crate::storage::Storage;
 use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
 use core::result::Result;
 
+// The default number of elements that iterators read from the storage at once.
+const ITER_CHUNK_LEN: u32 = 32;
+
+/// The offset of the `data_offset` field inside the packed header.
+#[cfg(test)]
+pub(crate) const DATA_OFFSET: Offset = (2 * u32::PACKED_LEN + u16::PACKED_LEN) as Offset;
+
 /// The instance of Storage Vector
 pub struct StorageVec<'a, T: Codec> {
     storage: &'a Storage,
@@ -25,7 +42,8 @@ pub struct StorageVec<'a, T: Codec> {
 }
 
 impl<'a, T: Codec> StorageVec<'a, T> {
-    /// creates and store a new instance of Storage Vector at the given offset
+    /// creates and store a new instance of Storage Vector with at least the specified capacity.
+    /// The vector will be able to hold `capacity` elements without reallocating.
     pub fn create(storage: &'a Storage, capacity: u32) -> Result<Self, Error> {
         let offset = storage.allocate(Header::PACKED_LEN)?;
         let data_offset = storage.allocate(T::PACKED_LEN * capacity as usize)?;
@@ -41,10 +59,15 @@ impl<'a, T: Codec> StorageVec<'a, T> {
     }
 
     /// load the Storage Vector
+    /// If the elements are written by another version of `T`, it returns `VersionMismatch` error.
     pub fn load(storage: &'a Storage, offset: u32) -> Result<Self, Error> {
-        let header: Header = storage.read(offset)?;
-
-        debug_assert_eq!(header.value_len, T::PACKED_LEN as u16);
+        let header = Header::read(storage, offset)?;
+        if header.value_version != T::VERSION {
+            return Err(Error::VersionMismatch(header.value_version));
+        }
+        if header.value_len != T::PACKED_LEN as u16 {
+            return Err(Error::TypeMismatch);
+        }
 
         Ok(StorageVec {
             storage,
@@ -54,6 +77,45 @@ impl<'a, T: Codec> StorageVec<'a, T> {
         })
     }
 
+    /// load the Storage Vector and migrates the elements that are written by an older version of `T`.
+    /// The elements are decoded by `Codec::try_from_version` and moved into a new space.
+    /// The vectors of the version 1 files can't be migrated, since their headers don't keep
+    /// the version. They should be copied into a new vector.
+    pub fn migrate(storage: &'a Storage, offset: u32) -> Result<Self, Error> {
+        let mut header = Header::read(storage, offset)?;
+        let old_version = header.value_version;
+        if old_version != T::VERSION {
+            // The headers of the version 1 files can't keep the new version
+            if storage.is_legacy(offset) {
+                return Err(Error::VersionMismatch(old_version));
+            }
+            let old_len =
+                T::packed_len_of(old_version).ok_or(CodecError::InvalidVersion(old_version))?;
+            if header.value_len as usize != old_len {
+                return Err(Error::TypeMismatch);
+            }
+
+            let bytes = storage.read_bytes(header.data_offset, header.count * old_len as u32)?;
+            let mut new_bytes = Vec::with_capacity(header.count as usize * T::PACKED_LEN);
+            for i in 0..header.count as usize {
+                let item = T::try_from_version(old_version, &bytes[i * old_len..][..old_len])?;
+                new_bytes.extend_from_slice(&item.to_bytes());
+            }
+
+            let data_offset = storage.allocate(header.capacity as usize * T::PACKED_LEN)?;
+            storage.write_bytes(data_offset, &new_bytes)?;
+            storage.free(header.data_offset, header.capacity as usize * old_len)?;
+
+            // update header
+            header.value_len = T::PACKED_LEN as u16;
+            header.value_version = T::VERSION;
+            header.data_offset = data_offset;
+            header.write(storage, offset)?;
+        }
+
+        Self::load(storage, offset)
+    }
+
     /// Returns the offset of `StorageVector` in the storage file.
     pub fn offset(&self) -> Offset {
         self.offset
@@ -74,17 +136,39 @@ impl<'a, T: Codec> StorageVec<'a, T> {
         self.len() == 0
     }
 
+    /// Reserves capacity for at least `additional` more elements to be inserted.
+    /// The `StorageVector` may reserve more space to avoid frequent reallocations.
+    pub fn reserve(&mut self, additional: usize) -> Result<(), Error> {
+        let required = self
+            .len()
+            .checked_add(additional)
+            .ok_or(Error::OutOfCapacity)?;
+        if required <= self.capacity() {
+            return Ok(());
+        }
+
+        let new_capacity = required.max(self.capacity() * 2);
+        self.reallocate(new_capacity)
+    }
+
+    /// Shrinks the capacity of the `StorageVector` as much as possible.
+    /// The unused space is freed.
+    pub fn shrink_to_fit(&mut self) -> Result<(), Error> {
+        if self.capacity() > self.len() {
+            self.reallocate(self.len())?;
+        }
+        Ok(())
+    }
+
     /// Appends an element to the back of a `StorageVector`.
     pub fn push(&mut self, value: T) -> Result<(), Error> {
-        if self.header.count >= self.header.capacity {
-            return Err(Error::OutOfCapacity);
-        }
+        self.reserve(1)?;
 
         let offset = self.item_offset(self.header.count)?;
         self.storage.write(offset, &value)?;
 
         self.header.count += 1;
-        self.storage.write(self.offset, &self.header)
+        self.header.write(self.storage, self.offset)
     }
 
     /// Returns an element at the given index or None if out of bounds..
@@ -98,46 +182,380 @@ impl<'a, T: Codec> StorageVec<'a, T> {
         Ok(Some(item))
     }
 
-    ///
+    /// Returns an iterator over the elements of the `StorageVector`.
+    /// The iterator reads the elements in chunks to reduce the number of storage calls.
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter::new(self.storage, &self.header, 0, self.header.count)
+    }
+
+    /// Returns an iterator over the elements in the given range of indices.
+    pub fn iter_range<R: RangeBounds<u32>>(&self, range: R) -> Result<Iter<'a, T>, Error> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start
+                .checked_add(1)
+                .ok_or(Error::IndexOutOfBounds(*start))?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end.checked_add(1).ok_or(Error::IndexOutOfBounds(*end))?,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.header.count,
+        };
+        if end > self.header.count {
+            return Err(Error::IndexOutOfBounds(end));
+        }
+        if start > end {
+            return Err(Error::IndexOutOfBounds(start));
+        }
+
+        Ok(Iter::new(self.storage, &self.header, start, end))
+    }
+
+    /// Replaces the element at the given index.
+    pub fn set(&mut self, index: u32, value: T) -> Result<(), Error> {
+        if index >= self.header.count {
+            return Err(Error::IndexOutOfBounds(index));
+        }
+
+        let offset = self.item_offset(index)?;
+        self.storage.write(offset, &value)
+    }
+
+    /// Removes the last element from the `StorageVector` and returns it, or None if it is empty.
+    pub fn pop(&mut self) -> Result<Option<T>, Error> {
+        if self.header.count == 0 {
+            return Ok(None);
+        }
+
+        let item = self
+            .storage
+            .read(self.item_offset(self.header.count - 1)?)?;
+        self.set_len(self.header.count - 1)?;
+        Ok(Some(item))
+    }
+
+    /// Inserts an element at position `index` within the `StorageVector`,
+    /// shifting all elements after it to the right.
+    pub fn insert(&mut self, index: u32, value: T) -> Result<(), Error> {
+        if index > self.header.count {
+            return Err(Error::IndexOutOfBounds(index));
+        }
+        self.reserve(1)?;
+
+        let tail = self.read_range(index, self.header.count)?;
+        let mut bytes = value.to_bytes();
+        bytes.extend_from_slice(&tail);
+        self.storage.write_bytes(self.item_offset(index)?, &bytes)?;
+
+        self.set_len(self.header.count + 1)
+    }
+
+    /// Removes and returns the element at position `index` within the `StorageVector`,
+    /// shifting all elements after it to the left.
+    pub fn remove(&mut self, index: u32) -> Result<T, Error> {
+        if index >= self.header.count {
+            return Err(Error::IndexOutOfBounds(index));
+        }
+
+        let bytes = self.read_range(index, self.header.count)?;
+        let (item, tail) = bytes.split_at(self.header.value_len as usize);
+        self.storage.write_bytes(self.item_offset(index)?, tail)?;
+
+        self.set_len(self.header.count - 1)?;
+        Ok(T::try_from_bytes(item)?)
+    }
+
+    /// Removes an element from the `StorageVector` and returns it.
+    /// The removed element is replaced by the last element of the `StorageVector`.
+    pub fn swap_remove(&mut self, index: u32) -> Result<T, Error> {
+        if index >= self.header.count {
+            return Err(Error::IndexOutOfBounds(index));
+        }
+
+        let last_index = self.header.count - 1;
+        let item = self.storage.read(self.item_offset(index)?)?;
+        if index != last_index {
+            let last = self.read_range(last_index, self.header.count)?;
+            self.storage.write_bytes(self.item_offset(index)?, &last)?;
+        }
+
+        self.set_len(last_index)?;
+        Ok(item)
+    }
+
+    /// Shortens the `StorageVector`, keeping the first `len` elements and dropping the rest.
+    /// If `len` is greater than the vector's current length, this has no effect.
+    /// Note that this method has no effect on the allocated capacity of the vector.
+    pub fn truncate(&mut self, len: u32) -> Result<(), Error> {
+        if len < self.header.count {
+            self.set_len(len)?;
+        }
+        Ok(())
+    }
+
+    /// Clears the `StorageVector`, removing all values.
+    /// Note that this method has no effect on the allocated capacity of the vector.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.truncate(0)
+    }
+
+    /// Appends all the elements in the slice to the `StorageVector`.
+    pub fn extend_from_slice(&mut self, slice: &[T]) -> Result<(), Error> {
+        self.reserve(slice.len())?;
+
+        let bytes = encode_slice(slice);
+        self.storage
+            .write_bytes(self.item_offset(self.header.count)?, &bytes)?;
+
+        self.set_len(self.header.count + slice.len() as u32)
+    }
+
+    /// Retains only the elements specified by the predicate.
+    /// All the elements are read by one storage call and the retained elements are written back
+    /// by another storage call.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> Result<(), Error> {
+        let value_len = self.header.value_len as usize;
+        let bytes = self.get_bytes()?;
+        let mut retained = Vec::with_capacity(bytes.len());
+        let mut count = 0;
+        for item_bytes in bytes.chunks(value_len.max(1)).take(self.len()) {
+            if f(&T::try_from_bytes(item_bytes)?) {
+                retained.extend_from_slice(item_bytes);
+                count += 1;
+            }
+        }
+        if count == self.header.count {
+            return Ok(());
+        }
+
+        self.storage
+            .write_bytes(self.header.data_offset, &retained)?;
+        self.set_len(count)
+    }
+
+    /// Replaces the content of the `StorageVector` with the given slice.
     pub fn set_slice(&mut self, slice: &[T]) -> Result<(), Error> {
         if slice.len() > self.capacity() {
-            return Err(Error::OutOfCapacity);
-        }
-        let mut offset = self.header.data_offset;
-        for v in slice {
-            self.storage.write(offset, v)?;
-            offset += T::PACKED_LEN as u32;
+            self.reserve(slice.len() - self.len())?;
         }
 
-        // update header
-        self.header.count = slice.len() as u32;
-        self.storage.write(self.offset, &self.header)
+        let bytes = encode_slice(slice);
+        self.storage.write_bytes(self.header.data_offset, &bytes)?;
+
+        self.set_len(slice.len() as u32)
     }
 
-    ///
+    /// Replaces the content of the `StorageVector` with the given raw bytes.
     pub fn set_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
-        if bytes.len() > (self.capacity() * self.header.value_len as usize) {
-            return Err(Error::OutOfCapacity);
+        let count = bytes.len() / self.header.value_len as usize;
+        if count > self.capacity() {
+            self.reserve(count - self.len())?;
         }
 
         self.storage.write_bytes(self.header.data_offset, bytes)?;
 
         // update header
         self.header.count = bytes.len() as u32 / self.header.value_len as u32;
-        self.storage.write(self.offset, &self.header)
+        self.header.write(self.storage, self.offset)
     }
 
-    ///
+    /// Returns the raw bytes of all elements in the `StorageVector`.
     pub fn get_bytes(&self) -> Result<Vec<u8>, Error> {
         let length = self.header.count * self.header.value_len as u32;
         self.storage.read_bytes(self.header.data_offset, length)
     }
 
+    // Moves the elements into a new space with the given capacity and frees the old space.
+    fn reallocate(&mut self, new_capacity: usize) -> Result<(), Error> {
+        let value_len = self.header.value_len as usize;
+        let new_capacity = u32::try_from(new_capacity).map_err(|_| Error::OutOfCapacity)?;
+        let new_len = (new_capacity as usize)
+            .checked_mul(value_len)
+            .ok_or(Error::OutOfCapacity)?;
+        let data_offset = self.storage.allocate(new_len)?;
+
+        let bytes = self.get_bytes()?;
+        self.storage.write_bytes(data_offset, &bytes)?;
+        self.storage.free(
+            self.header.data_offset,
+            self.header.capacity as usize * value_len,
+        )?;
+
+        // update header
+        self.header.capacity = new_capacity;
+        self.header.data_offset = data_offset;
+        self.header.write(self.storage, self.offset)
+    }
+
+    // Reads the raw bytes of the elements in the range `start..end`.
+    fn read_range(&self, start: u32, end: u32) -> Result<Vec<u8>, Error> {
+        let length = (end - start) * self.header.value_len as u32;
+        self.storage.read_bytes(self.item_offset(start)?, length)
+    }
+
+    fn set_len(&mut self, len: u32) -> Result<(), Error> {
+        // update header
+        self.header.count = len;
+        self.header.write(self.storage, self.offset)
+    }
+
     fn item_offset(&self, index: u32) -> Result<Offset, Error> {
         Ok(self.header.data_offset + (index * self.header.value_len as u32))
     }
 }
 
+/// An iterator over the elements of a `StorageVec`.
+///
+/// The elements are prefetched in chunks from both ends, so iterating a vector of `n` elements
+/// takes about `n / chunk_len` storage calls.
+pub struct Iter<'a, T: Codec> {
+    storage: &'a Storage,
+    data_offset: Offset,
+    value_len: u32,
+    chunk_len: u32,
+    // The indices of the elements that are not read yet are in the range `front..back`.
+    front: u32,
+    back: u32,
+    front_buffer: VecDeque<T>,
+    back_buffer: VecDeque<T>,
+}
+
+impl<'a, T: Codec> Iter<'a, T> {
+    fn new(storage: &'a Storage, header: &Header, front: u32, back: u32) -> Self {
+        Iter {
+            storage,
+            data_offset: header.data_offset,
+            value_len: header.value_len as u32,
+            chunk_len: ITER_CHUNK_LEN,
+            front,
+            back,
+            front_buffer: VecDeque::new(),
+            back_buffer: VecDeque::new(),
+        }
+    }
+
+    /// Sets the number of elements that are read from the storage at once.
+    pub fn prefetch(mut self, chunk_len: u32) -> Self {
+        self.chunk_len = chunk_len.max(1);
+        self
+    }
+
+    // Reads the elements in the range `start..end` into a buffer.
+    fn read_chunk(&mut self, start: u32, end: u32) -> Result<VecDeque<T>, Error> {
+        let offset = self.data_offset + start * self.value_len;
+        let items = self
+            .storage
+            .read_bytes(offset, (end - start) * self.value_len)
+            .and_then(|bytes| {
+                (0..(end - start) as usize)
+                    .map(|i| {
+                        let pos = i * self.value_len as usize;
+                        Ok(T::try_from_bytes(
+                            &bytes[pos..pos + self.value_len as usize],
+                        )?)
+                    })
+                    .collect()
+            });
+        if items.is_err() {
+            // Stop iterating after the first error
+            self.front = self.back;
+            self.front_buffer.clear();
+            self.back_buffer.clear();
+        }
+        items
+    }
+}
+
+impl<'a, T: Codec> Iterator for Iter<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front_buffer.is_empty() && self.front < self.back {
+            let end = self.back.min(self.front.saturating_add(self.chunk_len));
+            match self.read_chunk(self.front, end) {
+                Ok(buffer) => {
+                    self.front_buffer = buffer;
+                    self.front = end;
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        self.front_buffer
+            .pop_front()
+            .or_else(|| self.back_buffer.pop_front())
+            .map(Ok)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len =
+            self.front_buffer.len() + self.back_buffer.len() + (self.back - self.front) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T: Codec> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back_buffer.is_empty() && self.front < self.back {
+            let start = self.front.max(self.back.saturating_sub(self.chunk_len));
+            match self.read_chunk(start, self.back) {
+                Ok(buffer) => {
+                    self.back_buffer = buffer;
+                    self.back = start;
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        self.back_buffer
+            .pop_back()
+            .or_else(|| self.front_buffer.pop_back())
+            .map(Ok)
+    }
+}
+
+impl<'a, T: Codec> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T: Codec> IntoIterator for &StorageVec<'a, T> {
+    type Item = Result<T, Error>;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+fn encode_slice<T: Codec>(slice: &[T]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(slice.len() * T::PACKED_LEN);
+    for v in slice {
+        bytes.extend_from_slice(&v.to_bytes());
+    }
+    bytes
+}
+
+impl<'a, T: Codec> Verify for StorageVec<'a, T> {
+    fn verify(&self, verifier: &mut Verifier) {
+        if !verifier.block(
+            self.offset,
+            Header::packed_len_at(self.storage, self.offset),
+        ) {
+            return;
+        }
+        verifier.block(
+            self.header.data_offset,
+            self.header.capacity as usize * T::PACKED_LEN,
+        );
+        if self.header.count > self.header.capacity {
+            verifier.problem(Problem::CountMismatch {
+                offset: self.offset,
+                expected: self.header.count,
+                found: self.header.capacity,
+            });
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -149,11 +567,252 @@ mod tests {
         let mut vec_1 = StorageVec::<i32>::create(&storage, 2).unwrap();
         vec_1.push(1).unwrap();
         vec_1.push(2).unwrap();
-        assert!(vec_1.push(3).is_err());
+        assert_eq!(vec_1.capacity(), 2);
+        vec_1.push(3).unwrap();
+        assert_eq!(vec_1.capacity(), 4);
 
         let vec_2 = StorageVec::<i32>::load(&storage, vec_1.offset()).unwrap();
         assert_eq!(Some(1), vec_2.get(0).unwrap());
         assert_eq!(Some(2), vec_2.get(1).unwrap());
+        assert_eq!(Some(3), vec_2.get(2).unwrap());
         assert_eq!(None, vec_2.get(3).unwrap());
     }
+
+    fn to_vec(vec: &StorageVec<u16>) -> Vec<u16> {
+        vec.iter().map(|v| v.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_vector_mutation() {
+        let storage = mock_storage(1024 * 1024);
+        let mut vec = StorageVec::<u16>::create(&storage, 4).unwrap();
+
+        vec.extend_from_slice(&[1, 2, 3]).unwrap();
+        vec.set(1, 20).unwrap();
+        assert!(vec.set(3, 4).is_err());
+        assert_eq!(to_vec(&vec), [1, 20, 3]);
+
+        vec.insert(0, 0).unwrap();
+        vec.insert(4, 4).unwrap();
+        vec.insert(2, 10).unwrap();
+        assert!(vec.insert(7, 7).is_err());
+        assert_eq!(to_vec(&vec), [0, 1, 10, 20, 3, 4]);
+
+        assert_eq!(10, vec.remove(2).unwrap());
+        assert_eq!(4, vec.remove(4).unwrap());
+        assert!(vec.remove(4).is_err());
+        assert_eq!(to_vec(&vec), [0, 1, 20, 3]);
+
+        assert_eq!(0, vec.swap_remove(0).unwrap());
+        assert_eq!(to_vec(&vec), [3, 1, 20]);
+        assert_eq!(20, vec.swap_remove(2).unwrap());
+        assert_eq!(to_vec(&vec), [3, 1]);
+
+        assert_eq!(Some(1), vec.pop().unwrap());
+        assert_eq!(Some(3), vec.pop().unwrap());
+        assert_eq!(None, vec.pop().unwrap());
+
+        vec.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+        vec.retain(|v| v % 3 != 0).unwrap();
+        assert_eq!(to_vec(&vec), [1, 2, 4, 5, 7, 8]);
+
+        vec.truncate(10).unwrap();
+        assert_eq!(vec.len(), 6);
+        vec.truncate(2).unwrap();
+        assert_eq!(to_vec(&vec), [1, 2]);
+
+        vec.clear().unwrap();
+        assert!(vec.is_empty());
+
+        let vec_2 = StorageVec::<u16>::load(&storage, vec.offset()).unwrap();
+        assert!(vec_2.is_empty());
+        assert_eq!(vec_2.capacity(), 16);
+    }
+
+    #[test]
+    fn test_vector_iter() {
+        let storage = mock_storage(1024 * 1024);
+        let mut vec = StorageVec::<u32>::create(&storage, 0).unwrap();
+        assert!(vec.iter().next().is_none());
+
+        let items: Vec<u32> = (0..100).collect();
+        vec.extend_from_slice(&items).unwrap();
+
+        let iter = vec.iter();
+        assert_eq!(iter.len(), 100);
+        assert_eq!(iter.map(|v| v.unwrap()).collect::<Vec<_>>(), items);
+        assert_eq!(
+            vec.iter().rev().map(|v| v.unwrap()).collect::<Vec<_>>(),
+            items.iter().rev().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec.iter_range(10..20)
+                .unwrap()
+                .map(|v| v.unwrap())
+                .collect::<Vec<_>>(),
+            items[10..20]
+        );
+        assert_eq!(vec.iter_range(..=99).unwrap().len(), 100);
+        assert!(vec.iter_range(50..50).unwrap().next().is_none());
+        assert!(vec.iter_range(..101).is_err());
+        assert!(vec
+            .iter_range((Bound::Excluded(50), Bound::Excluded(50)))
+            .is_err());
+
+        // Alternating both ends, meeting in the middle of a chunk
+        let mut iter = vec.iter().prefetch(7);
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        while let Some(v) = iter.next() {
+            front.push(v.unwrap());
+            assert_eq!(iter.len(), 100 - front.len() - back.len());
+            match iter.next_back() {
+                Some(v) => back.push(v.unwrap()),
+                None => break,
+            }
+        }
+        back.reverse();
+        front.append(&mut back);
+        assert_eq!(front, items);
+
+        let mut sum = 0;
+        for v in &vec {
+            sum += v.unwrap();
+        }
+        assert_eq!(sum, 4950);
+    }
+
+    #[test]
+    fn test_vector_migrate() {
+        use crate::Codec;
+
+        #[derive(Codec)]
+        struct ProfileV1 {
+            age: u8,
+        }
+
+        #[derive(Codec, Debug, PartialEq, Eq)]
+        #[codec(version = 2, migrate_from(v1 = "ProfileV1"))]
+        struct Profile {
+            age: u8,
+            score: u32,
+        }
+
+        impl Profile {
+            fn migrate_from_v1(old: ProfileV1) -> Self {
+                Profile {
+                    age: old.age,
+                    score: 100,
+                }
+            }
+        }
+
+        let storage = mock_storage(1024 * 1024);
+        let mut vec_1 = StorageVec::<ProfileV1>::create(&storage, 4).unwrap();
+        for age in 0..3 {
+            vec_1.push(ProfileV1 { age }).unwrap();
+        }
+
+        assert!(matches!(
+            StorageVec::<Profile>::load(&storage, vec_1.offset()),
+            Err(Error::VersionMismatch(1))
+        ));
+
+        let mut vec_2 = StorageVec::<Profile>::migrate(&storage, vec_1.offset()).unwrap();
+        assert_eq!(vec_2.len(), 3);
+        assert_eq!(vec_2.capacity(), 4);
+        assert_eq!(vec_2.get(2).unwrap(), Some(Profile { age: 2, score: 100 }));
+        vec_2.push(Profile { age: 3, score: 0 }).unwrap();
+
+        // Migrating again has no effect
+        let vec_3 = StorageVec::<Profile>::migrate(&storage, vec_1.offset()).unwrap();
+        assert_eq!(vec_3.len(), 4);
+        assert!(StorageVec::<ProfileV1>::load(&storage, vec_1.offset()).is_err());
+    }
+
+    #[test]
+    fn test_vector_growth() {
+        let storage = mock_storage(1024 * 1024);
+        let mut vec = StorageVec::<u64>::create(&storage, 0).unwrap();
+        assert!(vec.is_empty());
+
+        for i in 0..100 {
+            vec.push(i).unwrap();
+        }
+        assert_eq!(vec.len(), 100);
+        assert_eq!(vec.capacity(), 128);
+        for i in 0..100 {
+            assert_eq!(Some(i as u64), vec.get(i).unwrap());
+        }
+
+        vec.reserve(10).unwrap();
+        assert_eq!(vec.capacity(), 128);
+        vec.reserve(100).unwrap();
+        assert_eq!(vec.capacity(), 256);
+
+        let data_offset = vec.header.data_offset;
+        vec.shrink_to_fit().unwrap();
+        assert_eq!(vec.capacity(), 100);
+        assert_eq!(Some(99), vec.get(99).unwrap());
+
+        // The freed space is reused
+        let vec_2 = StorageVec::<u64>::create(&storage, 256).unwrap();
+        assert_eq!(vec_2.header.data_offset, data_offset);
+
+        vec.set_slice(&[7; 300]).unwrap();
+        assert_eq!(vec.len(), 300);
+        assert_eq!(Some(7), vec.get(299).unwrap());
+    }
+
+    #[test]
+    fn test_vector_v1() {
+        use crate::storage::mock::MockV1Storage;
+        use crate::storage::str::StorageString;
+
+        // The vectors of a version 1 file: `[1, 2, 3]` with the capacity of 4,
+        // and a string with the capacity of 8.
+        let v1 = MockV1Storage::new(2048);
+        let header = |count: u32, capacity: u32, value_len: u16, data_offset: Offset| {
+            let mut bytes = count.to_bytes();
+            bytes.extend(capacity.to_bytes());
+            bytes.extend(value_len.to_bytes());
+            bytes.extend(data_offset.to_bytes());
+            bytes
+        };
+        let vec_offset = v1.allocate(&header(3, 4, 4, 1046));
+        assert_eq!(
+            v1.allocate(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 0]),
+            1046
+        );
+        let str_offset = v1.allocate(&header(4, 8, 1, 1076));
+        assert_eq!(v1.allocate(b"kelk\0\0\0\0"), 1076);
+        v1.fill_stack_at(0, vec_offset);
+        v1.fill_stack_at(1, str_offset);
+        let storage = Storage::load(v1.into_api()).unwrap();
+
+        let mut vec = StorageVec::<u32>::load(&storage, vec_offset).unwrap();
+        assert_eq!(vec.len(), 3);
+        assert_eq!(vec.get(2).unwrap(), Some(3));
+        for i in 4..10 {
+            vec.push(i).unwrap();
+        }
+        let mut name = StorageString::load(&storage, str_offset).unwrap();
+        assert_eq!(name.get_string().unwrap(), "kelk");
+        name.set_string("kelk storage").unwrap();
+
+        // The headers are kept in the former layout
+        let vec = StorageVec::<u32>::load(&storage, vec_offset).unwrap();
+        assert_eq!(
+            vec.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+            (1..10).collect::<Vec<_>>()
+        );
+        let name = StorageString::load(&storage, str_offset).unwrap();
+        assert_eq!(name.get_string().unwrap(), "kelk storage");
+        assert_eq!(storage.read_u32(1062).unwrap(), 12);
+
+        let mut verifier = storage.verifier().unwrap();
+        verifier.stack::<StorageVec<u32>>(0).unwrap();
+        verifier.stack::<StorageString>(1).unwrap();
+        assert!(verifier.finish().is_empty());
+    }
 }
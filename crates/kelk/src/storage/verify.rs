@@ -0,0 +1,392 @@
+//! Storage verification
+//!
+//! The verifier walks the structures of the storage file and reports the problems it finds,
+//! like dangling offsets, overlapping allocations or cycles.
+//!
+//! The structures are typed, but the storage file doesn't keep the type of the roots.
+//! Therefore `Storage::verify` only checks the allocator, the root stack and the root registry,
+//! and the typed roots should be checked by the [`Verifier`]:
+//!
+//! ```ignore
+//! let mut verifier = storage.verifier()?;
+//! verifier.root::<StorageBST<Address, i64>>("balances");
+//! let problems = verifier.finish();
+//! ```
+//!
+//! The state that is declared by `#[kelk::state]` has a `verify` function, that checks all
+//! the fields of the state.
+//!
+
+use crate::storage::codec::Codec;
+use crate::storage::error::Error;
+use crate::storage::header::META_LEN;
+use crate::storage::root::Root;
+use crate::storage::{block_len, size_class, Offset, Storage, SIZE_CLASSES};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// A problem that is found by the verifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The offset points outside of the allocated space
+    DanglingOffset(Offset),
+
+    /// The block at the first offset overlaps the block at the second offset
+    Overlap(Offset, Offset),
+
+    /// The block is reached more than once, like a cycle in a linked list
+    Cycle(Offset),
+
+    /// The header at the offset is written for another type
+    LayoutMismatch(Offset),
+
+    /// The data at the offset can't be read or decoded
+    Corrupted(Offset),
+
+    /// The number of the elements disagrees with the count in the header at the offset
+    CountMismatch {
+        /// The offset of the header
+        offset: Offset,
+        /// The count in the header
+        expected: u32,
+        /// The number of the reachable elements
+        found: u32,
+    },
+
+    /// The root with this name is registered with another type
+    TypeMismatch(String),
+
+    /// The checksum of the block at the offset disagrees with its data
+    ChecksumMismatch(Offset),
+}
+
+/// `Verify` trait is implemented by the structures that can be checked by the verifier.
+pub trait Verify {
+    /// Registers the allocated blocks of the structure and reports its problems.
+    fn verify(&self, verifier: &mut Verifier);
+}
+
+/// `Verifier` keeps the allocated blocks that are reached so far,
+/// and the problems that are found.
+pub struct Verifier<'a> {
+    storage: &'a Storage,
+    data_offset: Offset,
+    free_pos: Offset,
+    blocks: BTreeMap<Offset, Offset>,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Verifier<'a> {
+    /// Creates a new verifier and checks the free lists, the root stack, the root registry
+    /// and the block checksums.
+    pub(crate) fn new(storage: &'a Storage) -> Result<Self, Error> {
+        let data_offset = storage.data_offset();
+        let free_pos = storage.read_u32(storage.free_pos_offset())?;
+        let mut verifier = Verifier {
+            storage,
+            data_offset,
+            free_pos,
+            blocks: BTreeMap::new(),
+            problems: Vec::new(),
+        };
+        if free_pos < data_offset || free_pos > storage.size()? {
+            verifier.problem(Problem::DanglingOffset(free_pos));
+            return Ok(verifier);
+        }
+
+        // The metadata of an upgraded storage file is written after its allocated blocks
+        let meta_offset = storage.free_pos_offset();
+        if meta_offset >= data_offset
+            && !verifier.span(meta_offset, (meta_offset + META_LEN) as u64)
+        {
+            return Ok(verifier);
+        }
+
+        for class in 0..SIZE_CLASSES {
+            let mut offset = storage.read_u32(storage.free_list_offset(class))?;
+            while offset != 0 && verifier.block(offset, block_len(class) as usize) {
+                offset = storage.read_u32(offset)?;
+            }
+        }
+
+        // The stack of an upgraded storage file is moved into an allocated block
+        let stack_offset = storage.header.stack_offset;
+        let stack_len = storage.header.stack_size as usize * Offset::PACKED_LEN;
+        if stack_offset >= data_offset && !verifier.block(stack_offset, stack_len) {
+            return Ok(verifier);
+        }
+        for index in 0..storage.header.stack_size {
+            let offset = storage.read_stack_at(index)?;
+            if offset != 0 && !verifier.is_allocated(offset) {
+                verifier.problem(Problem::DanglingOffset(offset));
+            }
+        }
+
+        if let Some(registry) = storage.root_registry_if_exists()? {
+            verifier.check(&registry);
+            for entry in registry.iter() {
+                match entry {
+                    Ok((_, entry)) if !verifier.is_allocated(entry.offset) => {
+                        verifier.problem(Problem::DanglingOffset(entry.offset))
+                    }
+                    Ok(_) => {}
+                    Err(_) => verifier.problem(Problem::Corrupted(registry.offset())),
+                }
+            }
+        }
+
+        storage.verify_checksums(&mut verifier)?;
+
+        Ok(verifier)
+    }
+
+    /// Returns the storage that is verified.
+    pub fn storage(&self) -> &'a Storage {
+        self.storage
+    }
+
+    /// Registers a block that is allocated with the given length.
+    /// The legacy blocks of an upgraded storage file are registered by their exact length.
+    ///
+    /// It returns false if the block is dangling, overlaps another block or is
+    /// registered before. In this case the problem is reported and the block
+    /// should not be followed.
+    pub fn block(&mut self, offset: Offset, length: usize) -> bool {
+        if self.storage.is_legacy(offset) {
+            return self.span(offset, offset as u64 + length as u64);
+        }
+        let end = match size_class(length) {
+            Ok(class) => offset as u64 + block_len(class) as u64,
+            Err(_) => u64::MAX,
+        };
+        self.span(offset, end)
+    }
+
+    // Registers the bytes from `offset` until `end`.
+    fn span(&mut self, offset: Offset, end: u64) -> bool {
+        if offset < self.data_offset || end > self.free_pos as u64 {
+            self.problem(Problem::DanglingOffset(offset));
+            return false;
+        }
+        let end = end as Offset;
+        if self.blocks.contains_key(&offset) {
+            self.problem(Problem::Cycle(offset));
+            return false;
+        }
+        if let Some((prev, prev_end)) = self.blocks.range(..offset).next_back() {
+            if *prev_end > offset {
+                self.problem(Problem::Overlap(*prev, offset));
+                return false;
+            }
+        }
+        if let Some((next, _)) = self.blocks.range(offset..).next() {
+            if *next < end {
+                self.problem(Problem::Overlap(offset, *next));
+                return false;
+            }
+        }
+        self.blocks.insert(offset, end);
+        true
+    }
+
+    /// Reads `T` at the given offset. If it fails, the problem is reported.
+    pub fn read<T: Codec>(&mut self, offset: Offset) -> Option<T> {
+        match self.storage.read(offset) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.problem(Problem::Corrupted(offset));
+                None
+            }
+        }
+    }
+
+    /// Reports a problem.
+    pub fn problem(&mut self, problem: Problem) {
+        self.problems.push(problem);
+    }
+
+    /// Checks the given structure.
+    pub fn check<T: Verify>(&mut self, value: &T) {
+        value.verify(self);
+    }
+
+    /// Checks the root with the given name, if it exists.
+    pub fn root<T: Root<'a> + Verify>(&mut self, name: &str) -> Result<(), Error> {
+        if let Some((type_id, offset)) = self.storage.root_entry(name)? {
+            if type_id != T::type_id() {
+                self.problem(Problem::TypeMismatch(name.to_string()));
+            } else {
+                self.load::<T>(offset);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the structure that its offset is kept in the stack at the given `stack_index`.
+    pub fn stack<T: Root<'a> + Verify>(&mut self, stack_index: u16) -> Result<(), Error> {
+        let offset = self.storage.read_stack_at(stack_index)?;
+        if offset != 0 {
+            self.load::<T>(offset);
+        }
+        Ok(())
+    }
+
+    /// Returns the problems that are found.
+    pub fn finish(self) -> Vec<Problem> {
+        self.problems
+    }
+
+    fn load<T: Root<'a> + Verify>(&mut self, offset: Offset) {
+        match T::load_root(self.storage, offset) {
+            Ok(root) => self.check(&root),
+            Err(Error::TypeMismatch) | Err(Error::VersionMismatch(_)) => {
+                self.problem(Problem::LayoutMismatch(offset))
+            }
+            Err(_) => self.problem(Problem::Corrupted(offset)),
+        }
+    }
+
+    fn is_allocated(&self, offset: Offset) -> bool {
+        offset >= self.data_offset && offset < self.free_pos
+    }
+}
+
+impl Storage {
+    /// Creates a verifier, that checks the free lists, the root stack and the root registry.
+    /// The typed roots can be checked by the verifier.
+    pub fn verifier(&self) -> Result<Verifier<'_>, Error> {
+        Verifier::new(self)
+    }
+
+    /// Checks the free lists, the root stack and the root registry,
+    /// and returns the problems that are found.
+    pub fn verify(&self) -> Result<Vec<Problem>, Error> {
+        Ok(self.verifier()?.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::bst::StorageBST;
+    use crate::storage::btree::StorageBTreeMap;
+    use crate::storage::dyn_vec::StorageDynVec;
+    use crate::storage::hash_map::StorageHashMap;
+    use crate::storage::linked_list::{self, StorageLinkedList};
+    use crate::storage::mock::mock_storage;
+    use crate::storage::multi_map::StorageMultiMap;
+    use crate::storage::set::StorageSet;
+    use crate::storage::str::StorageString;
+    use crate::storage::vec::{self, StorageVec};
+    use alloc::string::String;
+
+    #[kelk::state]
+    struct State<'a> {
+        vec: StorageVec<'a, u32>,
+        string: StorageString<'a>,
+        dyn_vec: StorageDynVec<'a, String>,
+        list: StorageLinkedList<'a, u32>,
+        bst: StorageBST<'a, u32, u64>,
+        btree: StorageBTreeMap<'a, u32, u64>,
+        map: StorageHashMap<'a, u32, u64>,
+        set: StorageSet<'a, u32>,
+        multi_map: StorageMultiMap<'a, u32, u32>,
+        total: u64,
+    }
+
+    #[test]
+    fn test_verify() {
+        let storage = mock_storage(1024 * 1024);
+        let mut state = State::init(&storage).unwrap();
+        for i in 0..100 {
+            state.vec_mut().unwrap().push(i).unwrap();
+            state.dyn_vec_mut().unwrap().push(i.to_string()).unwrap();
+            state.list_mut().unwrap().push_back(i).unwrap();
+            state.bst_mut().unwrap().insert(i, i as u64).unwrap();
+            state.btree_mut().unwrap().insert(i, i as u64).unwrap();
+            state.map_mut().unwrap().insert(i, i as u64).unwrap();
+            state.set_mut().unwrap().insert(i).unwrap();
+            state.multi_map_mut().unwrap().insert(i % 10, i).unwrap();
+        }
+        for i in 0..50 {
+            state.dyn_vec_mut().unwrap().pop().unwrap();
+            state.list_mut().unwrap().pop_front().unwrap();
+            state.bst_mut().unwrap().remove(&i).unwrap();
+            state.btree_mut().unwrap().remove(&i).unwrap();
+            state.map_mut().unwrap().remove(&i).unwrap();
+            state.set_mut().unwrap().remove(&i).unwrap();
+            state
+                .multi_map_mut()
+                .unwrap()
+                .remove(&(i % 10), &i)
+                .unwrap();
+        }
+        state.string_mut().unwrap().set_string("kelk").unwrap();
+        state.set_total(&100).unwrap();
+
+        assert_eq!(storage.verify().unwrap(), []);
+        assert_eq!(State::verify(&storage).unwrap(), []);
+    }
+
+    #[test]
+    fn test_verify_problems() {
+        let storage = mock_storage(1024 * 1024);
+        let vec = storage.root::<StorageVec<u32>>("vec").unwrap();
+        let bst = storage.root::<StorageBST<u32, u32>>("bst").unwrap();
+        let mut list = storage.root::<StorageLinkedList<u32>>("list").unwrap();
+        list.push_back(1).unwrap();
+        list.push_back(2).unwrap();
+
+        // Linked list with a cycle
+        let head = storage
+            .read_u32(list.offset() + linked_list::HEAD_OFFSET)
+            .unwrap();
+        let second = storage.read_u32(head + linked_list::NEXT_OFFSET).unwrap();
+        storage
+            .write_u32(second + linked_list::NEXT_OFFSET, &head)
+            .unwrap();
+
+        // Vector that its data overlaps the tree header
+        let inside_bst = bst.offset() + 4;
+        storage
+            .write_u32(vec.offset() + vec::DATA_OFFSET, &inside_bst)
+            .unwrap();
+
+        let mut verifier = storage.verifier().unwrap();
+        verifier.root::<StorageBST<u32, u32>>("bst").unwrap();
+        verifier.root::<StorageVec<u32>>("vec").unwrap();
+        verifier.root::<StorageLinkedList<u32>>("list").unwrap();
+        verifier.root::<StorageVec<u64>>("bst").unwrap();
+        assert_eq!(
+            verifier.finish(),
+            [
+                Problem::Overlap(bst.offset(), inside_bst),
+                Problem::Cycle(head),
+                Problem::TypeMismatch("bst".to_string()),
+            ]
+        );
+
+        // Layout mismatch and dangling offsets
+        storage.fill_stack_at(0, vec.offset()).unwrap();
+        storage.fill_stack_at(1, 0xFFFF_0000).unwrap();
+        let mut verifier = storage.verifier().unwrap();
+        verifier.stack::<StorageVec<u64>>(0).unwrap();
+        assert_eq!(
+            verifier.finish(),
+            [
+                Problem::DanglingOffset(0xFFFF_0000),
+                Problem::LayoutMismatch(vec.offset()),
+            ]
+        );
+        storage.fill_stack_at(1, 0).unwrap();
+
+        // Freeing a block twice makes a cycle in the free list
+        let offset = storage.allocate(8).unwrap();
+        let other = storage.allocate(8).unwrap();
+        storage.free(offset, 8).unwrap();
+        storage.free(other, 8).unwrap();
+        storage.free(offset, 8).unwrap();
+        assert_eq!(storage.verify().unwrap(), [Problem::Cycle(offset)]);
+    }
+}